@@ -1,8 +1,10 @@
 // src/api/mod.rs
+use crate::core::config::NetworkConfig;
 use anyhow::{anyhow, Result};
 use biblatex::Bibliography;
 use reqwest::header::ACCEPT;
 use serde::Deserialize;
+use std::time::Duration;
 
 // Data structure for a single search result
 #[derive(Debug, Clone, Deserialize)]
@@ -13,9 +15,24 @@ pub struct SearchResultItem {
   pub doi: String,
 }
 
+// HTTP client honoring the [network] settings
+fn client(net: &NetworkConfig) -> Result<reqwest::Client> {
+  let mut agent = format!("mkbib/{}", env!("CARGO_PKG_VERSION"));
+  if !net.contact_email.trim().is_empty() {
+    agent.push_str(&format!(" (mailto:{})", net.contact_email.trim()));
+  }
+
+  Ok(
+    reqwest::Client::builder()
+      .timeout(Duration::from_secs(net.timeout_secs.max(1)))
+      .user_agent(agent)
+      .build()?,
+  )
+}
+
 // Fetch a single BibTeX entry by DOI
-pub async fn fetch_doi(doi: &str) -> Result<Bibliography> {
-  let client = reqwest::Client::new();
+pub async fn fetch_doi(doi: &str, net: &NetworkConfig) -> Result<Bibliography> {
+  let client = client(net)?;
   let url = format!("https://doi.org/{}", doi);
 
   let resp = client
@@ -30,8 +47,11 @@ pub async fn fetch_doi(doi: &str) -> Result<Bibliography> {
 }
 
 // Fetch a list of suggestions (Title, Author, Year)
-pub async fn search_crossref_suggestions(query: &str) -> Result<Vec<SearchResultItem>> {
-  let client = reqwest::Client::new();
+pub async fn search_crossref_suggestions(
+  query: &str,
+  net: &NetworkConfig,
+) -> Result<Vec<SearchResultItem>> {
+  let client = client(net)?;
   let search_url = "https://api.crossref.org/works";
 
   let params = [("query", query), ("rows", "10")];
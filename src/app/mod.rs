@@ -17,14 +17,23 @@ pub use model::{AppModel, AppMsg};
 
 use self::alert::AlertModel;
 use crate::core;
+use crate::logic::merger::EntryIds;
+use crate::logic::strings::Definitions;
 use crate::menu;
 // use crate::ui;
+use crate::ui::backup_dialog::{BackupDialogModel, BackupDialogOutput};
 use crate::ui::details_dialog::{DetailsDialogModel, DetailsDialogOutput};
 use crate::ui::duplicate_dialog::{DuplicateDialogModel, DuplicateDialogOutput}; // <--- FIX 1: ADD IMPORT
+use crate::ui::lock_dialog::{LockDialogModel, LockDialogOutput};
+use crate::ui::merge_dialog::{MergeDialogModel, MergeDialogOutput};
 use crate::ui::preferences::{PreferencesModel, PreferencesOutput};
+use crate::ui::recovery_dialog::{RecoveryDialogModel, RecoveryDialogMsg, RecoveryDialogOutput};
+use crate::ui::review_dialog::{ReviewDialogModel, ReviewDialogOutput};
 use crate::ui::row::BibEntryOutput;
 use crate::ui::search_dialog::{SearchDialogModel, SearchDialogOutput};
 use crate::ui::sidebar::{SidebarModel, SidebarOutput};
+use crate::ui::strings_dialog::{StringsDialogModel, StringsDialogOutput};
+use crate::ui::tex_rename_dialog::{TexRenameDialogModel, TexRenameDialogOutput};
 
 #[relm4::component(pub)]
 impl Component for AppModel {
@@ -37,8 +46,8 @@ impl Component for AppModel {
             gtk::ApplicationWindow {
                 set_title: Some("MkBib"),
                 set_icon_name: Some("mkbib"),
-                set_default_width: 1100,
-                set_default_height: 750,
+                set_default_width: model.config.ui.window_width,
+                set_default_height: model.config.ui.window_height,
 
                 gtk::Box {
                     set_orientation: gtk::Orientation::Vertical,
@@ -99,8 +108,12 @@ impl Component for AppModel {
         root: Self::Root,
         sender: ComponentSender<Self>,
     ) -> ComponentParts<Self> {
-        // FIX 2: Load config FIRST so 'key_config' variable exists
-        let key_config = core::config::load();
+        // FIX 2: Load config FIRST so 'config' variable exists.
+        // A broken file is reported once the alert dialog exists.
+        let (config, config_error) = match core::config::load() {
+            Ok(config) => (config, None),
+            Err(e) => (core::config::AppConfig::default(), Some(e)),
+        };
 
         menu::actions_file::init(&root, sender.clone());
         menu::actions_edit::init(&root, sender.clone());
@@ -119,12 +132,23 @@ impl Component for AppModel {
         file_menu.append(Some("Open"), Some("win.open"));
         file_menu.append(Some("Save"), Some("win.save"));
         file_menu.append(Some("Save As..."), Some("win.save_as"));
+        file_menu.append(Some("Restore from Backup..."), Some("win.restore_backup"));
+        file_menu.append(Some("Reformat Entire File"), Some("win.reformat_all"));
         file_menu.append(Some("Quit"), Some("win.quit"));
         menu_model.append_submenu(Some("File"), &file_menu);
 
         let edit_menu = gio::Menu::new();
         edit_menu.append(Some("Preferences"), Some("edit.preferences"));
+        edit_menu.append(Some("String Definitions..."), Some("edit.strings"));
         edit_menu.append(Some("Regenerate Keys"), Some("edit.regenerate_keys"));
+        edit_menu.append(
+            Some("Regenerate Mismatched Keys"),
+            Some("edit.regenerate_mismatched_keys"),
+        );
+        edit_menu.append(
+            Some("Update Citations in LaTeX Files..."),
+            Some("edit.update_tex_citations"),
+        );
         edit_menu.append(Some("Scan for Duplicates"), Some("edit.scan_duplicates"));
         edit_menu.append(
             Some("Abbreviate Journal Titles"),
@@ -134,6 +158,20 @@ impl Component for AppModel {
             Some("Un-abbreviate Journal Titles"),
             Some("edit.unabbreviate_journals"),
         );
+        edit_menu.append(
+            Some("Convert Text to Unicode"),
+            Some("edit.convert_unicode"),
+        );
+        edit_menu.append(Some("Convert Text to LaTeX"), Some("edit.convert_latex"));
+        edit_menu.append(
+            Some("Convert Entries to BibTeX"),
+            Some("edit.convert_bibtex"),
+        );
+        edit_menu.append(
+            Some("Convert Entries to BibLaTeX"),
+            Some("edit.convert_biblatex"),
+        );
+        edit_menu.append(Some("Sort Library"), Some("edit.sort_library"));
         menu_model.append_submenu(Some("Edit"), &edit_menu);
 
         let help_menu = gio::Menu::new();
@@ -184,11 +222,26 @@ impl Component for AppModel {
             .forward(sender.input_sender(), |resp| AppMsg::SaveResponse(resp));
         save_dialog.widget().set_transient_for(Some(&root));
 
+        let tex_folder_dialog = OpenDialog::builder()
+            .launch(OpenDialogSettings {
+                folder_mode: true,
+                accept_label: "Select Project Folder".into(),
+                is_modal: true,
+                ..Default::default()
+            })
+            .forward(sender.input_sender(), |resp| {
+                AppMsg::TexFolderResponse(resp)
+            });
+        tex_folder_dialog.widget().set_transient_for(Some(&root));
+
         let preferences = PreferencesModel::builder()
             .transient_for(&root)
-            .launch(key_config.clone()) // Now 'key_config' exists!
+            .launch(config.clone()) // Now 'config' exists!
             .forward(sender.input_sender(), |msg| match msg {
-                PreferencesOutput::ConfigUpdated(cfg) => AppMsg::UpdateKeyConfig(cfg),
+                PreferencesOutput::ConfigUpdated(cfg) => AppMsg::UpdateConfig(cfg),
+                PreferencesOutput::LibraryConfigUpdated(path, cfg) => {
+                    AppMsg::UpdateLibraryConfig(path, cfg)
+                }
             });
 
         let details_dialog = DetailsDialogModel::builder()
@@ -213,15 +266,97 @@ impl Component for AppModel {
                 DuplicateDialogOutput::DeleteEntry(key) => AppMsg::DeleteEntry(key),
             });
 
+        let tex_rename_dialog = TexRenameDialogModel::builder()
+            .transient_for(&root)
+            .launch(())
+            .forward(sender.input_sender(), |output| match output {
+                TexRenameDialogOutput::Apply(edits) => AppMsg::ApplyTexEdits(edits),
+            });
+
+        let backup_dialog = BackupDialogModel::builder()
+            .transient_for(&root)
+            .launch(())
+            .forward(sender.input_sender(), |output| match output {
+                BackupDialogOutput::Restore(path, text) => AppMsg::RestoreBackup(path, text),
+            });
+
+        let merge_dialog = MergeDialogModel::builder()
+            .transient_for(&root)
+            .launch(())
+            .forward(sender.input_sender(), |output| match output {
+                MergeDialogOutput::Resolve(choices) => AppMsg::ResolveMerge(choices),
+                MergeDialogOutput::Dismiss => AppMsg::DismissMerge,
+            });
+
+        let recovery_dialog = RecoveryDialogModel::builder()
+            .transient_for(&root)
+            .launch(())
+            .forward(sender.input_sender(), |output| match output {
+                RecoveryDialogOutput::Restore(file) => AppMsg::RestoreRecovery(file),
+                RecoveryDialogOutput::Discard(file) => AppMsg::DiscardRecovery(file),
+            });
+        let lock_dialog = LockDialogModel::builder()
+            .transient_for(&root)
+            .launch(())
+            .forward(sender.input_sender(), |output| match output {
+                LockDialogOutput::OpenReadOnly(path) => AppMsg::OpenLocked(path, false),
+                LockDialogOutput::ForceOpen(path) => AppMsg::OpenLocked(path, true),
+            });
+        let strings_dialog = StringsDialogModel::builder()
+            .transient_for(&root)
+            .launch(())
+            .forward(sender.input_sender(), |output| match output {
+                StringsDialogOutput::Apply(strings) => AppMsg::UpdateStrings(strings),
+            });
+        let review_dialog = ReviewDialogModel::builder()
+            .transient_for(&root)
+            .launch(())
+            .forward(sender.input_sender(), |output| match output {
+                ReviewDialogOutput::Save(path) => AppMsg::ConfirmSave(path),
+            });
+
+        // Edits left behind by a crash or an unsaved session
+        recovery_dialog.emit(RecoveryDialogMsg::Open(core::recovery::pending()));
+
+        // Poll the open file for changes made by other programs
+        gtk::glib::timeout_add_seconds_local(
+            2,
+            gtk::glib::clone!(@strong sender => move || {
+                sender.input(AppMsg::CheckDiskChanges);
+                gtk::glib::ControlFlow::Continue
+            }),
+        );
+        // Autosave and lock refresh; the autosave interval comes from the config
+        gtk::glib::timeout_add_seconds_local(
+            5,
+            gtk::glib::clone!(@strong sender => move || {
+                sender.input(AppMsg::Autosave);
+                gtk::glib::ControlFlow::Continue
+            }),
+        );
+
         let alert = AlertModel::builder()
             .transient_for(&root)
             .launch(())
             .detach();
 
+        if let Some(e) = config_error {
+            alert.emit(alert::AlertMsg::Show(format!(
+                "Settings could not be loaded from {}",
+                e
+            )));
+        }
+
         let model = AppModel {
             bibliography: Bibliography::new(),
             entries,
             original_file_content: None,
+            definitions: Definitions::default(),
+            disk_stamp: None,
+            disk_seen: None,
+            pending_merge: None,
+            file_lock: None,
+            read_only: false,
             current_file_path: None,
             sidebar,
             open_dialog,
@@ -231,10 +366,22 @@ impl Component for AppModel {
             details_dialog,
             search_dialog,
             duplicate_dialog,
-            key_config,
+            tex_folder_dialog,
+            tex_rename_dialog,
+            backup_dialog,
+            merge_dialog,
+            recovery_dialog,
+            lock_dialog,
+            strings_dialog,
+            review_dialog,
+            global_config: config.clone(),
+            config,
             is_dirty: false,
+            last_autosave: None,
             undo_stack: std::collections::VecDeque::new(),
             redo_stack: std::collections::VecDeque::new(),
+            key_renames: std::collections::BTreeMap::new(),
+            entry_ids: EntryIds::default(),
         };
 
         let entries_list_box = model.entries.widget();
@@ -247,4 +394,9 @@ impl Component for AppModel {
     fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>, _root: &Self::Root) {
         update::handle_msg(self, msg, sender);
     }
+
+    fn shutdown(&mut self, _widgets: &mut Self::Widgets, _output: relm4::Sender<Self::Output>) {
+        // Release the lock file so the library is not reported as in use
+        self.file_lock = None;
+    }
 }
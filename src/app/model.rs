@@ -5,18 +5,34 @@ use relm4::factory::FactoryVecDeque;
 use relm4::Controller;
 use relm4_components::open_dialog::OpenDialog;
 use relm4_components::save_dialog::SaveDialog;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 use super::alert::AlertModel;
-use crate::core::keygen::KeyGenConfig;
+use crate::core::config::{AppConfig, Dialect};
+use crate::core::latex::TextEncoding;
+use crate::core::lock::FileLock;
+use crate::core::recovery::RecoveryFile;
+use crate::logic::external::{DiskStamp, PendingMerge, Side};
+use crate::logic::merger::EntryIds;
+use crate::logic::strings::Definitions;
+use crate::logic::tex_rename::TexEdit;
 use crate::ui;
+use crate::ui::backup_dialog::BackupDialogModel;
 use crate::ui::details_dialog::DetailsDialogModel;
 use crate::ui::duplicate_dialog::DuplicateDialogModel;
+use crate::ui::lock_dialog::LockDialogModel;
+use crate::ui::merge_dialog::MergeDialogModel;
 use crate::ui::preferences::PreferencesModel;
+use crate::ui::recovery_dialog::RecoveryDialogModel;
+use crate::ui::review_dialog::ReviewDialogModel;
 use crate::ui::row::BibEntryOutput;
 use crate::ui::search_dialog::SearchDialogModel;
 use crate::ui::sidebar::SidebarModel;
+use crate::ui::strings_dialog::StringsDialogModel;
+use crate::ui::tex_rename_dialog::TexRenameDialogModel;
 use std::collections::VecDeque;
+use std::time::Instant;
 
 // --- State ---
 pub struct AppModel {
@@ -24,6 +40,16 @@ pub struct AppModel {
     pub entries: FactoryVecDeque<ui::row::BibEntry>,
     pub current_file_path: Option<PathBuf>,
     pub original_file_content: Option<String>,
+    /// @string/@preamble/@comment blocks and macro references of the file
+    pub definitions: Definitions,
+    /// Size and mtime of the file when we last loaded, merged or saved it
+    pub disk_stamp: Option<DiskStamp>,
+    /// Last version on disk looked at, even if it could not be taken in
+    pub disk_seen: Option<DiskStamp>,
+    pub pending_merge: Option<PendingMerge>,
+    /// Held while the file is open; None in read-only mode
+    pub file_lock: Option<FileLock>,
+    pub read_only: bool,
 
     // Child Components (Sidebar now handles inputs & status)
     pub sidebar: Controller<SidebarModel>,
@@ -34,11 +60,27 @@ pub struct AppModel {
     pub details_dialog: Controller<DetailsDialogModel>,
     pub search_dialog: Controller<SearchDialogModel>,
     pub duplicate_dialog: Controller<DuplicateDialogModel>,
+    pub tex_folder_dialog: Controller<OpenDialog>,
+    pub tex_rename_dialog: Controller<TexRenameDialogModel>,
+    pub backup_dialog: Controller<BackupDialogModel>,
+    pub merge_dialog: Controller<MergeDialogModel>,
+    pub recovery_dialog: Controller<RecoveryDialogModel>,
+    pub lock_dialog: Controller<LockDialogModel>,
+    pub strings_dialog: Controller<StringsDialogModel>,
+    pub review_dialog: Controller<ReviewDialogModel>,
 
-    pub key_config: KeyGenConfig,
+    // Settings in effect: the global ones with the library's .mkbib.toml applied
+    pub config: AppConfig,
+    pub global_config: AppConfig,
     pub is_dirty: bool,
-    pub undo_stack: VecDeque<Bibliography>,
-    pub redo_stack: VecDeque<Bibliography>,
+    pub last_autosave: Option<Instant>,
+    pub undo_stack: VecDeque<Snapshot>,
+    pub redo_stack: VecDeque<Snapshot>,
+    /// Old key -> current key, for every key renamed since the file was
+    /// opened. Used to update \cite commands in the LaTeX sources.
+    pub key_renames: BTreeMap<String, String>,
+    /// Stable IDs of the entries, so renamed ones are saved in place.
+    pub entry_ids: EntryIds,
 }
 
 // --- Messages ---
@@ -53,9 +95,25 @@ pub enum AppMsg {
     TriggerOpen,
     TriggerSave,
     TriggerSaveAs,
+    ConfirmSave(PathBuf),
+    ShowBackups,
+    RestoreBackup(PathBuf, String),
+    ReformatAll,
+    CheckDiskChanges,
+    ResolveMerge(Vec<Side>),
+    DismissMerge,
+    Autosave,
+    RestoreRecovery(RecoveryFile),
+    DiscardRecovery(RecoveryFile),
+    OpenLocked(PathBuf, bool), // (Path, Force: take over the lock instead of read-only)
     ShowPreferences,
+    ShowStrings,
+    UpdateStrings(Vec<(String, String)>), // (Name, Value as BibTeX)
     AbbreviateAllJournals,
     UnabbreviateAllJournals,
+    ConvertText(TextEncoding),
+    ConvertDialect(Dialect),
+    SortLibrary,
     Undo,
     Redo,
 
@@ -67,10 +125,14 @@ pub enum AppMsg {
     HandleRowOutput(BibEntryOutput),
     FinishEditEntry(String, String),
     RegenerateAllKeys,
+    RegenerateMismatchedKeys,
     ScanDuplicates,
-    UpdateKeyConfig(KeyGenConfig),
+    UpdateConfig(AppConfig),
+    UpdateLibraryConfig(PathBuf, AppConfig),
     AddBiblatexEntry(biblatex::Entry),
     DeleteEntry(String),
+    UpdateTexCitations,
+    ApplyTexEdits(Vec<TexEdit>),
 
     OpenResponse(
         relm4_components::open_dialog::OpenDialogResponse<
@@ -78,9 +140,45 @@ pub enum AppMsg {
         >,
     ),
     SaveResponse(relm4_components::save_dialog::SaveDialogResponse),
+    TexFolderResponse(
+        relm4_components::open_dialog::OpenDialogResponse<
+            relm4_components::open_dialog::SingleSelection,
+        >,
+    ),
+}
+
+/// One undo step: the library, which entry came from which block of the
+/// file (so undoing a rename puts the entry back in its place), and the
+/// @string definitions its macro references expand with.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub bibliography: Bibliography,
+    pub entry_ids: EntryIds,
+    pub definitions: Definitions,
 }
 
 impl AppModel {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            bibliography: self.bibliography.clone(),
+            entry_ids: self.entry_ids.clone(),
+            definitions: self.definitions.clone(),
+        }
+    }
+
+    /// Goes back (or forward) to `snapshot`.
+    pub fn restore_snapshot(&mut self, snapshot: Snapshot) {
+        self.bibliography = snapshot.bibliography;
+        self.entry_ids.restore(&snapshot.entry_ids);
+        // Unreadable text is not part of undo, and the strings are compared
+        // with the file as it is now, which may have been saved since
+        let malformed = std::mem::take(&mut self.definitions.malformed);
+        self.definitions = snapshot.definitions;
+        self.definitions.malformed = malformed;
+        self.definitions
+            .compare_with(self.original_file_content.as_deref().unwrap_or(""));
+    }
+
     pub fn push_snapshot(&mut self) {
         // 1. Clear Redo stack (Standard logic: new action kills the future)
         self.redo_stack.clear();
@@ -91,6 +189,29 @@ impl AppModel {
         }
 
         // 3. Save current state
-        self.undo_stack.push_back(self.bibliography.clone());
+        self.undo_stack.push_back(self.snapshot());
+    }
+
+    /// Records renames that happened at the same time (one edit or one
+    /// regeneration pass), following chains: A -> B, then B -> C gives A -> C.
+    pub fn record_key_renames(&mut self, renames: &[(String, String)]) {
+        let batch: BTreeMap<&str, &str> = renames
+            .iter()
+            .filter(|(old, _)| !old.is_empty())
+            .map(|(old, new)| (old.as_str(), new.as_str()))
+            .collect();
+
+        for target in self.key_renames.values_mut() {
+            if let Some(new_key) = batch.get(target.as_str()) {
+                *target = new_key.to_string();
+            }
+        }
+        for (old_key, new_key) in batch {
+            self.key_renames
+                .insert(old_key.to_string(), new_key.to_string());
+        }
+        self.key_renames.retain(|old, new| old != new);
+        self.definitions.rename_entries(renames);
+        self.entry_ids.rename(renames);
     }
 }
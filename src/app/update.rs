@@ -8,11 +8,12 @@ use relm4_components::save_dialog::SaveDialogMsg;
 use super::alert::AlertMsg; // Import AlertMsg
 use super::model::{AppModel, AppMsg};
 use crate::core;
-use crate::logic::{deduplicator, fetch, library}; // Import deduplicator
+use crate::logic::{deduplicator, external, fetch, library, strings, tex_rename}; // Import deduplicator
 use crate::menu::file_io;
 use crate::ui::duplicate_dialog::DuplicateDialogMsg; // Import DialogMsg
-use crate::ui::preferences::PreferencesMsg;
+use crate::ui::preferences::{LibraryScope, PreferencesMsg};
 use crate::ui::sidebar::SidebarMsg;
+use crate::ui::strings_dialog::StringsDialogMsg;
 
 pub fn handle_msg(model: &mut AppModel, msg: AppMsg, sender: ComponentSender<AppModel>) {
     match msg {
@@ -24,6 +25,16 @@ pub fn handle_msg(model: &mut AppModel, msg: AppMsg, sender: ComponentSender<App
         AppMsg::ClearAll => {
             model.bibliography = Bibliography::new();
             model.entries.guard().clear();
+            model.key_renames.clear();
+            // Closes the file too: nothing left to lock, merge or save back
+            model.current_file_path = None;
+            model.original_file_content = None;
+            model.definitions = Default::default();
+            model.entry_ids = Default::default();
+            model.disk_stamp = None;
+            model.pending_merge = None;
+            model.file_lock = None;
+            model.read_only = false;
             model
                 .sidebar
                 .emit(SidebarMsg::SetStatus("Library cleared.".into()));
@@ -35,9 +46,27 @@ pub fn handle_msg(model: &mut AppModel, msg: AppMsg, sender: ComponentSender<App
         AppMsg::TriggerSaveAs => model
             .save_dialog
             .emit(SaveDialogMsg::SaveAs("library.bib".into())),
+        AppMsg::ConfirmSave(path) => file_io::confirm_save(model, path),
+
+        AppMsg::ShowBackups => file_io::show_backups(model),
+        AppMsg::RestoreBackup(path, text) => file_io::restore_backup(model, path, text),
+        AppMsg::ReformatAll => file_io::reformat_all(model),
+        AppMsg::CheckDiskChanges => external::check(model),
+        AppMsg::ResolveMerge(choices) => external::resolve(model, choices),
+        AppMsg::DismissMerge => external::dismiss(model),
+        AppMsg::Autosave => {
+            if let Some(lock) = model.file_lock.as_mut() {
+                lock.refresh();
+            }
+            file_io::autosave(model)
+        }
+        AppMsg::RestoreRecovery(file) => file_io::restore_recovery(model, file),
+        AppMsg::DiscardRecovery(file) => file_io::discard_recovery(model, file),
 
         AppMsg::OpenResponse(resp) => file_io::handle_open_response(model, resp, sender),
+        AppMsg::OpenLocked(path, force) => file_io::open_locked(model, path, force),
         AppMsg::SaveResponse(resp) => file_io::handle_save_response(model, resp),
+        AppMsg::TexFolderResponse(resp) => tex_rename::handle_folder_response(model, resp),
 
         AppMsg::FetchSuccess(bib) => fetch::handle_success(model, bib, sender),
         AppMsg::FetchError(err) => fetch::handle_error(model, err),
@@ -90,20 +119,65 @@ pub fn handle_msg(model: &mut AppModel, msg: AppMsg, sender: ComponentSender<App
                 .emit(SidebarMsg::SetStatus(format!("Deleted entry: {}", key)));
         }
 
-        AppMsg::RegenerateAllKeys => library::regenerate_keys(model, sender),
+        AppMsg::RegenerateAllKeys => {
+            library::regenerate_keys(model, library::RegenScope::All, sender)
+        }
+        AppMsg::RegenerateMismatchedKeys => {
+            library::regenerate_keys(model, library::RegenScope::Mismatched, sender)
+        }
         AppMsg::AbbreviateAllJournals => library::abbreviate_all_entries(model),
         AppMsg::UnabbreviateAllJournals => library::unabbreviate_all_entries(model),
+        AppMsg::ConvertText(encoding) => library::convert_all_entries(model, encoding),
+        AppMsg::ConvertDialect(dialect) => library::convert_dialect_all(model, dialect),
+        AppMsg::SortLibrary => library::sort_library(model),
 
         AppMsg::FinishEditEntry(key, content) => library::finish_edit(model, key, content, sender),
+        AppMsg::UpdateTexCitations => tex_rename::trigger(model),
+        AppMsg::ApplyTexEdits(edits) => tex_rename::apply(model, edits),
+        AppMsg::ShowStrings => {
+            let defs = &model.definitions;
+            let list = defs
+                .strings
+                .iter()
+                .map(|def| (def.name.clone(), def.value.clone(), defs.usage(&def.name)))
+                .collect();
+            model.strings_dialog.emit(StringsDialogMsg::Open(list));
+        }
+        AppMsg::UpdateStrings(list) => strings::update_definitions(model, list),
 
         // --- Preferences ---
-        AppMsg::ShowPreferences => model.preferences.emit(PreferencesMsg::Show),
-        AppMsg::UpdateKeyConfig(config) => {
-            model.key_config = config;
-            core::config::save(&model.key_config);
-            model
-                .sidebar
-                .emit(SidebarMsg::SetStatus("Preferences saved.".into()));
+        AppMsg::ShowPreferences => {
+            let library = model.current_file_path.as_ref().map(|path| LibraryScope {
+                path: core::config::project_file_for(path),
+                config: model.config.clone(),
+            });
+            model.preferences.emit(PreferencesMsg::Show(library));
+        }
+        AppMsg::UpdateConfig(config) => {
+            model.global_config = config;
+            match core::config::save(&model.global_config) {
+                Ok(_) => model
+                    .sidebar
+                    .emit(SidebarMsg::SetStatus("Preferences saved.".into())),
+                Err(e) => model.alert.emit(AlertMsg::Show(format!(
+                    "Could not save preferences:\n{}",
+                    e
+                ))),
+            }
+            file_io::reload_library_config(model);
+        }
+        AppMsg::UpdateLibraryConfig(path, config) => {
+            match core::config::save_project(&path, &config, &model.global_config) {
+                Ok(_) => model.sidebar.emit(SidebarMsg::SetStatus(format!(
+                    "Library settings saved to {}",
+                    path.display()
+                ))),
+                Err(e) => model.alert.emit(AlertMsg::Show(format!(
+                    "Could not save library settings:\n{}",
+                    e
+                ))),
+            }
+            file_io::reload_library_config(model);
         }
         AppMsg::Undo => {
             crate::logic::undo::perform_undo(model);
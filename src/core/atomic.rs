@@ -0,0 +1,169 @@
+// src/core/atomic.rs
+//
+// Durable replacement of a file: write a sibling temp file, flush it,
+// give it the original's permissions, rename it over the target, then
+// flush the folder so the rename itself survives a power cut. Symlinks
+// are followed so the file they point to is replaced, not the link.
+
+use super::tmp_path;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Symlink chains longer than this are treated as loops.
+const MAX_LINK_DEPTH: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteStage {
+    ResolveLink,
+    CreateTemp,
+    WriteTemp,
+    SyncTemp,
+    CopyPermissions,
+    Rename,
+    SyncFolder,
+}
+
+#[derive(Debug)]
+pub struct WriteError {
+    pub stage: WriteStage,
+    pub path: PathBuf,
+    pub source: io::Error,
+}
+
+impl WriteError {
+    /// True if the new content is already in place and only the final
+    /// folder flush failed.
+    pub fn file_replaced(&self) -> bool {
+        self.stage == WriteStage::SyncFolder
+    }
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let action = match self.stage {
+            WriteStage::ResolveLink => "follow the symlink",
+            WriteStage::CreateTemp => "create the temporary file",
+            WriteStage::WriteTemp => "write the temporary file",
+            WriteStage::SyncTemp => "flush the temporary file to disk",
+            WriteStage::CopyPermissions => "copy the file permissions to",
+            WriteStage::Rename => "replace",
+            WriteStage::SyncFolder => "flush the folder containing",
+        };
+        write!(
+            f,
+            "could not {} {}: {}",
+            action,
+            self.path.display(),
+            self.source
+        )
+    }
+}
+
+impl std::error::Error for WriteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl From<WriteError> for io::Error {
+    fn from(e: WriteError) -> Self {
+        io::Error::new(e.source.kind(), e.to_string())
+    }
+}
+
+/// Writes to a temp file next to the real target of `path`, then renames
+/// it into place, so a crash never leaves a half-written file behind.
+pub fn atomic_write(path: &Path, content: &str) -> Result<(), WriteError> {
+    let target = resolve_links(path).map_err(at(WriteStage::ResolveLink, path))?;
+    let tmp = tmp_path(&target);
+
+    if let Err(e) = replace_with_temp(&target, &tmp, content) {
+        let _ = fs::remove_file(&tmp);
+        return Err(e);
+    }
+
+    sync_folder(&target).map_err(at(WriteStage::SyncFolder, &target))
+}
+
+fn replace_with_temp(target: &Path, tmp: &Path, content: &str) -> Result<(), WriteError> {
+    let original = fs::metadata(target).ok();
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(tmp)
+        .map_err(at(WriteStage::CreateTemp, tmp))?;
+    file.write_all(content.as_bytes())
+        .map_err(at(WriteStage::WriteTemp, tmp))?;
+    file.sync_all().map_err(at(WriteStage::SyncTemp, tmp))?;
+    drop(file);
+
+    if let Some(meta) = &original {
+        fs::set_permissions(tmp, meta.permissions())
+            .map_err(at(WriteStage::CopyPermissions, tmp))?;
+        copy_owner(tmp, meta);
+    }
+
+    fs::rename(tmp, target).map_err(at(WriteStage::Rename, target))
+}
+
+fn at(stage: WriteStage, path: &Path) -> impl FnOnce(io::Error) -> WriteError {
+    let path = path.to_path_buf();
+    move |source| WriteError {
+        stage,
+        path,
+        source,
+    }
+}
+
+/// Follows symlinks (relative ones from the link's folder) to the file
+/// that should really be replaced. The final target may not exist yet.
+fn resolve_links(path: &Path) -> io::Result<PathBuf> {
+    let mut current = path.to_path_buf();
+    for _ in 0..MAX_LINK_DEPTH {
+        match fs::symlink_metadata(&current) {
+            Ok(meta) if meta.file_type().is_symlink() => {
+                let link = fs::read_link(&current)?;
+                current = match current.parent() {
+                    Some(parent) => parent.join(link),
+                    None => link,
+                };
+            }
+            _ => return Ok(current),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "too many levels of symbolic links",
+    ))
+}
+
+/// Keeps the owner and group when we are allowed to (e.g. a shared
+/// library owned by a project group). Failing is not an error.
+#[cfg(unix)]
+fn copy_owner(tmp: &Path, original: &fs::Metadata) {
+    use std::os::unix::fs::MetadataExt;
+    let _ = std::os::unix::fs::chown(tmp, Some(original.uid()), Some(original.gid()));
+}
+
+#[cfg(not(unix))]
+fn copy_owner(_tmp: &Path, _original: &fs::Metadata) {}
+
+#[cfg(unix)]
+fn sync_folder(target: &Path) -> io::Result<()> {
+    let folder = match target.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs::File::open(folder)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_folder(_target: &Path) -> io::Result<()> {
+    // Folders cannot be opened for flushing on Windows; the rename is
+    // already durable there once the file itself is flushed.
+    Ok(())
+}
@@ -0,0 +1,185 @@
+// src/core/backup.rs
+//
+// Rotating backup history. Before every save the library on disk is copied
+// to "<file>.<timestamp>.bak" in the backup folder, and old copies are
+// pruned by count, age and total size. The newest copy is always kept.
+
+use crate::core::config::BackupConfig;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone)]
+pub struct BackupInfo {
+    pub path: PathBuf,
+    pub created: SystemTime,
+    pub size: u64,
+}
+
+impl BackupInfo {
+    /// "2026-10-17 14:30:05 UTC (12.3 KB)"
+    pub fn label(&self) -> String {
+        let (date, time) = utc_parts(self.created);
+        format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC ({:.1} KB)",
+            date.0,
+            date.1,
+            date.2,
+            time.0,
+            time.1,
+            time.2,
+            self.size as f64 / 1024.0
+        )
+    }
+}
+
+/// Folder holding the backups of `bib_path`.
+pub fn backup_dir(bib_path: &Path, config: &BackupConfig) -> PathBuf {
+    let library_dir = bib_path.parent().unwrap_or_else(|| Path::new("."));
+    // Joining an absolute location replaces the library folder
+    library_dir.join(config.location.trim())
+}
+
+fn backup_prefix(bib_path: &Path) -> String {
+    format!(
+        "{}.",
+        bib_path.file_name().unwrap_or_default().to_string_lossy()
+    )
+}
+
+/// All backups of `bib_path`, newest first.
+pub fn list_backups(bib_path: &Path, config: &BackupConfig) -> Vec<BackupInfo> {
+    let prefix = backup_prefix(bib_path);
+    let Ok(dir) = fs::read_dir(backup_dir(bib_path, config)) else {
+        return Vec::new();
+    };
+
+    let mut backups: Vec<BackupInfo> = dir
+        .flatten()
+        .filter(|item| {
+            let name = item.file_name().to_string_lossy().into_owned();
+            name.strip_prefix(&prefix)
+                .and_then(|rest| rest.strip_suffix(".bak"))
+                .map_or(false, |stamp| {
+                    !stamp.is_empty() && stamp.chars().all(|c| c.is_ascii_digit() || c == '-')
+                })
+        })
+        .filter_map(|item| {
+            let meta = item.metadata().ok()?;
+            Some(BackupInfo {
+                path: item.path(),
+                created: meta.modified().unwrap_or(UNIX_EPOCH),
+                size: meta.len(),
+            })
+        })
+        .collect();
+
+    backups.sort_by(|a, b| b.created.cmp(&a.created).then(b.path.cmp(&a.path)));
+    backups
+}
+
+/// Copies the current file into the backup folder and prunes old copies.
+/// Returns the new backup, or None if backups are off, there is nothing
+/// on disk yet, or the file is identical to the latest backup.
+pub fn create_backup(bib_path: &Path, config: &BackupConfig) -> std::io::Result<Option<PathBuf>> {
+    if !config.enabled || !bib_path.exists() {
+        return Ok(None);
+    }
+
+    let dir = backup_dir(bib_path, config);
+    fs::create_dir_all(&dir)?;
+    let current = fs::read(bib_path)?;
+
+    // Saving twice without changes must not push older copies out
+    if let Some(latest) = list_backups(bib_path, config).first() {
+        if fs::read(&latest.path).map_or(false, |old| old == current) {
+            return Ok(None);
+        }
+    }
+
+    let prefix = backup_prefix(bib_path);
+    let stamp = timestamp(SystemTime::now());
+    let mut target = dir.join(format!("{}{}.bak", prefix, stamp));
+    let mut n = 2;
+    while target.exists() {
+        target = dir.join(format!("{}{}-{}.bak", prefix, stamp, n));
+        n += 1;
+    }
+
+    fs::write(&target, &current)?;
+    prune(bib_path, config);
+    Ok(Some(target))
+}
+
+/// Deletes backups beyond the count, age and size limits. Returns how many
+/// were removed.
+pub fn prune(bib_path: &Path, config: &BackupConfig) -> usize {
+    let now = SystemTime::now();
+    let max_age = Duration::from_secs(config.max_age_days * 24 * 60 * 60);
+    let max_bytes = config.max_total_mb * 1024 * 1024;
+    let mut total = 0;
+    let mut removed = 0;
+
+    for (i, backup) in list_backups(bib_path, config).iter().enumerate() {
+        total += backup.size;
+        if i == 0 {
+            continue;
+        }
+
+        let too_many = i >= config.max_count.max(1);
+        let too_old = config.max_age_days > 0
+            && now
+                .duration_since(backup.created)
+                .map_or(false, |age| age > max_age);
+        let too_big = config.max_total_mb > 0 && total > max_bytes;
+
+        if (too_many || too_old || too_big) && fs::remove_file(&backup.path).is_ok() {
+            removed += 1;
+        }
+    }
+
+    removed
+}
+
+// ----------------------------------------------------------------------------
+// Timestamps (UTC, no timezone database needed)
+// ----------------------------------------------------------------------------
+
+/// Today's date (UTC) as "2026-10-17".
+pub fn today() -> String {
+    let ((y, mo, d), _) = utc_parts(SystemTime::now());
+    format!("{:04}-{:02}-{:02}", y, mo, d)
+}
+
+/// "20261017-143005"
+fn timestamp(time: SystemTime) -> String {
+    let ((y, mo, d), (h, mi, s)) = utc_parts(time);
+    format!("{:04}{:02}{:02}-{:02}{:02}{:02}", y, mo, d, h, mi, s)
+}
+
+fn utc_parts(time: SystemTime) -> ((i64, u32, u32), (u64, u64, u64)) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    (
+        civil_from_days(days),
+        (rem / 3600, rem % 3600 / 60, rem % 60),
+    )
+}
+
+/// Days since 1970-01-01 to (year, month, day), proleptic Gregorian.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
@@ -1,7 +1,417 @@
+// src/core/config.rs
+//
+// Application settings, stored as config.toml in the user's config dir.
+// The file carries a schema version; older layouts are migrated on load.
+// A .mkbib.toml next to a library (or in a parent folder) overrides the
+// keygen, formatting and import sections for that project.
+
 use crate::core::keygen::KeyGenConfig;
+use crate::core::latex::TextEncoding;
 use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// Schema version written to config.toml.
+///   1: flat `KeyGenConfig` with formatting and import options mixed in (no `version` key)
+///   2: sectioned `AppConfig`
+pub const CONFIG_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    pub version: u32,
+    pub keygen: KeyGenConfig,
+    pub formatting: FormatConfig,
+    pub import: ImportConfig,
+    pub network: NetworkConfig,
+    pub backup: BackupConfig,
+    pub recovery: RecoveryConfig,
+    pub ui: UiConfig,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            keygen: KeyGenConfig::default(),
+            formatting: FormatConfig::default(),
+            import: ImportConfig::default(),
+            network: NetworkConfig::default(),
+            backup: BackupConfig::default(),
+            recovery: RecoveryConfig::default(),
+            ui: UiConfig::default(),
+        }
+    }
+}
+
+/// How entries are written back to the .bib file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FormatConfig {
+    pub indent_char: char,
+    pub indent_width: u8,
+    pub field_order: Vec<String>,
+    // Brace words like "DNA" or "LaTeX" in titles so styles keep their case.
+    pub protect_capitals: bool,
+    // Pad field names so the "=" signs line up within an entry.
+    pub align_equals: bool,
+    pub delimiter: Delimiter,
+    // Fields always written as "value", whatever `delimiter` says.
+    pub quoted_fields: Vec<String>,
+    pub type_case: NameCase,
+    pub field_case: NameCase,
+    // Comma after the last field.
+    pub trailing_comma: bool,
+    // year = 2020 instead of year = {2020} when the value is all digits.
+    pub bare_numbers: bool,
+    // Wrap long values at this column; 0 keeps each field on one line.
+    pub wrap_width: usize,
+    // Join line breaks inside values into single spaces (always done
+    // before wrapping, so re-saving a wrapped file gives the same text).
+    pub unwrap_lines: bool,
+    // Spell text as Unicode or as LaTeX on save; math is left alone.
+    pub encoding: TextEncoding,
+    // Rename fields and types to BibTeX or BibLaTeX on save.
+    pub dialect: Dialect,
+    // Show what saving changes in the file and ask before writing it.
+    pub review_changes: bool,
+    // Write entries in `sort_order` instead of where they are in the file.
+    pub sort_on_save: bool,
+    // Each criterion breaks the ties of the one before.
+    pub sort_order: Vec<SortKey>,
+}
+
+/// What surrounds field values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Delimiter {
+    /// title = {Value}
+    #[default]
+    Braces,
+    /// title = "Value"
+    Quotes,
+}
+
+impl Delimiter {
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Braces => "Braces {value}",
+            Self::Quotes => "Quotes \"value\"",
+        }
+    }
+}
+
+/// Which flavour of the format entries are written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Dialect {
+    /// Fields and types as each entry has them.
+    #[default]
+    Keep,
+    /// journal, year + month, address, school; @phdthesis, @techreport
+    Bibtex,
+    /// journaltitle, date, location, institution; @thesis, @report
+    Biblatex,
+}
+
+impl Dialect {
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Keep => "Keep as-is",
+            Self::Bibtex => "BibTeX (journal, year)",
+            Self::Biblatex => "BibLaTeX (journaltitle, date)",
+        }
+    }
+}
+
+/// A criterion of the library sort order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SortKey {
+    #[default]
+    Key,
+    /// Last name of the first author
+    Author,
+    Year,
+    EntryType,
+    /// The `dateadded` field (or another tool's equivalent)
+    DateAdded,
+}
+
+impl SortKey {
+    pub const ALL: [SortKey; 5] = [
+        Self::Key,
+        Self::Author,
+        Self::Year,
+        Self::EntryType,
+        Self::DateAdded,
+    ];
+
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Key => "Citation key",
+            Self::Author => "First author",
+            Self::Year => "Year",
+            Self::EntryType => "Entry type",
+            Self::DateAdded => "Date added",
+        }
+    }
+}
+
+/// Case of entry types and field names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum NameCase {
+    #[default]
+    Lower,
+    Upper,
+    Capitalized,
+}
+
+impl NameCase {
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Lower => "lowercase",
+            Self::Upper => "UPPERCASE",
+            Self::Capitalized => "Capitalized",
+        }
+    }
+
+    pub fn apply(&self, name: &str) -> String {
+        match self {
+            Self::Lower => name.to_lowercase(),
+            Self::Upper => name.to_uppercase(),
+            Self::Capitalized => {
+                let lower = name.to_lowercase();
+                let mut chars = lower.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect(),
+                    None => String::new(),
+                }
+            }
+        }
+    }
+}
+
+impl Default for FormatConfig {
+    fn default() -> Self {
+        Self {
+            indent_char: default_indent(),
+            indent_width: default_indent_width(),
+            field_order: default_field_order(),
+            protect_capitals: true,
+            align_equals: false,
+            delimiter: Delimiter::default(),
+            quoted_fields: Vec::new(),
+            type_case: NameCase::default(),
+            field_case: NameCase::default(),
+            trailing_comma: true,
+            bare_numbers: false,
+            wrap_width: 0,
+            unwrap_lines: false,
+            encoding: TextEncoding::default(),
+            dialect: Dialect::default(),
+            review_changes: false,
+            sort_on_save: false,
+            sort_order: vec![SortKey::Author, SortKey::Year, SortKey::Key],
+        }
+    }
+}
+
+/// What happens to entries when they are added to the library.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ImportConfig {
+    pub abbreviate_journals: bool,
+    // Convert the text of new entries to Unicode or LaTeX.
+    pub encoding: TextEncoding,
+    // Stamp new entries with `dateadded`, for sorting by date added.
+    pub record_date_added: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    pub timeout_secs: u64,
+    // Sent to Crossref as "mailto:" so requests use the polite pool.
+    pub contact_email: String,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 30,
+            contact_email: String::new(),
+        }
+    }
+}
+
+/// Copies of the library taken before each save (see `core::backup`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    pub enabled: bool,
+    // Folder for the copies. Relative paths start at the library's folder.
+    pub location: String,
+    pub max_count: usize,
+    // 0 disables the limit.
+    pub max_age_days: u64,
+    pub max_total_mb: u64,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            location: ".mkbib-backups".into(),
+            max_count: 10,
+            max_age_days: 90,
+            max_total_mb: 100,
+        }
+    }
+}
+
+/// Autosave of unsaved edits to the data directory (see `core::recovery`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RecoveryConfig {
+    pub enabled: bool,
+    pub interval_secs: u64,
+}
+
+impl Default for RecoveryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiConfig {
+    pub window_width: i32,
+    pub window_height: i32,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            window_width: 1100,
+            window_height: 750,
+        }
+    }
+}
+
+// --- Defaults for Serde ---
+fn default_indent() -> char {
+    ' '
+}
+fn default_indent_width() -> u8 {
+    4
+}
+fn default_field_order() -> Vec<String> {
+    vec![
+        "author".into(),
+        "title".into(),
+        "year".into(),
+        "date".into(),
+        "journaltitle".into(),
+        "volume".into(),
+        "number".into(),
+        "pages".into(),
+        "doi".into(),
+        "url".into(),
+    ]
+}
+
+// ----------------------------------------------------------------------------
+// Errors
+// ----------------------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:\n{}", self.path.display(), self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// ----------------------------------------------------------------------------
+// Migration
+// ----------------------------------------------------------------------------
+
+/// Layout of config.toml before `AppConfig` existed.
+#[derive(Deserialize)]
+struct ConfigV1 {
+    #[serde(flatten)]
+    keygen: KeyGenConfig,
+    #[serde(default)]
+    abbreviate_journals: bool,
+    #[serde(default = "default_indent")]
+    indent_char: char,
+    #[serde(default = "default_indent_width")]
+    indent_width: u8,
+    #[serde(default = "default_field_order")]
+    field_order: Vec<String>,
+}
+
+impl From<ConfigV1> for AppConfig {
+    fn from(old: ConfigV1) -> Self {
+        Self {
+            keygen: old.keygen,
+            formatting: FormatConfig {
+                indent_char: old.indent_char,
+                indent_width: old.indent_width,
+                field_order: old.field_order,
+                ..FormatConfig::default()
+            },
+            import: ImportConfig {
+                abbreviate_journals: old.abbreviate_journals,
+                ..ImportConfig::default()
+            },
+            ..Self::default()
+        }
+    }
+}
+
+/// Parses a config file of any known version. Returns the config and the
+/// version it was stored as.
+pub fn parse(content: &str) -> Result<(AppConfig, u32), String> {
+    let table: toml::Table = toml::from_str(content).map_err(|e| e.to_string())?;
+
+    let version = match table.get("version") {
+        None => 1,
+        Some(v) => v
+            .as_integer()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or("`version` must be a positive number.")?,
+    };
+
+    let value = toml::Value::Table(table);
+    let config = match version {
+        1 => AppConfig::from(value.try_into::<ConfigV1>().map_err(|e| e.to_string())?),
+        CONFIG_VERSION => value.try_into::<AppConfig>().map_err(|e| e.to_string())?,
+        v if v > CONFIG_VERSION => {
+            return Err(format!(
+                "Settings version {} was written by a newer MkBib (this one reads up to {}).",
+                v, CONFIG_VERSION
+            ))
+        }
+        v => return Err(format!("Unknown settings version {}.", v)),
+    };
+
+    Ok((config, version))
+}
+
+// ----------------------------------------------------------------------------
+// Load / Save
+// ----------------------------------------------------------------------------
 
 fn get_config_path() -> Option<PathBuf> {
     if let Some(proj_dirs) = ProjectDirs::from("com", "mkbib", "mkbib-rs") {
@@ -14,21 +424,181 @@ fn get_config_path() -> Option<PathBuf> {
     None
 }
 
-pub fn save(config: &KeyGenConfig) {
-    if let Some(path) = get_config_path() {
-        if let Ok(toml_str) = toml::to_string_pretty(config) {
-            let _ = fs::write(path, toml_str);
+pub fn save(config: &AppConfig) -> Result<(), ConfigError> {
+    let Some(path) = get_config_path() else {
+        return Ok(());
+    };
+    let error = |message: String| ConfigError {
+        path: path.clone(),
+        message,
+    };
+
+    let toml_str = toml::to_string_pretty(config).map_err(|e| error(e.to_string()))?;
+    crate::core::atomic_write(&path, &toml_str).map_err(|e| error(e.to_string()))
+}
+
+/// Loads the settings, migrating older layouts in place (the old file is
+/// kept as config.toml.v<N>). A missing file gives the defaults; a broken
+/// one is reported and copied to config.toml.invalid so it can be fixed.
+pub fn load() -> Result<AppConfig, ConfigError> {
+    let Some(path) = get_config_path() else {
+        return Ok(AppConfig::default());
+    };
+    if !path.exists() {
+        return Ok(AppConfig::default());
+    }
+
+    let content = fs::read_to_string(&path).map_err(|e| ConfigError {
+        path: path.clone(),
+        message: e.to_string(),
+    })?;
+
+    let (mut config, version) = parse(&content).map_err(|message| {
+        let _ = fs::copy(&path, path.with_extension("toml.invalid"));
+        ConfigError {
+            path: path.clone(),
+            message: format!(
+                "{}\n\nDefaults are used for now. A copy was kept as config.toml.invalid.",
+                message
+            ),
         }
+    })?;
+
+    if version < CONFIG_VERSION {
+        config.version = CONFIG_VERSION;
+        let _ = fs::copy(&path, path.with_extension(format!("toml.v{}", version)));
+        save(&config)?;
     }
+
+    Ok(config)
+}
+
+// ----------------------------------------------------------------------------
+// Project Settings (.mkbib.toml)
+// ----------------------------------------------------------------------------
+
+pub const PROJECT_FILE: &str = ".mkbib.toml";
+
+/// Sections a project file may override; network and UI stay per-user.
+const PROJECT_SECTIONS: [&str; 3] = ["keygen", "formatting", "import"];
+
+/// The nearest .mkbib.toml in the folder of `bib_path` or one of its parents.
+pub fn find_project_file(bib_path: &Path) -> Option<PathBuf> {
+    bib_path
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|p| p.is_file())
+}
+
+/// Where settings for this library are read from and saved to: the existing
+/// project file, or a new one next to the .bib.
+pub fn project_file_for(bib_path: &Path) -> PathBuf {
+    find_project_file(bib_path).unwrap_or_else(|| bib_path.with_file_name(PROJECT_FILE))
 }
 
-pub fn load() -> KeyGenConfig {
-    if let Some(path) = get_config_path() {
-        if let Ok(content) = fs::read_to_string(path) {
-            if let Ok(cfg) = toml::from_str(&content) {
-                return cfg;
+fn to_table(config: &AppConfig) -> toml::Table {
+    toml::Table::try_from(config).unwrap_or_default()
+}
+
+/// The global settings with the values of a project file laid over them.
+/// Only the keys present in the project file are replaced.
+pub fn load_project(path: &Path, global: &AppConfig) -> Result<AppConfig, ConfigError> {
+    let error = |message: String| ConfigError {
+        path: path.to_path_buf(),
+        message,
+    };
+
+    let content = fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+    let overrides: toml::Table = toml::from_str(&content).map_err(|e| error(e.to_string()))?;
+    let mut merged = to_table(global);
+
+    for (section, values) in overrides {
+        if !PROJECT_SECTIONS.contains(&section.as_str()) {
+            return Err(error(format!(
+                "[{}] can only be set in the global preferences.",
+                section
+            )));
+        }
+        let toml::Value::Table(values) = values else {
+            return Err(error(format!("`{}` must be a [section].", section)));
+        };
+        if let Some(toml::Value::Table(base)) = merged.get_mut(&section) {
+            base.extend(values);
+        }
+    }
+
+    toml::Value::Table(merged)
+        .try_into()
+        .map_err(|e: toml::de::Error| error(e.to_string()))
+}
+
+/// Writes the settings changed in the dialog to a project file. `config` is
+/// compared with what the file gave before, not with the global settings:
+/// a pinned value stays pinned even if it matches this user's preferences,
+/// and the rest of the file, comments included, is left as it was.
+pub fn save_project(
+    path: &Path,
+    config: &AppConfig,
+    global: &AppConfig,
+) -> Result<(), ConfigError> {
+    let error = |message: String| ConfigError {
+        path: path.to_path_buf(),
+        message,
+    };
+
+    let existing = if path.is_file() {
+        Some(fs::read_to_string(path).map_err(|e| error(e.to_string()))?)
+    } else {
+        None
+    };
+    let (before, mut doc) = match &existing {
+        Some(content) => (
+            load_project(path, global)?,
+            content
+                .parse::<toml_edit::DocumentMut>()
+                .map_err(|e| error(e.to_string()))?,
+        ),
+        None => (global.clone(), toml_edit::DocumentMut::new()),
+    };
+
+    let ours = to_table(config);
+    let base = to_table(&before);
+
+    for section in PROJECT_SECTIONS {
+        let (Some(toml::Value::Table(ours)), Some(toml::Value::Table(base))) =
+            (ours.get(section), base.get(section))
+        else {
+            continue;
+        };
+        for (key, value) in ours {
+            if base.get(key) == Some(value) {
+                continue;
+            }
+            let mut value: toml_edit::Value = value
+                .to_string()
+                .parse()
+                .map_err(|e: toml_edit::TomlError| error(e.to_string()))?;
+            let table = doc
+                .entry(section)
+                .or_insert_with(toml_edit::table)
+                .as_table_mut()
+                .ok_or_else(|| error(format!("`{}` must be a [section].", section)))?;
+            // Keeps a comment at the end of the line
+            if let Some(old) = table.get(key).and_then(|item| item.as_value()) {
+                *value.decor_mut() = old.decor().clone();
             }
+            table[key.as_str()] = toml_edit::Item::Value(value);
         }
     }
-    KeyGenConfig::default()
+
+    let content = match existing {
+        Some(_) => doc.to_string(),
+        None => format!(
+            "# MkBib settings for the libraries in this folder.\n\
+             # Values here override the global preferences.\n\n{}",
+            doc
+        ),
+    };
+    crate::core::atomic_write(path, &content).map_err(|e| error(e.to_string()))
 }
@@ -1,7 +1,11 @@
 // src/core/keygen.rs
 use crate::core;
-use biblatex::Entry;
+use crate::core::transliterate::{transliterate, Transliteration};
+use biblatex::{Entry, EntryType};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum KeyPart {
@@ -22,50 +26,42 @@ impl KeyPart {
             Self::JournalFirstWord => "Journal (1st Word)",
         }
     }
+
+    /// The pattern marker this preset expands to.
+    /// The fallbacks reproduce the placeholders the fixed parts used to emit.
+    pub fn marker(&self) -> &'static str {
+        match self {
+            Self::AuthorLastName => "[auth:(Unknown)]",
+            Self::Year => "[year:(0000)]",
+            Self::ShortYear => "[shortyear:(00)]",
+            Self::TitleFirstWord => "[title:firstword:(Untitled)]",
+            Self::JournalFirstWord => "[journal:firstword:(Preprint)]",
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct KeyGenConfig {
     pub parts: Vec<KeyPart>,
     pub separator: String,
 
-    // Feature flag for journal abbreviations
+    // Key pattern such as "[auth:lower][year][shorttitle:3]".
+    // When empty, the pattern is built from `parts` and `separator`.
     #[serde(default)]
-    pub abbreviate_journals: bool,
-
-    // ✅ NEW: Formatting - Indentation Character
-    #[serde(default = "default_indent")]
-    pub indent_char: char,
+    pub pattern: String,
 
-    // ✅ NEW: Formatting - Indentation Width
-    #[serde(default = "default_indent_width")]
-    pub indent_width: u8,
+    // How accented names and LaTeX macros are spelled in the key.
+    #[serde(default)]
+    pub transliteration: Transliteration,
 
-    // ✅ NEW: Formatting - Field Order
-    #[serde(default = "default_field_order")]
-    pub field_order: Vec<String>,
-}
+    // Pattern overrides keyed by entry type name (e.g. "thesis", "software").
+    #[serde(default)]
+    pub type_patterns: BTreeMap<String, String>,
 
-// --- Defaults for Serde ---
-fn default_indent() -> char {
-    ' '
-}
-fn default_indent_width() -> u8 {
-    4
-}
-fn default_field_order() -> Vec<String> {
-    vec![
-        "author".into(),
-        "title".into(),
-        "year".into(),
-        "date".into(),
-        "journaltitle".into(),
-        "volume".into(),
-        "number".into(),
-        "pages".into(),
-        "doi".into(),
-        "url".into(),
-    ]
+    // How colliding keys are told apart: "Smith2020a", "Smith2020_1", ...
+    #[serde(default)]
+    pub suffix_style: SuffixStyle,
 }
 
 impl Default for KeyGenConfig {
@@ -77,64 +73,695 @@ impl Default for KeyGenConfig {
                 KeyPart::TitleFirstWord,
             ],
             separator: String::new(),
-            abbreviate_journals: false,
-            // ✅ Initialize new defaults
-            indent_char: default_indent(),
-            indent_width: default_indent_width(),
-            field_order: default_field_order(),
+            pattern: String::new(),
+            transliteration: Transliteration::default(),
+            type_patterns: BTreeMap::new(),
+            suffix_style: SuffixStyle::default(),
         }
     }
 }
 
-pub fn generate_key(entry: &Entry, config: &KeyGenConfig) -> String {
-    // ... (Existing implementation remains unchanged) ...
-    let mut segments = Vec::new();
-
-    for part in &config.parts {
-        let val = match part {
-            KeyPart::AuthorLastName => {
-                if let Ok(authors) = entry.author() {
-                    if let Some(first_author) = authors.iter().next() {
-                        first_author.name.clone()
-                    } else {
-                        "Unknown".to_string()
+// ----------------------------------------------------------------------------
+// Collision Suffixes
+// ----------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SuffixStyle {
+    /// a, b, ..., z, aa, ab, ...
+    #[default]
+    Letters,
+    /// _1, _2, ...
+    Numbers,
+    /// -1, -2, ...
+    Dashed,
+}
+
+impl SuffixStyle {
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Letters => "Letters (Smith2020a)",
+            Self::Numbers => "Numbers (Smith2020_1)",
+            Self::Dashed => "Dashed (Smith2020-1)",
+        }
+    }
+
+    /// The suffix for the `index`-th colliding entry (0-based).
+    pub fn suffix(&self, index: usize) -> String {
+        match self {
+            Self::Letters => {
+                // Bijective base 26: 0 -> a, 25 -> z, 26 -> aa
+                let mut n = index + 1;
+                let mut letters = Vec::new();
+                while n > 0 {
+                    n -= 1;
+                    letters.push((b'a' + (n % 26) as u8) as char);
+                    n /= 26;
+                }
+                letters.iter().rev().collect()
+            }
+            Self::Numbers => format!("_{}", index + 1),
+            Self::Dashed => format!("-{}", index + 1),
+        }
+    }
+
+    /// True if `text` is a suffix this style produces: "a" to "zz" (no
+    /// group gets bigger), or "_1"/"-1" onwards. A word after the base,
+    /// as in "Smith2020deep", is not a suffix.
+    pub fn is_suffix(&self, text: &str) -> bool {
+        // As written by `suffix`: no sign, no leading zero
+        let number =
+            |s: &str| !s.is_empty() && !s.starts_with('0') && s.chars().all(|c| c.is_ascii_digit());
+        match self {
+            Self::Letters => {
+                (1..=2).contains(&text.len()) && text.chars().all(|c| c.is_ascii_lowercase())
+            }
+            Self::Numbers => text.strip_prefix('_').map_or(false, number),
+            Self::Dashed => text.strip_prefix('-').map_or(false, number),
+        }
+    }
+}
+
+impl KeyGenConfig {
+    /// The pattern built from the preset parts, joined by the separator.
+    pub fn parts_pattern(&self) -> String {
+        self.parts
+            .iter()
+            .map(|p| p.marker())
+            .collect::<Vec<_>>()
+            .join(&self.separator)
+    }
+
+    /// The pattern actually used for generation.
+    pub fn effective_pattern(&self) -> String {
+        if self.pattern.trim().is_empty() {
+            self.parts_pattern()
+        } else {
+            self.pattern.trim().to_string()
+        }
+    }
+
+    /// The override for an entry type, if one is configured.
+    /// `@phdthesis` and `@mastersthesis` also pick up a `thesis` override.
+    pub fn type_pattern(&self, entry_type: &EntryType) -> Option<&str> {
+        [entry_type.clone(), entry_type.to_biblatex()]
+            .iter()
+            .filter_map(|t| self.type_patterns.get(&type_name(t)))
+            .map(|p| p.trim())
+            .find(|p| !p.is_empty())
+    }
+
+    /// The pattern used for entries of the given type.
+    pub fn pattern_for(&self, entry_type: &EntryType) -> String {
+        self.type_pattern(entry_type)
+            .map(str::to_string)
+            .unwrap_or_else(|| self.effective_pattern())
+    }
+
+    /// Stores an override under the canonical type name ("PhDThesis" -> "phdthesis").
+    /// An empty pattern removes the override.
+    pub fn set_type_pattern(&mut self, entry_type: &str, pattern: &str) {
+        let name = type_name(&EntryType::new(entry_type.trim()));
+        if pattern.trim().is_empty() {
+            self.type_patterns.remove(&name);
+        } else {
+            self.type_patterns.insert(name, pattern.trim().to_string());
+        }
+    }
+}
+
+/// Lowercase name of an entry type, as written after the '@'.
+pub fn type_name(entry_type: &EntryType) -> String {
+    match entry_type {
+        EntryType::Unknown(name) => name.to_lowercase(),
+        other => other.to_string().to_lowercase(),
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Key Pattern Language
+// ----------------------------------------------------------------------------
+//
+// A pattern is literal text mixed with markers in square brackets:
+//
+//   [auth:lower][year][shorttitle:3]    ->  smith2020deeplea
+//   [auth.etal]-[veryshorttitle]        ->  Smith.etal-Deep
+//
+// Markers: auth, authN, authors, authorsN, auth.etal, authEtAl, authorLast,
+// year, shortyear, title, shorttitle, veryshorttitle, camel, firstpage,
+// lastpage, entrytype, or the name of any field (e.g. [journal]).
+//
+// Modifiers follow the marker, separated by ':' :
+//   lower, upper, capitalize, abbr, firstword, N (truncate to N characters),
+//   regex("pattern","replacement"), (fallback text used when empty).
+
+const FUNCTION_WORDS: &[&str] = &[
+    "a", "an", "the", "of", "and", "or", "but", "nor", "so", "yet", "in", "on", "at", "for", "to",
+    "with", "from", "by", "as", "about", "into", "onto", "upon", "via",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternError {
+    /// Characters before the error in the pattern.
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Column {}: {}", self.position + 1, self.message)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Modifier {
+    Lower,
+    Upper,
+    Capitalize,
+    Abbr,
+    FirstWord,
+    Truncate(usize),
+    Replace(Regex, String),
+    Fallback(String),
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Literal(String),
+    Marker {
+        name: String,
+        modifiers: Vec<Modifier>,
+    },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct KeyPattern {
+    tokens: Vec<Token>,
+    transliteration: Transliteration,
+}
+
+impl KeyPattern {
+    pub fn parse(pattern: &str) -> Result<Self, PatternError> {
+        // Found as a byte offset; the column counts characters
+        Self::parse_tokens(pattern).map_err(|e| PatternError {
+            position: pattern
+                .get(..e.position)
+                .map_or(e.position, |before| before.chars().count()),
+            ..e
+        })
+    }
+
+    fn parse_tokens(pattern: &str) -> Result<Self, PatternError> {
+        let mut tokens = Vec::new();
+        let mut literal = String::new();
+        let mut chars = pattern.char_indices().peekable();
+
+        while let Some((idx, c)) = chars.next() {
+            if c == ']' {
+                return Err(PatternError {
+                    position: idx,
+                    message: "Unexpected ']' outside of a marker".into(),
+                });
+            }
+            if c != '[' {
+                if is_key_char(c) {
+                    literal.push(c);
+                }
+                continue;
+            }
+
+            if !literal.is_empty() {
+                tokens.push(Token::Literal(std::mem::take(&mut literal)));
+            }
+
+            // Collect the marker body up to the matching ']',
+            // ignoring brackets inside quoted regex arguments.
+            let mut body = String::new();
+            let mut in_quotes = false;
+            let mut closed = false;
+            while let Some((_, c)) = chars.next() {
+                match c {
+                    '\\' if in_quotes => {
+                        body.push(c);
+                        if let Some((_, next)) = chars.next() {
+                            body.push(next);
+                        }
+                        continue;
+                    }
+                    '"' => in_quotes = !in_quotes,
+                    ']' if !in_quotes => {
+                        closed = true;
+                        break;
                     }
-                } else {
-                    "Unknown".to_string()
+                    _ => {}
                 }
+                body.push(c);
+            }
+
+            if !closed {
+                return Err(PatternError {
+                    position: idx,
+                    message: "Unclosed '[' marker".into(),
+                });
+            }
+
+            tokens.push(parse_marker(&body, idx + 1)?);
+        }
+
+        if !literal.is_empty() {
+            tokens.push(Token::Literal(literal));
+        }
+
+        Ok(Self {
+            tokens,
+            transliteration: Transliteration::default(),
+        })
+    }
+
+    pub fn evaluate(&self, entry: &Entry) -> String {
+        let mut key = String::new();
+
+        for token in &self.tokens {
+            match token {
+                Token::Literal(s) => key.push_str(s),
+                Token::Marker { name, modifiers } => {
+                    let mut value = marker_value(entry, name, self.transliteration);
+                    for modifier in modifiers {
+                        value = apply_modifier(&value, modifier);
+                    }
+                    key.extend(value.chars().filter(|c| is_key_char(*c)));
+                }
+            }
+        }
+
+        if key.is_empty() {
+            "Unknown".to_string()
+        } else {
+            key
+        }
+    }
+}
+
+/// Characters BibTeX accepts inside a citation key.
+fn is_key_char(c: char) -> bool {
+    !c.is_whitespace() && !c.is_control() && !",{}()\"#%'=~\\".contains(c)
+}
+
+fn parse_marker(body: &str, offset: usize) -> Result<Token, PatternError> {
+    let parts = split_modifiers(body);
+    let name = parts[0].trim();
+    if name.is_empty() {
+        return Err(PatternError {
+            position: offset,
+            message: "Empty marker".into(),
+        });
+    }
+
+    let mut modifiers = Vec::new();
+    let mut pos = offset + parts[0].len() + 1;
+    for raw in &parts[1..] {
+        modifiers.push(parse_modifier(raw.trim(), pos)?);
+        pos += raw.len() + 1;
+    }
+
+    Ok(Token::Marker {
+        name: name.to_string(),
+        modifiers,
+    })
+}
+
+/// Splits "name:mod1:regex(\"a:b\",\"c\")" on top-level colons.
+fn split_modifiers(body: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut depth = 0;
+    let mut escaped = false;
+
+    for (i, c) in body.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '(' if !in_quotes => depth += 1,
+            ')' if !in_quotes && depth > 0 => depth -= 1,
+            ':' if !in_quotes && depth == 0 => {
+                parts.push(&body[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&body[start..]);
+    parts
+}
+
+fn parse_modifier(raw: &str, pos: usize) -> Result<Modifier, PatternError> {
+    let err = |message: String| PatternError {
+        position: pos,
+        message,
+    };
+
+    match raw {
+        "lower" => return Ok(Modifier::Lower),
+        "upper" => return Ok(Modifier::Upper),
+        "capitalize" => return Ok(Modifier::Capitalize),
+        "abbr" => return Ok(Modifier::Abbr),
+        "firstword" => return Ok(Modifier::FirstWord),
+        _ => {}
+    }
+
+    if let Ok(n) = raw.parse::<usize>() {
+        return Ok(Modifier::Truncate(n));
+    }
+
+    if raw.starts_with('(') && raw.ends_with(')') && raw.len() >= 2 {
+        return Ok(Modifier::Fallback(raw[1..raw.len() - 1].to_string()));
+    }
+
+    if let Some(args) = raw
+        .strip_prefix("regex(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        let strings = parse_quoted_args(args)
+            .ok_or_else(|| err("regex expects two quoted arguments".into()))?;
+        if strings.len() != 2 {
+            return Err(err("regex expects two quoted arguments".into()));
+        }
+        let re = Regex::new(&strings[0]).map_err(|e| err(format!("Invalid regex: {}", e)))?;
+        return Ok(Modifier::Replace(re, strings[1].clone()));
+    }
+
+    Err(err(format!("Unknown modifier '{}'", raw)))
+}
+
+/// Parses `"a", "b"` into its unescaped string arguments.
+fn parse_quoted_args(args: &str) -> Option<Vec<String>> {
+    let mut out = Vec::new();
+    let mut chars = args.chars().peekable();
+
+    loop {
+        while chars.peek().map_or(false, |c| c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.next()? != '"' {
+            return None;
+        }
+
+        let mut s = String::new();
+        loop {
+            match chars.next()? {
+                '\\' => match chars.next()? {
+                    '"' => s.push('"'),
+                    other => {
+                        s.push('\\');
+                        s.push(other);
+                    }
+                },
+                '"' => break,
+                c => s.push(c),
+            }
+        }
+        out.push(s);
+
+        while chars.peek().map_or(false, |c| c.is_whitespace()) {
+            chars.next();
+        }
+        match chars.next() {
+            None => return Some(out),
+            Some(',') => continue,
+            Some(_) => return None,
+        }
+    }
+}
+
+fn apply_modifier(value: &str, modifier: &Modifier) -> String {
+    match modifier {
+        Modifier::Lower => value.to_lowercase(),
+        Modifier::Upper => value.to_uppercase(),
+        Modifier::Capitalize => value
+            .split_whitespace()
+            .map(capitalize)
+            .collect::<Vec<_>>()
+            .join(" "),
+        Modifier::Abbr => value
+            .split_whitespace()
+            .filter_map(|w| w.chars().next())
+            .collect(),
+        Modifier::FirstWord => value.split_whitespace().next().unwrap_or("").to_string(),
+        Modifier::Truncate(n) => value
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .take(*n)
+            .collect(),
+        Modifier::Replace(re, replacement) => re.replace_all(value, replacement.as_str()).into(),
+        Modifier::Fallback(text) => {
+            if value.trim().is_empty() {
+                text.clone()
+            } else {
+                value.to_string()
+            }
+        }
+    }
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+/// Strips everything but letters and digits from a single word.
+fn clean_word(word: &str) -> String {
+    word.chars().filter(|c| c.is_alphanumeric()).collect()
+}
+
+/// Field text split into transliterated, cleaned words.
+fn field_words(entry: &Entry, field: &str, mode: Transliteration) -> Vec<String> {
+    let text = entry
+        .fields
+        .get(field)
+        .map(|v| core::bib_to_string(v))
+        .unwrap_or_default();
+    transliterate(&text, mode)
+        .split_whitespace()
+        .map(clean_word)
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+fn last_names(entry: &Entry, mode: Transliteration) -> Vec<String> {
+    entry
+        .author()
+        .map(|authors| {
+            authors
+                .iter()
+                .map(|p| clean_word(&transliterate(&p.name, mode)))
+                .filter(|n| !n.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn title_words(entry: &Entry, skip_function_words: bool, mode: Transliteration) -> Vec<String> {
+    field_words(entry, "title", mode)
+        .into_iter()
+        .filter(|w| !skip_function_words || !FUNCTION_WORDS.contains(&w.to_lowercase().as_str()))
+        .collect()
+}
+
+fn year_of(entry: &Entry) -> String {
+    let year = field_words(entry, "year", Transliteration::Off).join("");
+    if !year.is_empty() {
+        return year;
+    }
+    entry
+        .fields
+        .get("date")
+        .map(|v| core::bib_to_string(v))
+        .map(|d| d.chars().take_while(|c| c.is_ascii_digit()).collect())
+        .unwrap_or_default()
+}
+
+fn page_bounds(entry: &Entry) -> (String, String) {
+    let pages = entry
+        .fields
+        .get("pages")
+        .map(|v| core::bib_to_string(v))
+        .unwrap_or_default();
+    let mut bounds = pages
+        .split(|c: char| c == '-' || c == '–' || c == '—')
+        .map(|p| clean_word(p.trim()))
+        .filter(|p| !p.is_empty());
+    let first = bounds.next().unwrap_or_default();
+    let last = bounds.last().unwrap_or_else(|| first.clone());
+    (first, last)
+}
+
+/// Raw (unmodified) text of a marker; words are separated by spaces.
+fn marker_value(entry: &Entry, name: &str, mode: Transliteration) -> String {
+    let names = || last_names(entry, mode);
+
+    match name {
+        "auth" => names().into_iter().next().unwrap_or_default(),
+        "authors" => names().join(""),
+        "authorLast" => names().pop().unwrap_or_default(),
+        "auth.etal" => {
+            let n = names();
+            match n.len() {
+                0 => String::new(),
+                1 => n[0].clone(),
+                2 => format!("{}.{}", n[0], n[1]),
+                _ => format!("{}.etal", n[0]),
             }
-            KeyPart::Year => entry
-                .fields
-                .get("year")
-                .map(|c| core::bib_to_string(c))
-                .unwrap_or_else(|| "0000".to_string()),
-            KeyPart::ShortYear => {
-                let y = entry
-                    .fields
-                    .get("year")
-                    .map(|c| core::bib_to_string(c))
-                    .unwrap_or_else(|| "0000".to_string());
-                if y.len() >= 4 {
-                    y[2..].to_string()
-                } else {
-                    y
+        }
+        "authEtAl" => {
+            let n = names();
+            match n.len() {
+                0 => String::new(),
+                1 => n[0].clone(),
+                2 => format!("{}And{}", n[0], n[1]),
+                _ => format!("{}EtAl", n[0]),
+            }
+        }
+        "year" => year_of(entry),
+        "shortyear" => {
+            // Last two digits of a full year; anything else as it is
+            let y = year_of(entry);
+            let chars: Vec<char> = y.chars().collect();
+            if chars.len() >= 4 && chars.iter().all(char::is_ascii_digit) {
+                chars[chars.len() - 2..].iter().collect()
+            } else {
+                y
+            }
+        }
+        "title" => title_words(entry, false, mode).join(" "),
+        "shorttitle" => title_words(entry, true, mode)
+            .into_iter()
+            .take(3)
+            .collect::<Vec<_>>()
+            .join(" "),
+        "veryshorttitle" => title_words(entry, true, mode)
+            .into_iter()
+            .next()
+            .unwrap_or_default(),
+        "camel" => title_words(entry, false, mode)
+            .iter()
+            .map(|w| capitalize(w))
+            .collect(),
+        "firstpage" => page_bounds(entry).0,
+        "lastpage" => page_bounds(entry).1,
+        "entrytype" => entry.entry_type.to_string(),
+        _ => {
+            // authN: first N characters of the first author's last name
+            if let Some(n) = name
+                .strip_prefix("authors")
+                .and_then(|n| n.parse::<usize>().ok())
+            {
+                let all = names();
+                let mut out: String = all.iter().take(n).cloned().collect();
+                if all.len() > n {
+                    out.push_str("EtAl");
                 }
+                return out;
+            }
+            if let Some(n) = name
+                .strip_prefix("auth")
+                .and_then(|n| n.parse::<usize>().ok())
+            {
+                return names()
+                    .into_iter()
+                    .next()
+                    .map(|a| a.chars().take(n).collect())
+                    .unwrap_or_default();
+            }
+            field_words(entry, &name.to_lowercase(), mode).join(" ")
+        }
+    }
+}
+
+/// All patterns of a config, parsed once for batch generation.
+#[derive(Debug, Clone)]
+pub struct KeyGenerator {
+    default: KeyPattern,
+    by_type: HashMap<String, KeyPattern>,
+}
+
+impl KeyGenerator {
+    pub fn new(config: &KeyGenConfig) -> Result<Self, PatternError> {
+        let mut default = KeyPattern::parse(&config.effective_pattern())?;
+        default.transliteration = config.transliteration;
+
+        let mut by_type = HashMap::new();
+        for (name, pattern) in &config.type_patterns {
+            if pattern.trim().is_empty() {
+                continue;
             }
-            KeyPart::TitleFirstWord => entry
-                .fields
-                .get("title")
-                .map(|v| core::bib_to_string(v))
-                .map(|t| t.split_whitespace().next().unwrap_or("").to_string())
-                .unwrap_or_else(|| "Untitled".to_string()),
-            KeyPart::JournalFirstWord => entry
-                .fields
-                .get("journal")
-                .map(|v| core::bib_to_string(v))
-                .map(|t| t.split_whitespace().next().unwrap_or("").to_string())
-                .unwrap_or_else(|| "Preprint".to_string()),
-        };
-        let sanitized: String = val.chars().filter(|c: &char| c.is_alphanumeric()).collect();
-        segments.push(sanitized);
-    }
-    segments.join(&config.separator)
+            let mut parsed = KeyPattern::parse(pattern).map_err(|e| PatternError {
+                message: format!("{} (in the @{} pattern)", e.message, name),
+                ..e
+            })?;
+            parsed.transliteration = config.transliteration;
+            by_type.insert(name.clone(), parsed);
+        }
+
+        Ok(Self { default, by_type })
+    }
+
+    pub fn pattern_for(&self, entry_type: &EntryType) -> &KeyPattern {
+        [entry_type.clone(), entry_type.to_biblatex()]
+            .iter()
+            .find_map(|t| self.by_type.get(&type_name(t)))
+            .unwrap_or(&self.default)
+    }
+
+    pub fn generate(&self, entry: &Entry) -> String {
+        self.pattern_for(&entry.entry_type).evaluate(entry)
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Key Locks
+// ----------------------------------------------------------------------------
+
+/// Field marking an entry whose key must survive "Regenerate Keys".
+/// It lives in the .bib itself, so the lock travels with the library.
+pub const KEY_LOCK_FIELD: &str = "keylock";
+
+pub fn is_key_locked(entry: &Entry) -> bool {
+    entry
+        .fields
+        .get(KEY_LOCK_FIELD)
+        .map(|v| core::bib_to_string(v).trim().to_lowercase())
+        .map_or(false, |v| v == "true" || v == "yes" || v == "1")
+}
+
+pub fn set_key_locked(entry: &mut Entry, locked: bool) {
+    if locked {
+        entry.fields.insert(
+            KEY_LOCK_FIELD.into(),
+            vec![biblatex::Spanned::new(
+                biblatex::Chunk::Normal("true".into()),
+                0..0,
+            )],
+        );
+    } else {
+        entry.fields.remove(KEY_LOCK_FIELD);
+    }
+}
+
+pub fn generate_key(entry: &Entry, config: &KeyGenConfig) -> String {
+    // A malformed type pattern falls back to the default pattern, and a
+    // malformed default to the preset parts, so imports never end up
+    // without a key.
+    let mut pattern = KeyPattern::parse(&config.pattern_for(&entry.entry_type))
+        .or_else(|_| KeyPattern::parse(&config.effective_pattern()))
+        .or_else(|_| KeyPattern::parse(&config.parts_pattern()))
+        .unwrap_or_default();
+    pattern.transliteration = config.transliteration;
+    pattern.evaluate(entry)
 }
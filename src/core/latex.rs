@@ -0,0 +1,425 @@
+// src/core/latex.rs
+//
+// Converts field text between Unicode and LaTeX spellings: accented
+// letters, dashes, quotes, Greek letters and common symbols. Text is TeX
+// source as the parser leaves it in a chunk, so commands it did not
+// decode (`\textit{x}`) are kept. Math chunks are never converted.
+
+use crate::core::transliterate::{accent_argument, accent_mark, letter_macro};
+use biblatex::{Chunk, Spanned};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::compose;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TextEncoding {
+    /// Leave text as it is.
+    #[default]
+    Keep,
+    /// é, –, “…”, α
+    Unicode,
+    /// \'{e}, --, ``...'', $\alpha$ (pure ASCII)
+    Latex,
+}
+
+impl TextEncoding {
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Keep => "Keep as-is",
+            Self::Unicode => "Unicode (é, –, α)",
+            Self::Latex => "LaTeX (\\'{e}, --, $\\alpha$)",
+        }
+    }
+}
+
+/// Text-mode symbols and their LaTeX spelling.
+const TEXT_SYMBOLS: &[(char, &str)] = &[
+    ('–', "--"),
+    ('—', "---"),
+    ('‘', "`"),
+    ('’', "'"),
+    ('“', "``"),
+    ('”', "''"),
+    ('„', "\\quotedblbase{}"),
+    ('«', "\\guillemotleft{}"),
+    ('»', "\\guillemotright{}"),
+    ('…', "\\ldots{}"),
+    ('\u{a0}', "~"),
+    ('¡', "!`"),
+    ('¿', "?`"),
+    ('©', "\\textcopyright{}"),
+    ('®', "\\textregistered{}"),
+    ('™', "\\texttrademark{}"),
+    ('§', "\\S{}"),
+    ('¶', "\\P{}"),
+    ('†', "\\dag{}"),
+    ('‡', "\\ddag{}"),
+    ('•', "\\textbullet{}"),
+    ('°', "\\textdegree{}"),
+    ('£', "\\pounds{}"),
+    ('€', "\\euro{}"),
+    ('‰', "\\textperthousand{}"),
+];
+
+/// Other names LaTeX sources use for the text symbols above.
+const TEXT_ALIASES: &[(&str, char)] = &[
+    ("textendash", '–'),
+    ("textemdash", '—'),
+    ("textquoteleft", '‘'),
+    ("textquoteright", '’'),
+    ("textquotedblleft", '“'),
+    ("textquotedblright", '”'),
+    ("dots", '…'),
+    ("textellipsis", '…'),
+    ("textsection", '§'),
+    ("textparagraph", '¶'),
+    ("textdagger", '†'),
+    ("textdaggerdbl", '‡'),
+    ("textsterling", '£'),
+    ("texteuro", '€'),
+];
+
+/// Symbols that only exist in math mode; written as `$\alpha$`.
+const MATH_SYMBOLS: &[(char, &str)] = &[
+    ('α', "\\alpha"),
+    ('β', "\\beta"),
+    ('γ', "\\gamma"),
+    ('δ', "\\delta"),
+    ('ε', "\\epsilon"),
+    ('ϵ', "\\epsilon"),
+    ('ζ', "\\zeta"),
+    ('η', "\\eta"),
+    ('θ', "\\theta"),
+    ('ϑ', "\\vartheta"),
+    ('ι', "\\iota"),
+    ('κ', "\\kappa"),
+    ('λ', "\\lambda"),
+    ('μ', "\\mu"),
+    ('µ', "\\mu"),
+    ('ν', "\\nu"),
+    ('ξ', "\\xi"),
+    ('π', "\\pi"),
+    ('ρ', "\\rho"),
+    ('σ', "\\sigma"),
+    ('ς', "\\varsigma"),
+    ('τ', "\\tau"),
+    ('υ', "\\upsilon"),
+    ('φ', "\\phi"),
+    ('ϕ', "\\phi"),
+    ('χ', "\\chi"),
+    ('ψ', "\\psi"),
+    ('ω', "\\omega"),
+    ('Γ', "\\Gamma"),
+    ('Δ', "\\Delta"),
+    ('Θ', "\\Theta"),
+    ('Λ', "\\Lambda"),
+    ('Ξ', "\\Xi"),
+    ('Π', "\\Pi"),
+    ('Σ', "\\Sigma"),
+    ('Υ', "\\Upsilon"),
+    ('Φ', "\\Phi"),
+    ('Ψ', "\\Psi"),
+    ('Ω', "\\Omega"),
+    ('±', "\\pm"),
+    ('∓', "\\mp"),
+    ('×', "\\times"),
+    ('÷', "\\div"),
+    ('·', "\\cdot"),
+    ('≤', "\\leq"),
+    ('≥', "\\geq"),
+    ('≠', "\\neq"),
+    ('≈', "\\approx"),
+    ('≡', "\\equiv"),
+    ('∼', "\\sim"),
+    ('∝', "\\propto"),
+    ('∞', "\\infty"),
+    ('∂', "\\partial"),
+    ('∇', "\\nabla"),
+    ('∑', "\\sum"),
+    ('∏', "\\prod"),
+    ('∫', "\\int"),
+    ('√', "\\surd"),
+    ('∈', "\\in"),
+    ('∉', "\\notin"),
+    ('⊂', "\\subset"),
+    ('∩', "\\cap"),
+    ('∪', "\\cup"),
+    ('∅', "\\emptyset"),
+    ('∀', "\\forall"),
+    ('∃', "\\exists"),
+    ('→', "\\rightarrow"),
+    ('←', "\\leftarrow"),
+    ('↔', "\\leftrightarrow"),
+    ('⇒', "\\Rightarrow"),
+    ('⇔', "\\Leftrightarrow"),
+    ('′', "\\prime"),
+    ('ℏ', "\\hbar"),
+    ('⁰', "^{0}"),
+    ('¹', "^{1}"),
+    ('²', "^{2}"),
+    ('³', "^{3}"),
+    ('⁴', "^{4}"),
+    ('⁵', "^{5}"),
+    ('⁶', "^{6}"),
+    ('⁷', "^{7}"),
+    ('⁸', "^{8}"),
+    ('⁹', "^{9}"),
+    ('₀', "_{0}"),
+    ('₁', "_{1}"),
+    ('₂', "_{2}"),
+    ('₃', "_{3}"),
+    ('₄', "_{4}"),
+    ('₅', "_{5}"),
+    ('₆', "_{6}"),
+    ('₇', "_{7}"),
+    ('₈', "_{8}"),
+    ('₉', "_{9}"),
+];
+
+/// Accent commands, in the order tried when spelling a combining mark.
+const ACCENT_COMMANDS: &[&str] = &[
+    "'", "`", "^", "\"", "~", "=", ".", "u", "v", "H", "r", "c", "k", "d", "b",
+];
+
+/// Letter macros, in the order tried when spelling a letter.
+const LETTER_COMMANDS: &[&str] = &[
+    "ss", "o", "O", "aa", "AA", "ae", "AE", "oe", "OE", "l", "L", "i", "j", "dh", "DH", "th", "TH",
+    "ng", "NG",
+];
+
+/// Converts the text of every `Normal` and `Verbatim` chunk. Returns
+/// `None` when nothing changed. Going to LaTeX, math symbols become `Math`
+/// chunks of their own.
+pub fn convert_chunks(
+    chunks: &[Spanned<Chunk>],
+    encoding: TextEncoding,
+) -> Option<Vec<Spanned<Chunk>>> {
+    let mut out = Vec::with_capacity(chunks.len());
+    let mut changed = false;
+
+    for chunk in chunks {
+        let (text, verbatim) = match &chunk.v {
+            Chunk::Normal(t) => (t, false),
+            Chunk::Verbatim(t) => (t, true),
+            Chunk::Math(_) => {
+                out.push(chunk.clone());
+                continue;
+            }
+        };
+        let pieces = match encoding {
+            TextEncoding::Keep => vec![(false, text.clone())],
+            TextEncoding::Unicode => vec![(false, to_unicode(text))],
+            TextEncoding::Latex => latex_pieces(text),
+        };
+        if let [(false, t)] = pieces.as_slice() {
+            if t == text {
+                out.push(chunk.clone());
+                continue;
+            }
+        }
+
+        changed = true;
+        for (math, t) in pieces {
+            let v = if math {
+                Chunk::Math(t)
+            } else if verbatim {
+                Chunk::Verbatim(t)
+            } else {
+                Chunk::Normal(t)
+            };
+            out.push(Spanned {
+                v,
+                span: chunk.span.clone(),
+            });
+        }
+    }
+
+    changed.then_some(out)
+}
+
+/// Spells every non-ASCII character that has a LaTeX form in LaTeX; math
+/// symbols are wrapped in `$...$`. Characters without one are kept.
+pub fn to_latex(text: &str) -> String {
+    latex_pieces(text)
+        .into_iter()
+        .map(|(math, t)| if math { format!("${}$", t) } else { t })
+        .collect()
+}
+
+/// Splits `text` into text and math pieces, already spelled in LaTeX.
+fn latex_pieces(text: &str) -> Vec<(bool, String)> {
+    let mut pieces = Vec::new();
+    let mut current = String::with_capacity(text.len());
+
+    for c in text.nfc() {
+        if c.is_ascii() {
+            current.push(c);
+        } else if let Some((_, math)) = MATH_SYMBOLS.iter().find(|(m, _)| *m == c) {
+            if !current.is_empty() {
+                pieces.push((false, std::mem::take(&mut current)));
+            }
+            // Neighbouring symbols share one math group: $\alpha\beta$
+            match pieces.last_mut() {
+                Some((true, m)) => m.push_str(math),
+                _ => pieces.push((true, math.to_string())),
+            }
+        } else {
+            current.push_str(&spell_char(c).unwrap_or_else(|| c.to_string()));
+        }
+    }
+
+    if !current.is_empty() || pieces.is_empty() {
+        pieces.push((false, current));
+    }
+    pieces
+}
+
+/// The LaTeX spelling of a text character: a symbol, a letter macro
+/// (`\o{}`) or accents over a base letter (`\'{e}`, `\'{\i}`).
+fn spell_char(c: char) -> Option<String> {
+    if let Some((_, s)) = TEXT_SYMBOLS.iter().find(|(t, _)| *t == c) {
+        return Some(s.to_string());
+    }
+    if let Some(name) = letter_command(c) {
+        return Some(format!("\\{}{{}}", name));
+    }
+
+    let mut decomposed = c.to_string().nfd().collect::<Vec<char>>().into_iter();
+    let base = decomposed.next()?;
+    let mut spelled = match base {
+        'i' => "\\i".to_string(),
+        'j' => "\\j".to_string(),
+        b if b.is_ascii_alphabetic() => b.to_string(),
+        b => format!("\\{}{{}}", letter_command(b)?),
+    };
+    let mut accented = false;
+    for mark in decomposed {
+        let command = ACCENT_COMMANDS
+            .iter()
+            .find(|cmd| accent_mark(cmd) == Some(mark))?;
+        spelled = format!("\\{}{{{}}}", command, spelled);
+        accented = true;
+    }
+    accented.then_some(spelled)
+}
+
+fn letter_command(c: char) -> Option<&'static str> {
+    let letter = c.to_string();
+    LETTER_COMMANDS
+        .iter()
+        .copied()
+        .find(|name| letter_macro(name) == Some(letter.as_str()))
+}
+
+/// Decodes LaTeX spellings into Unicode: accent and letter macros, symbol
+/// commands, ``quotes'' and dashes. Commands without a Unicode form stay
+/// as they are, and so do grouping braces.
+pub fn to_unicode(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                let (decoded, next) = decode_command(&chars, i);
+                match decoded {
+                    Some(s) => out.push_str(&s),
+                    None => out.extend(&chars[i..next]),
+                }
+                i = next;
+            }
+            '-' if chars.get(i + 1) == Some(&'-') => {
+                if chars.get(i + 2) == Some(&'-') {
+                    out.push('—');
+                    i += 3;
+                } else {
+                    out.push('–');
+                    i += 2;
+                }
+            }
+            '`' if chars.get(i + 1) == Some(&'`') => {
+                out.push('“');
+                i += 2;
+            }
+            '\'' if chars.get(i + 1) == Some(&'\'') => {
+                out.push('”');
+                i += 2;
+            }
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    out.nfc().collect()
+}
+
+/// Decodes the command starting at `chars[start]` (a backslash). Returns
+/// its Unicode text, if it has one, and the index after it.
+fn decode_command(chars: &[char], start: usize) -> (Option<String>, usize) {
+    let mut i = start + 1;
+    let Some(&first) = chars.get(i) else {
+        return (None, i);
+    };
+    let name: String = if first.is_ascii_alphabetic() {
+        chars[i..]
+            .iter()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .collect()
+    } else {
+        first.to_string()
+    };
+    i += name.chars().count();
+
+    if let Some(mark) = accent_mark(&name) {
+        let mut arg = i;
+        if first.is_ascii_alphabetic() {
+            while chars.get(arg) == Some(&' ') {
+                arg += 1;
+            }
+        }
+        return match accent_argument(chars, arg) {
+            (Some(base), next) => (Some(compose(base, mark).unwrap_or(base).to_string()), next),
+            (None, _) => (None, i),
+        };
+    }
+
+    let symbol = letter_macro(&name)
+        .filter(|_| name != "SS")
+        .map(str::to_string)
+        .or_else(|| {
+            let spelled = format!("\\{}{{}}", name);
+            TEXT_SYMBOLS
+                .iter()
+                .find(|(_, s)| *s == spelled)
+                .map(|(c, _)| c.to_string())
+        })
+        .or_else(|| {
+            TEXT_ALIASES
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, c)| c.to_string())
+        })
+        .or_else(|| {
+            let spelled = format!("\\{}", name);
+            MATH_SYMBOLS
+                .iter()
+                .find(|(_, s)| *s == spelled)
+                .map(|(c, _)| c.to_string())
+        });
+
+    match symbol {
+        // A named command ends at "{}" or one space
+        Some(s) if first.is_ascii_alphabetic() => {
+            if chars.get(i) == Some(&'{') && chars.get(i + 1) == Some(&'}') {
+                i += 2;
+            } else if chars.get(i) == Some(&' ') {
+                i += 1;
+            }
+            (Some(s), i)
+        }
+        _ => (None, i),
+    }
+}
@@ -0,0 +1,211 @@
+// src/core/lock.rs
+//
+// Advisory lock so two MkBib instances (possibly on different machines
+// sharing a drive) do not edit the same library. Opening "refs.bib"
+// creates ".refs.bib.mkbib-lock" next to it, recording who holds it. The
+// holder rewrites the lock regularly; a lock that has not been refreshed
+// for a while, or whose process is gone, is treated as left over from a
+// crash.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// A lock not refreshed for this long is considered stale.
+const STALE_AFTER: Duration = Duration::from_secs(5 * 60);
+/// How often the holder refreshes its lock.
+const REFRESH_EVERY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockInfo {
+    pub host: String,
+    pub pid: u32,
+    /// Seconds since the Unix epoch when the file was opened.
+    pub since: u64,
+}
+
+impl LockInfo {
+    fn current() -> Self {
+        Self {
+            host: host_name(),
+            pid: std::process::id(),
+            since: unix_now(),
+        }
+    }
+
+    fn is_ours(&self) -> bool {
+        self.host == host_name() && self.pid == std::process::id()
+    }
+}
+
+impl fmt::Display for LockInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let minutes = unix_now().saturating_sub(self.since) / 60;
+        write!(
+            f,
+            "host {}, process {}, opened {} minutes ago",
+            self.host, self.pid, minutes
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    /// Another live session holds the lock.
+    Held(LockInfo),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockError::Held(info) => write!(f, "Already open in MkBib ({})", info),
+            LockError::Io(e) => write!(f, "Could not create the lock file: {}", e),
+        }
+    }
+}
+
+/// A held lock. Dropping it removes the lock file.
+#[derive(Debug)]
+pub struct FileLock {
+    path: PathBuf,
+    info: LockInfo,
+    refreshed: Instant,
+}
+
+/// ".refs.bib.mkbib-lock" next to "refs.bib".
+pub fn lock_path(bib_path: &Path) -> PathBuf {
+    let name = bib_path.file_name().unwrap_or_default().to_string_lossy();
+    bib_path.with_file_name(format!(".{}.mkbib-lock", name))
+}
+
+/// Takes the lock for `bib_path`. With `force`, a lock held by someone
+/// else is taken over.
+pub fn acquire(bib_path: &Path, force: bool) -> Result<FileLock, LockError> {
+    let path = lock_path(bib_path);
+    let info = LockInfo::current();
+
+    // Only one of two sessions opening the file at once can create it
+    match create_lock(&path, &info) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+            let holder = read_holder(&path).or_else(|| {
+                // Just created by someone still writing it
+                std::thread::sleep(Duration::from_millis(100));
+                read_holder(&path)
+            });
+            if let Some(holder) = holder {
+                if !force && !holder.is_ours() && !is_stale(&path, &holder) {
+                    return Err(LockError::Held(holder));
+                }
+            }
+            write_lock(&path, &info).map_err(LockError::Io)?;
+        }
+        Err(e) => return Err(LockError::Io(e)),
+    }
+
+    // Another session replacing the same stale lock: the last write wins
+    match read_holder(&path) {
+        Some(holder) if holder == info => Ok(FileLock {
+            path,
+            info,
+            refreshed: Instant::now(),
+        }),
+        Some(holder) => Err(LockError::Held(holder)),
+        None => Err(LockError::Io(std::io::Error::new(
+            ErrorKind::InvalidData,
+            "the lock file could not be read back",
+        ))),
+    }
+}
+
+impl FileLock {
+    /// Rewrites the lock so other instances see we are still alive. Cheap
+    /// to call often; only touches the disk every `REFRESH_EVERY`.
+    pub fn refresh(&mut self) {
+        if self.refreshed.elapsed() < REFRESH_EVERY {
+            return;
+        }
+        self.refreshed = Instant::now();
+        // Someone forced the file open: leave their lock alone
+        if read_holder(&self.path).map_or(true, |holder| holder == self.info) {
+            let _ = write_lock(&self.path, &self.info);
+        }
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        if read_holder(&self.path).map_or(false, |holder| holder == self.info) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn read_holder(path: &Path) -> Option<LockInfo> {
+    let text = fs::read_to_string(path).ok()?;
+    toml::from_str(&text).ok()
+}
+
+/// Creates the lock file; fails with `AlreadyExists` if there is one.
+fn create_lock(path: &Path, info: &LockInfo) -> std::io::Result<()> {
+    let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    fill_lock(file, info)
+}
+
+/// Replaces the lock file: only for our own lock, a stale one, or when
+/// forced.
+fn write_lock(path: &Path, info: &LockInfo) -> std::io::Result<()> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    fill_lock(file, info)
+}
+
+fn fill_lock(mut file: fs::File, info: &LockInfo) -> std::io::Result<()> {
+    let text = toml::to_string(info).map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+    file.write_all(text.as_bytes())?;
+    file.sync_all()
+}
+
+fn is_stale(path: &Path, holder: &LockInfo) -> bool {
+    let untouched = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| SystemTime::now().duration_since(t).ok())
+        .map_or(true, |age| age > STALE_AFTER);
+    untouched || (holder.host == host_name() && !process_alive(holder.pid))
+}
+
+#[cfg(target_os = "linux")]
+fn process_alive(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_alive(_pid: u32) -> bool {
+    // No cheap check; rely on the refresh time instead
+    true
+}
+
+fn host_name() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
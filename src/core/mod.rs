@@ -1,10 +1,18 @@
 // src/core/mod.rs
+pub mod atomic;
+pub mod backup;
 pub mod config;
 pub mod keygen;
+pub mod latex;
+pub mod lock;
+pub mod recovery;
+pub mod transliterate;
 
 use biblatex::{Chunk, Spanned};
 // use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+pub use atomic::atomic_write;
 
 /// Helper to safely get string from a list of chunks
 pub fn bib_to_string(val: &[Spanned<Chunk>]) -> String {
@@ -25,34 +33,9 @@ pub fn normalize(s: &str) -> String {
         .to_lowercase()
 }
 
-// pub fn create_backup(file_path: &Path) -> std::io::Result<PathBuf> {
-// let mut backup_path = file_path.to_path_buf();
-
-// if let Some(extension) = backup_path.extension() {
-// let mut ext_str = extension.to_os_string();
-// ext_str.push(".bak");
-// backup_path.set_extension(ext_str);
-// } else {
-// backup_path.set_extension("bak");
-// }
-
-// fs::copy(file_path, &backup_path)?;
-// Ok(backup_path)
-// }
-//
-pub fn create_backup(path: &Path) -> std::io::Result<()> {
-    if path.exists() {
-        let mut backup_path = path.to_path_buf();
-        if let Some(stem) = path.file_stem() {
-            let mut new_name = stem.to_os_string();
-            new_name.push(".bib.bak"); // Creates trial.bib.bak
-            backup_path.set_file_name(new_name);
-        } else {
-            // Fallback
-            backup_path.set_extension("bak");
-        }
-
-        std::fs::copy(path, backup_path)?;
-    }
-    Ok(())
+/// Sibling temp file used by `atomic_write`: "trial.bib" -> "trial.bib.tmp".
+pub fn tmp_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".tmp");
+    path.with_file_name(name)
 }
@@ -0,0 +1,142 @@
+// src/core/recovery.rs
+//
+// Crash-recovery journal. While there are unsaved edits the library is
+// written to "<data dir>/recovery/<name>.bib" periodically; the
+// user's own .bib is never touched. The file is removed after a
+// successful save, so anything still there at startup is lost work.
+
+use directories::ProjectDirs;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const HEADER: &str = "% MkBib recovery file";
+const LIBRARY_PREFIX: &str = "% library:";
+
+#[derive(Debug, Clone)]
+pub struct RecoveryFile {
+    pub file: PathBuf,
+    /// The library the edits belong to; None for a library never saved.
+    pub library: Option<PathBuf>,
+    pub saved: SystemTime,
+    pub content: String,
+}
+
+impl RecoveryFile {
+    pub fn library_label(&self) -> String {
+        self.library
+            .as_ref()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|| "Untitled library".to_string())
+    }
+}
+
+pub fn recovery_dir() -> Option<PathBuf> {
+    ProjectDirs::from("com", "mkbib", "mkbib-rs").map(|dirs| dirs.data_dir().join("recovery"))
+}
+
+/// Recovery file used for `library`: "refs-1a2b3c4d5e6f7a8b.bib", or
+/// "untitled.bib" before the library has a path.
+pub fn recovery_path(library: Option<&Path>) -> Option<PathBuf> {
+    let dir = recovery_dir()?;
+    let name = match library {
+        Some(path) => {
+            let absolute = absolute(path);
+            let stem = absolute.file_stem().unwrap_or_default().to_string_lossy();
+            format!(
+                "{}-{:016x}.bib",
+                stem,
+                fnv1a(absolute.to_string_lossy().as_bytes())
+            )
+        }
+        None => "untitled.bib".to_string(),
+    };
+    Some(dir.join(name))
+}
+
+/// Writes the serialized library to its recovery file.
+pub fn write(library: Option<&Path>, content: &str) -> std::io::Result<()> {
+    let Some(path) = recovery_path(library) else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "no data directory",
+        ));
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let library_line = library
+        .map(|p| absolute(p).display().to_string())
+        .unwrap_or_default();
+    let text = format!(
+        "{}\n{} {}\n\n{}",
+        HEADER, LIBRARY_PREFIX, library_line, content
+    );
+    super::atomic_write(&path, &text).map_err(Into::into)
+}
+
+/// Removes the recovery file for `library`, if any.
+pub fn clear(library: Option<&Path>) {
+    if let Some(path) = recovery_path(library) {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Recovery files worth offering at startup, newest first. Files older
+/// than their library are skipped; they are replaced or cleared the next
+/// time that library is edited and saved.
+pub fn pending() -> Vec<RecoveryFile> {
+    let Some(dir) = recovery_dir() else {
+        return Vec::new();
+    };
+    let Ok(items) = fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+
+    let mut found: Vec<RecoveryFile> = items
+        .flatten()
+        .filter(|item| item.path().extension().map_or(false, |ext| ext == "bib"))
+        .filter_map(|item| read(&item.path()))
+        .filter(|recovery| {
+            let library_time = recovery
+                .library
+                .as_ref()
+                .and_then(|p| fs::metadata(p).ok())
+                .and_then(|m| m.modified().ok());
+            library_time.map_or(true, |t| t < recovery.saved)
+        })
+        .collect();
+
+    found.sort_by(|a, b| b.saved.cmp(&a.saved));
+    found
+}
+
+fn read(file: &Path) -> Option<RecoveryFile> {
+    let text = fs::read_to_string(file).ok()?;
+    let saved = fs::metadata(file)
+        .and_then(|m| m.modified())
+        .unwrap_or(UNIX_EPOCH);
+
+    let rest = text.strip_prefix(HEADER)?.strip_prefix('\n')?;
+    let (library_line, content) = rest.split_once('\n')?;
+    let library = library_line.strip_prefix(LIBRARY_PREFIX)?.trim();
+
+    Some(RecoveryFile {
+        file: file.to_path_buf(),
+        library: (!library.is_empty()).then(|| PathBuf::from(library)),
+        saved,
+        content: content.trim_start_matches('\n').to_string(),
+    })
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Stable across builds, unlike `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
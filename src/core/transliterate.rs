@@ -0,0 +1,265 @@
+// src/core/transliterate.rs
+//
+// Turns author names and titles into ASCII before they end up in a
+// citation key: LaTeX accent macros are decoded first, then any Unicode
+// letter is folded to its closest ASCII spelling.
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::{compose, is_combining_mark};
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Transliteration {
+    /// Keep the text as-is (accented letters stay in the key).
+    Off,
+    /// Drop diacritics: ü -> u, ø -> o, ß -> ss.
+    #[default]
+    Ascii,
+    /// German umlaut spelling: ü -> ue, ä -> ae, ö -> oe.
+    German,
+}
+
+impl Transliteration {
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Off => "Off (keep Unicode)",
+            Self::Ascii => "ASCII (ü → u)",
+            Self::German => "ASCII, German umlauts (ü → ue)",
+        }
+    }
+}
+
+pub fn transliterate(text: &str, mode: Transliteration) -> String {
+    match mode {
+        Transliteration::Off => text.to_string(),
+        Transliteration::Ascii => fold_to_ascii(&decode_latex(text), false),
+        Transliteration::German => fold_to_ascii(&decode_latex(text), true),
+    }
+}
+
+/// Combining mark for a LaTeX accent command (`\"`, `\'`, `\c`, ...).
+pub fn accent_mark(command: &str) -> Option<char> {
+    Some(match command {
+        "`" => '\u{300}',
+        "'" | "´" => '\u{301}',
+        "^" => '\u{302}',
+        "~" => '\u{303}',
+        "=" => '\u{304}',
+        "u" => '\u{306}',
+        "." => '\u{307}',
+        "\"" => '\u{308}',
+        "r" => '\u{30A}',
+        "H" => '\u{30B}',
+        "v" => '\u{30C}',
+        "d" => '\u{323}',
+        "c" => '\u{327}',
+        "k" => '\u{328}',
+        "b" => '\u{332}',
+        _ => return None,
+    })
+}
+
+/// Letters spelled as argument-less macros (`\ss`, `\o`, `\AE`, ...).
+pub fn letter_macro(command: &str) -> Option<&'static str> {
+    Some(match command {
+        "ss" => "ß",
+        "SS" => "SS",
+        "o" => "ø",
+        "O" => "Ø",
+        "aa" => "å",
+        "AA" => "Å",
+        "ae" => "æ",
+        "AE" => "Æ",
+        "oe" => "œ",
+        "OE" => "Œ",
+        "l" => "ł",
+        "L" => "Ł",
+        "i" => "ı",
+        "j" => "ȷ",
+        "dh" => "ð",
+        "DH" => "Ð",
+        "th" => "þ",
+        "TH" => "Þ",
+        "ng" => "ŋ",
+        "NG" => "Ŋ",
+        _ => return None,
+    })
+}
+
+/// Decodes LaTeX accent macros into Unicode and drops grouping braces.
+/// `M{\"u}ller`, `M\"{u}ller` and `M\"uller` all become `Müller`.
+pub fn decode_latex(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '{' | '}' => i += 1,
+            '\\' => {
+                i += 1;
+                let Some(&first) = chars.get(i) else { break };
+
+                // 1. Read the command name
+                let command: String = if first.is_ascii_alphabetic() {
+                    let start = i;
+                    while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                        i += 1;
+                    }
+                    chars[start..i].iter().collect()
+                } else {
+                    i += 1;
+                    first.to_string()
+                };
+
+                // 2. Accent: combine with the following letter or {group}
+                if let Some(mark) = accent_mark(&command) {
+                    if command.chars().all(|c| c.is_ascii_alphabetic()) {
+                        while i < chars.len() && chars[i] == ' ' {
+                            i += 1;
+                        }
+                    }
+                    let (base, next) = accent_argument(&chars, i);
+                    i = next;
+                    if let Some(base) = base {
+                        out.push(compose(base, mark).unwrap_or(base));
+                    }
+                    continue;
+                }
+
+                // 3. Letter macros, optionally terminated by "{}"
+                if let Some(letter) = letter_macro(&command) {
+                    out.push_str(letter);
+                    if chars.get(i) == Some(&'{') && chars.get(i + 1) == Some(&'}') {
+                        i += 2;
+                    } else if chars.get(i) == Some(&' ') {
+                        i += 1;
+                    }
+                    continue;
+                }
+
+                // 4. Escaped symbols (\& \%) keep the symbol; other
+                //    commands (\emph, \textit) are dropped, keeping their argument.
+                if !first.is_ascii_alphabetic() {
+                    out.push(first);
+                }
+            }
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+
+    out
+}
+
+/// The base letter an accent applies to, and the index after it.
+pub fn accent_argument(chars: &[char], mut i: usize) -> (Option<char>, usize) {
+    let braced = chars.get(i) == Some(&'{');
+    if braced {
+        i += 1;
+    }
+
+    let base = match chars.get(i) {
+        // Dotless i/j inside an accent: \'{\i}
+        Some('\\') => {
+            let name: String = chars[i + 1..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphabetic())
+                .collect();
+            i += 1 + name.len();
+            match name.as_str() {
+                "i" => Some('i'),
+                "j" => Some('j'),
+                _ => name.chars().next(),
+            }
+        }
+        Some(&c) => {
+            i += 1;
+            Some(c)
+        }
+        None => None,
+    };
+
+    if braced {
+        while i < chars.len() && chars[i] != '}' {
+            i += 1;
+        }
+        i += 1;
+    }
+
+    (base, i)
+}
+
+/// Letters that have no canonical decomposition into ASCII + accents.
+fn special_fold(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ß' => "ss",
+        'ẞ' => "SS",
+        'ø' => "o",
+        'Ø' => "O",
+        'æ' => "ae",
+        'Æ' => "AE",
+        'œ' => "oe",
+        'Œ' => "OE",
+        'ł' => "l",
+        'Ł' => "L",
+        'đ' => "d",
+        'Đ' => "D",
+        'ð' => "d",
+        'Ð' => "D",
+        'þ' => "th",
+        'Þ' => "Th",
+        'ı' => "i",
+        'ȷ' => "j",
+        'ŋ' => "ng",
+        'Ŋ' => "Ng",
+        _ => return None,
+    })
+}
+
+fn german_fold(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ä' => "ae",
+        'ö' => "oe",
+        'ü' => "ue",
+        'Ä' => "Ae",
+        'Ö' => "Oe",
+        'Ü' => "Ue",
+        _ => return None,
+    })
+}
+
+/// Folds Unicode text to ASCII. Characters without an ASCII spelling are dropped.
+pub fn fold_to_ascii(text: &str, german: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for c in text.nfc() {
+        if c.is_ascii() {
+            out.push(c);
+            continue;
+        }
+        if german {
+            if let Some(s) = german_fold(c) {
+                out.push_str(s);
+                continue;
+            }
+        }
+        if let Some(s) = special_fold(c) {
+            out.push_str(s);
+            continue;
+        }
+        for d in c.to_string().nfd() {
+            if d.is_ascii() {
+                out.push(d);
+            } else if !is_combining_mark(d) {
+                if let Some(s) = special_fold(d) {
+                    out.push_str(s);
+                }
+            }
+        }
+    }
+
+    out
+}
@@ -0,0 +1,214 @@
+// src/logic/dialect.rs
+//
+// Converts entries between classic BibTeX and BibLaTeX: renamed fields,
+// year + month versus date, and the thesis and report types that BibLaTeX
+// folds into one type with a `type` field.
+
+use crate::core;
+use crate::core::config::Dialect;
+use biblatex::{Chunk, Entry, EntryType, Spanned};
+
+/// Fields BibLaTeX renamed, as (BibTeX, BibLaTeX).
+const RENAMED_FIELDS: &[(&str, &str)] = &[
+    ("journal", "journaltitle"),
+    ("address", "location"),
+    ("school", "institution"),
+];
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// Converts `entry` to `dialect`. Returns true if anything changed.
+pub fn convert_entry(entry: &mut Entry, dialect: Dialect) -> bool {
+    match dialect {
+        Dialect::Keep => false,
+        Dialect::Bibtex => to_bibtex(entry),
+        Dialect::Biblatex => to_biblatex(entry),
+    }
+}
+
+/// The name the other dialect uses for `field`, if it differs.
+pub fn counterpart(field: &str) -> Option<&'static str> {
+    RENAMED_FIELDS.iter().find_map(|(bibtex, biblatex)| {
+        if field == *bibtex {
+            Some(*biblatex)
+        } else if field == *biblatex {
+            Some(*bibtex)
+        } else {
+            None
+        }
+    })
+}
+
+/// Month number (1-12) of "3", "03", "mar" or "March"; 0 if unknown.
+pub fn month_number(month: &str) -> u32 {
+    let month = month.trim().to_lowercase();
+    month.parse().unwrap_or_else(|_| {
+        MONTHS
+            .iter()
+            .position(|name| month.starts_with(&name[..3].to_lowercase()))
+            .map_or(0, |i| i as u32 + 1)
+    })
+}
+
+fn to_biblatex(entry: &mut Entry) -> bool {
+    let mut changed = false;
+
+    // 1. @phdthesis -> @thesis with type = {phdthesis}
+    let (new_type, kind) = match entry.entry_type {
+        EntryType::PhdThesis => (EntryType::Thesis, "phdthesis"),
+        EntryType::MastersThesis => (EntryType::Thesis, "mathesis"),
+        EntryType::TechReport => (EntryType::Report, "techreport"),
+        _ => (entry.entry_type.clone(), ""),
+    };
+    if !kind.is_empty() {
+        entry.entry_type = new_type;
+        if !entry.fields.contains_key("type") {
+            set_field(entry, "type", kind);
+        }
+        changed = true;
+    }
+
+    // 2. journal -> journaltitle, ...
+    for (bibtex, biblatex) in RENAMED_FIELDS {
+        changed |= rename_field(entry, bibtex, biblatex);
+    }
+
+    // 3. year + month -> date
+    if entry.fields.contains_key("date") {
+        return changed;
+    }
+    let year = field_text(entry, "year");
+    if year.len() != 4 || !year.chars().all(|c| c.is_ascii_digit()) {
+        return changed;
+    }
+    let date = if entry.fields.contains_key("month") {
+        match month_number(&field_text(entry, "month")) {
+            m @ 1..=12 => format!("{}-{:02}", year, m),
+            // "Spring" has no date form; keep year and month
+            _ => return changed,
+        }
+    } else {
+        year
+    };
+    entry.fields.remove("year");
+    entry.fields.remove("month");
+    set_field(entry, "date", &date);
+    true
+}
+
+fn to_bibtex(entry: &mut Entry) -> bool {
+    let mut changed = false;
+
+    // 1. @thesis with type = {mathesis} -> @mastersthesis. Other `type`
+    //    text ("Habilitation") is kept; BibTeX prints it as the label.
+    let kind = field_text(entry, "type").to_lowercase();
+    let new_type = match entry.entry_type {
+        EntryType::Thesis if kind == "mathesis" || kind == "mastersthesis" => {
+            EntryType::MastersThesis
+        }
+        EntryType::Thesis => EntryType::PhdThesis,
+        EntryType::Report => EntryType::TechReport,
+        EntryType::Unknown(_) => entry.entry_type.clone(),
+        ref other => other.to_bibtex(),
+    };
+    if new_type != entry.entry_type {
+        let implied = match new_type {
+            EntryType::PhdThesis => kind == "phdthesis",
+            EntryType::MastersThesis => true,
+            EntryType::TechReport => kind == "techreport",
+            _ => false,
+        };
+        if implied {
+            entry.fields.remove("type");
+        }
+        entry.entry_type = new_type;
+        changed = true;
+    }
+
+    // 2. journaltitle -> journal, ... BibTeX has `institution` too, for
+    //    reports; only theses use `school`.
+    let thesis = matches!(
+        entry.entry_type,
+        EntryType::PhdThesis | EntryType::MastersThesis
+    );
+    for (bibtex, biblatex) in RENAMED_FIELDS {
+        if *biblatex != "institution" || thesis {
+            changed |= rename_field(entry, biblatex, bibtex);
+        }
+    }
+
+    // 3. date -> year + month (+ day)
+    if entry.fields.contains_key("year") || entry.fields.contains_key("month") {
+        return changed;
+    }
+    let date = field_text(entry, "date");
+    let parts: Vec<&str> = date.split('-').collect();
+    let valid = parts.len() <= 3
+        && parts[0].len() == 4
+        && parts
+            .iter()
+            .all(|p| !p.is_empty() && p.len() <= 4 && p.chars().all(|c| c.is_ascii_digit()));
+    if date.is_empty() || !valid {
+        // Ranges (2020/2021) and open dates have no BibTeX form
+        return changed;
+    }
+    let month = parts.get(1).map_or(0, |m| month_number(m));
+    if parts.len() > 1 && !(1..=12).contains(&month) {
+        return changed;
+    }
+
+    entry.fields.remove("date");
+    set_field(entry, "year", parts[0]);
+    if month > 0 {
+        set_field(entry, "month", MONTHS[month as usize - 1]);
+    }
+    if let Some(day) = parts.get(2) {
+        set_field(entry, "day", day.trim_start_matches('0'));
+    }
+    true
+}
+
+/// Moves `from` to `to`, unless the entry already has `to`.
+fn rename_field(entry: &mut Entry, from: &str, to: &str) -> bool {
+    if entry.fields.contains_key(to) {
+        return false;
+    }
+    match entry.fields.remove(from) {
+        Some(value) => {
+            entry.fields.insert(to.to_string(), value);
+            true
+        }
+        None => false,
+    }
+}
+
+fn field_text(entry: &Entry, name: &str) -> String {
+    entry
+        .fields
+        .get(name)
+        .map(|v| core::bib_to_string(v).trim().to_string())
+        .unwrap_or_default()
+}
+
+fn set_field(entry: &mut Entry, name: &str, text: &str) {
+    entry.fields.insert(
+        name.to_string(),
+        vec![Spanned {
+            v: Chunk::Normal(text.to_string()),
+            span: 0..0,
+        }],
+    );
+}
@@ -0,0 +1,323 @@
+// src/logic/diff.rs
+//
+// Human-readable comparisons between two versions of a library, and of
+// a .bib file before and after saving.
+
+use crate::core;
+use crate::logic::scanner::{self, Block};
+use biblatex::{Bibliography, Entry};
+use std::collections::{BTreeSet, HashMap};
+
+/// Entry-level summary of what replacing `current` with `other` would do.
+pub fn describe_library_changes(current: &Bibliography, other: &Bibliography) -> String {
+    let mut added = Vec::new();
+    let mut removed = Vec::new();
+    let mut changed = Vec::new();
+
+    for entry in other.iter() {
+        match current.get(&entry.key) {
+            None => added.push(format!("+ {}", entry.key)),
+            Some(now) => {
+                let fields = changed_fields(now, entry);
+                if !fields.is_empty() {
+                    changed.push(format!("~ {}  ({})", entry.key, fields.join(", ")));
+                }
+            }
+        }
+    }
+    for entry in current.iter() {
+        if other.get(&entry.key).is_none() {
+            removed.push(format!("- {}", entry.key));
+        }
+    }
+
+    if added.is_empty() && removed.is_empty() && changed.is_empty() {
+        return "Identical to the current library.".to_string();
+    }
+
+    let mut out = format!(
+        "{} restored, {} removed, {} changed\n",
+        added.len(),
+        removed.len(),
+        changed.len()
+    );
+    for line in added.iter().chain(&removed).chain(&changed) {
+        out.push('\n');
+        out.push_str(line);
+    }
+    out
+}
+
+/// Names of the fields (and "type") that differ between two entries.
+fn changed_fields(a: &Entry, b: &Entry) -> Vec<String> {
+    let mut fields = Vec::new();
+    if a.entry_type != b.entry_type {
+        fields.push("type".to_string());
+    }
+
+    let names: BTreeSet<&String> = a.fields.keys().chain(b.fields.keys()).collect();
+    for name in names {
+        let left = a.fields.get(name).map(|v| core::bib_to_string(v));
+        let right = b.fields.get(name).map(|v| core::bib_to_string(v));
+        if left != right {
+            fields.push(name.clone());
+        }
+    }
+    fields
+}
+
+// ----------------------------------------------------------------------------
+// Save preview
+// ----------------------------------------------------------------------------
+
+/// What saving does to a block of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// One line of a block, marked like a unified diff.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine {
+    Same(String),
+    Added(String),
+    Removed(String),
+}
+
+/// A block that saving adds, removes or rewrites.
+#[derive(Debug, Clone)]
+pub struct BlockChange {
+    pub change: Change,
+    /// Citation key ("old → new" when renamed), or "@string name", ...
+    pub label: String,
+    /// Fields on the changed lines, and "type" if that changed.
+    pub fields: Vec<String>,
+    pub lines: Vec<DiffLine>,
+}
+
+/// Block-by-block differences between the file as it is (`before`) and
+/// as saving would write it (`after`). Entries are matched by key, or by
+/// `source_key` (the key they had in `before`) if they were renamed.
+pub fn diff_sources(
+    before: &str,
+    after: &str,
+    source_key: impl Fn(&str) -> Option<String>,
+) -> Vec<BlockChange> {
+    let old = scanner::scan(before).blocks;
+    let new = scanner::scan(after).blocks;
+    let old_ids: Vec<String> = old.iter().map(|b| block_id(b, before)).collect();
+    let new_ids: Vec<String> = new
+        .iter()
+        .map(|b| match source_key(&b.key).filter(|_| is_entry(b)) {
+            Some(source) => source.to_lowercase(),
+            None => block_id(b, after),
+        })
+        .collect();
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    let mut matched = matching_blocks(&old_ids, &new_ids);
+    matched.push((old.len(), new.len()));
+
+    for (oi, nj) in matched {
+        for block in &old[i..oi] {
+            let text = &before[block.start..block.end];
+            changes.push(block_change(Change::Removed, block_label(block), text, ""));
+        }
+        for block in &new[j..nj] {
+            let text = &after[block.start..block.end];
+            changes.push(block_change(Change::Added, block_label(block), "", text));
+        }
+
+        if oi < old.len() && nj < new.len() {
+            let (a, b) = (&old[oi], &new[nj]);
+            let (left, right) = (&before[a.start..a.end], &after[b.start..b.end]);
+            if left != right {
+                let label = if a.key != b.key && is_entry(b) {
+                    format!("{} → {}", a.key, b.key)
+                } else {
+                    block_label(b)
+                };
+                changes.push(block_change(Change::Modified, label, left, right));
+            }
+        }
+        i = oi + 1;
+        j = nj + 1;
+    }
+    changes
+}
+
+fn is_entry(block: &Block) -> bool {
+    !matches!(block.kind.as_str(), "string" | "preamble" | "comment")
+}
+
+/// What a block is matched by: the key of an entry or @string, the text of
+/// anything else.
+fn block_id(block: &Block, text: &str) -> String {
+    match block.kind.as_str() {
+        "preamble" | "comment" => text[block.start..block.end].to_string(),
+        "string" => format!("@string {}", block.key.to_lowercase()),
+        _ => block.key.to_lowercase(),
+    }
+}
+
+fn block_label(block: &Block) -> String {
+    match block.kind.as_str() {
+        "preamble" | "comment" => format!("@{}", block.kind),
+        "string" => format!("@string {}", block.key),
+        _ => block.key.clone(),
+    }
+}
+
+/// Index pairs of blocks present in both lists, in order: the longest run
+/// of shared IDs that appear in the same order on both sides. IDs that
+/// occur twice on a side are left unmatched.
+fn matching_blocks(old: &[String], new: &[String]) -> Vec<(usize, usize)> {
+    let in_old = positions(old);
+    let in_new = positions(new);
+    let pairs: Vec<(usize, usize)> = old
+        .iter()
+        .enumerate()
+        .filter_map(
+            |(i, id)| match (in_old.get(id.as_str()), in_new.get(id.as_str())) {
+                (Some(Some(_)), Some(Some(j))) => Some((i, *j)),
+                _ => None,
+            },
+        )
+        .collect();
+
+    // Longest increasing run of new indices (patience sorting)
+    let mut tails: Vec<usize> = Vec::new(); // index into pairs
+    let mut prev: Vec<Option<usize>> = vec![None; pairs.len()];
+    for (k, &(_, j)) in pairs.iter().enumerate() {
+        let pos = tails.partition_point(|&t| pairs[t].1 < j);
+        if pos > 0 {
+            prev[k] = Some(tails[pos - 1]);
+        }
+        if pos == tails.len() {
+            tails.push(k);
+        } else {
+            tails[pos] = k;
+        }
+    }
+    let mut run = Vec::with_capacity(tails.len());
+    let mut next = tails.last().copied();
+    while let Some(k) = next {
+        run.push(pairs[k]);
+        next = prev[k];
+    }
+    run.reverse();
+    run
+}
+
+/// ID -> its index, or None if the ID occurs more than once.
+fn positions(ids: &[String]) -> HashMap<&str, Option<usize>> {
+    let mut seen = HashMap::new();
+    for (i, id) in ids.iter().enumerate() {
+        seen.entry(id.as_str())
+            .and_modify(|slot| *slot = None)
+            .or_insert(Some(i));
+    }
+    seen
+}
+
+fn block_change(change: Change, label: String, before: &str, after: &str) -> BlockChange {
+    let lines = diff_lines(before, after);
+    let mut fields: Vec<String> = Vec::new();
+    if change == Change::Modified && header(before) != header(after) {
+        fields.push("type".to_string());
+    }
+    if change == Change::Modified {
+        for line in &lines {
+            let (DiffLine::Added(text) | DiffLine::Removed(text)) = line else {
+                continue;
+            };
+            if let Some(name) = field_name(text) {
+                if !fields.contains(&name) {
+                    fields.push(name);
+                }
+            }
+        }
+    }
+    BlockChange {
+        change,
+        label,
+        fields,
+        lines,
+    }
+}
+
+/// Lowercase entry type of a block: "article" in "@Article{key,".
+fn header(block: &str) -> String {
+    block
+        .trim_start_matches('@')
+        .split(|c: char| c == '{' || c == '(')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+}
+
+/// The field a line starts, if it starts one (`  title = {...},`).
+fn field_name(line: &str) -> Option<String> {
+    let (name, _) = line.split_once('=')?;
+    let name = name.trim();
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':'));
+    valid.then(|| name.to_lowercase())
+}
+
+/// Line diff of two blocks (longest common subsequence). Blocks too long
+/// to compare line by line are shown as removed and added whole.
+fn diff_lines(before: &str, after: &str) -> Vec<DiffLine> {
+    let a: Vec<&str> = if before.is_empty() {
+        Vec::new()
+    } else {
+        before.lines().collect()
+    };
+    let b: Vec<&str> = if after.is_empty() {
+        Vec::new()
+    } else {
+        after.lines().collect()
+    };
+    if a.len() * b.len() > 250_000 {
+        return a
+            .iter()
+            .map(|l| DiffLine::Removed(l.to_string()))
+            .chain(b.iter().map(|l| DiffLine::Added(l.to_string())))
+            .collect();
+    }
+
+    // lcs[i][j]: common lines of a[i..] and b[j..]
+    let mut lcs = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::with_capacity(a.len().max(b.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            lines.push(DiffLine::Same(a[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(DiffLine::Removed(a[i].to_string()));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(b[j].to_string()));
+            j += 1;
+        }
+    }
+    lines
+}
@@ -0,0 +1,525 @@
+// src/logic/external.rs
+//
+// Notices when the open .bib is changed by another program (git pull, a
+// text editor, a colleague on a shared drive). Without unsaved edits the
+// file is simply reloaded; otherwise the snapshot we loaded, the version
+// on disk and the library in memory are merged entry by entry, and the
+// entries changed on both sides are handed to the merge dialog.
+
+use crate::app::alert::AlertMsg;
+use crate::app::AppModel;
+use crate::core;
+use crate::logic::library;
+use crate::logic::loader::{self, Loaded};
+use crate::logic::strings::Definitions;
+use crate::ui::merge_dialog::{ConflictView, MergeDialogMsg};
+use crate::ui::sidebar::SidebarMsg;
+use biblatex::{Bibliography, Entry};
+use relm4::ComponentController;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Cheap fingerprint of the file on disk, compared on every poll.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskStamp {
+    pub modified: SystemTime,
+    pub len: u64,
+}
+
+impl DiskStamp {
+    pub fn of(path: &Path) -> Option<Self> {
+        let meta = std::fs::metadata(path).ok()?;
+        Some(Self {
+            modified: meta.modified().ok()?,
+            len: meta.len(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Mine,
+    Disk,
+}
+
+/// An entry changed differently in memory and on disk. `None` means the
+/// entry does not exist on that side.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub key: String,
+    pub base: Option<Entry>,
+    pub mine: Option<Entry>,
+    pub disk: Option<Entry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MergeOutcome {
+    /// Keys in output order (disk order, then entries only we have).
+    order: Vec<String>,
+    resolved: HashMap<String, Entry>,
+    pub conflicts: Vec<Conflict>,
+    /// Entries taken from disk without asking.
+    pub from_disk: usize,
+}
+
+/// A merge waiting for the user to resolve its conflicts.
+#[derive(Debug)]
+pub struct PendingMerge {
+    pub disk_content: String,
+    pub stamp: Option<DiskStamp>,
+    pub outcome: MergeOutcome,
+}
+
+// ----------------------------------------------------------------------------
+// Three-way merge
+// ----------------------------------------------------------------------------
+
+pub fn three_way_merge(
+    base: &Bibliography,
+    mine: &Bibliography,
+    disk: &Bibliography,
+) -> MergeOutcome {
+    let mut order: Vec<String> = Vec::new();
+    let mut seen = HashSet::new();
+    for entry in disk.iter().chain(mine.iter()) {
+        if seen.insert(entry.key.clone()) {
+            order.push(entry.key.clone());
+        }
+    }
+    for entry in base.iter() {
+        if seen.insert(entry.key.clone()) {
+            order.push(entry.key.clone());
+        }
+    }
+
+    let mut resolved = HashMap::new();
+    let mut conflicts = Vec::new();
+    let mut from_disk = 0;
+
+    for key in &order {
+        let b = base.get(key);
+        let m = mine.get(key);
+        let d = disk.get(key);
+
+        let same = |x: Option<&Entry>, y: Option<&Entry>| match (x, y) {
+            (Some(x), Some(y)) => entries_equal(x, y),
+            (None, None) => true,
+            _ => false,
+        };
+
+        let merged = if same(m, d) || same(b, d) {
+            Ok(m.cloned())
+        } else if same(b, m) {
+            from_disk += 1;
+            Ok(d.cloned())
+        } else if let (Some(b), Some(m), Some(d)) = (b, m, d) {
+            match merge_fields(b, m, d) {
+                Some(entry) => {
+                    from_disk += 1;
+                    Ok(Some(entry))
+                }
+                None => Err(()),
+            }
+        } else {
+            Err(())
+        };
+
+        match merged {
+            Ok(Some(entry)) => {
+                resolved.insert(key.clone(), entry);
+            }
+            Ok(None) => {}
+            Err(()) => conflicts.push(Conflict {
+                key: key.clone(),
+                base: b.cloned(),
+                mine: m.cloned(),
+                disk: d.cloned(),
+            }),
+        }
+    }
+
+    MergeOutcome {
+        order,
+        resolved,
+        conflicts,
+        from_disk,
+    }
+}
+
+impl MergeOutcome {
+    /// Builds the final library, one choice per conflict (missing choices
+    /// keep our version).
+    pub fn finish(mut self, choices: &[Side]) -> Bibliography {
+        for (i, conflict) in self.conflicts.drain(..).enumerate() {
+            let chosen = match choices.get(i).copied().unwrap_or(Side::Mine) {
+                Side::Mine => conflict.mine,
+                Side::Disk => conflict.disk,
+            };
+            if let Some(entry) = chosen {
+                self.resolved.insert(conflict.key, entry);
+            }
+        }
+
+        let mut bib = Bibliography::new();
+        for key in &self.order {
+            if let Some(entry) = self.resolved.remove(key) {
+                bib.insert(entry);
+            }
+        }
+        bib
+    }
+}
+
+fn field_text(entry: &Entry, name: &str) -> Option<String> {
+    entry.fields.get(name).map(|v| core::bib_to_string(v))
+}
+
+/// Compares what gets written out, not parser spans.
+fn entries_equal(a: &Entry, b: &Entry) -> bool {
+    a.key == b.key
+        && a.entry_type == b.entry_type
+        && a.fields.len() == b.fields.len()
+        && a.fields
+            .keys()
+            .all(|name| field_text(a, name) == field_text(b, name))
+}
+
+/// Field-level merge of an entry edited on both sides. Returns None when
+/// the same field (or the entry type) was changed differently.
+fn merge_fields(base: &Entry, mine: &Entry, disk: &Entry) -> Option<Entry> {
+    let mut merged = mine.clone();
+
+    if mine.entry_type != disk.entry_type {
+        if mine.entry_type == base.entry_type {
+            merged.entry_type = disk.entry_type.clone();
+        } else if disk.entry_type != base.entry_type {
+            return None;
+        }
+    }
+
+    let names: BTreeSet<&String> = base
+        .fields
+        .keys()
+        .chain(mine.fields.keys())
+        .chain(disk.fields.keys())
+        .collect();
+
+    for name in names {
+        let b = field_text(base, name);
+        let m = field_text(mine, name);
+        let d = field_text(disk, name);
+        if m == d || b == d {
+            continue;
+        }
+        if b != m {
+            return None;
+        }
+        match disk.fields.get(name) {
+            Some(value) => {
+                merged.fields.insert(name.clone(), value.clone());
+            }
+            None => {
+                merged.fields.remove(name);
+            }
+        }
+    }
+
+    Some(merged)
+}
+
+// ----------------------------------------------------------------------------
+// Handlers
+// ----------------------------------------------------------------------------
+
+/// Called periodically. Does nothing unless the open file changed on disk
+/// since we last loaded or saved it.
+pub fn check(model: &mut AppModel) {
+    if model.pending_merge.is_some() {
+        return;
+    }
+    let Some(path) = model.current_file_path.clone() else {
+        return;
+    };
+
+    let stamp = DiskStamp::of(&path);
+    // Versions that could not be taken in are looked at once
+    if stamp == model.disk_stamp || stamp == model.disk_seen {
+        return;
+    }
+    model.disk_seen = stamp;
+    take_in(model, &path, stamp);
+}
+
+/// Reloads or merges the version on disk. `disk_stamp` only moves on once
+/// that version is part of the library, so saving cannot overwrite it.
+fn take_in(model: &mut AppModel, path: &Path, stamp: Option<DiskStamp>) {
+    if stamp.is_none() {
+        model.disk_stamp = None;
+        model.sidebar.emit(SidebarMsg::SetStatus(format!(
+            "{} was removed from disk. Saving will recreate it.",
+            path.display()
+        )));
+        return;
+    }
+
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return;
+    };
+    // Touched (or rewritten by our own save) without any real change
+    if model.original_file_content.as_deref() == Some(content.as_str()) {
+        model.disk_stamp = stamp;
+        return;
+    }
+
+    // Text that could not be read when loading is fine. Anything else is
+    // often a half-finished git merge; wait for the next change
+    let Loaded {
+        bib: disk,
+        malformed,
+    } = loader::load(&content);
+    let known = |text: &str| model.definitions.malformed.iter().any(|m| m.text == text);
+    if let Some(block) = malformed.iter().find(|block| !known(&block.text)) {
+        model.sidebar.emit(SidebarMsg::SetStatus(format!(
+            "{} changed on disk but cannot be read yet: {} ({})",
+            path.display(),
+            block.error,
+            block.label
+        )));
+        return;
+    }
+
+    if !model.is_dirty {
+        model.push_snapshot();
+        model.definitions = Definitions::scan(&content, &disk);
+        model.definitions.malformed = malformed;
+        // Rebased, not renumbered, so undo still finds the blocks
+        model.entry_ids.rebase(&disk);
+        model.bibliography = disk;
+        model.original_file_content = Some(content);
+        model.disk_stamp = stamp;
+        library::refresh_ui_list(model);
+        model.sidebar.emit(SidebarMsg::SetStatus(format!(
+            "Reloaded {} (changed on disk).",
+            path.display()
+        )));
+        return;
+    }
+
+    let base = model
+        .original_file_content
+        .as_deref()
+        .map(|text| loader::load(text).bib)
+        .unwrap_or_else(Bibliography::new);
+    let outcome = three_way_merge(&base, &model.bibliography, &disk);
+
+    if outcome.conflicts.is_empty() {
+        let from_disk = outcome.from_disk;
+        apply_merge(model, content, outcome.finish(&[]));
+        model.disk_stamp = stamp;
+        model.sidebar.emit(SidebarMsg::SetStatus(format!(
+            "{} changed on disk: merged {} entries with your edits.",
+            path.display(),
+            from_disk
+        )));
+        return;
+    }
+
+    let views = outcome.conflicts.iter().map(conflict_view).collect();
+    model.merge_dialog.emit(MergeDialogMsg::Open(views));
+    model.pending_merge = Some(PendingMerge {
+        disk_content: content,
+        stamp,
+        outcome,
+    });
+}
+
+/// Applies the user's choices from the merge dialog.
+pub fn resolve(model: &mut AppModel, choices: Vec<Side>) {
+    let Some(pending) = model.pending_merge.take() else {
+        return;
+    };
+    let conflicts = pending.outcome.conflicts.len();
+    let merged = pending.outcome.finish(&choices);
+    model.disk_stamp = pending.stamp;
+    apply_merge(model, pending.disk_content, merged);
+    model.sidebar.emit(SidebarMsg::SetStatus(format!(
+        "Merged changes from disk ({} conflicts resolved). Save to write the result.",
+        conflicts
+    )));
+}
+
+/// The user closed the merge dialog: keep the library as it is. The next
+/// save overwrites the version on disk.
+pub fn dismiss(model: &mut AppModel) {
+    if let Some(pending) = model.pending_merge.take() {
+        model.disk_stamp = pending.stamp;
+        model.sidebar.emit(SidebarMsg::SetStatus(
+            "Kept your version. Saving will overwrite the changes on disk.".into(),
+        ));
+    }
+}
+
+/// Guard used before saving: true if the file on disk no longer matches
+/// what we loaded, in which case a merge has been started instead, or the
+/// save is refused while the version on disk cannot be read.
+pub fn changed_before_save(model: &mut AppModel) -> bool {
+    if model.pending_merge.is_some() {
+        model.alert.emit(AlertMsg::Show(
+            "The file changed on disk.\nResolve the merge before saving.".into(),
+        ));
+        return true;
+    }
+    let Some(path) = model.current_file_path.clone() else {
+        return false;
+    };
+    let stamp = DiskStamp::of(&path);
+    if stamp == model.disk_stamp {
+        return false;
+    }
+
+    model.disk_seen = stamp;
+    take_in(model, &path, stamp);
+    if model.pending_merge.is_some() {
+        return true;
+    }
+    if model.disk_stamp != stamp {
+        model.alert.emit(AlertMsg::Show(format!(
+            "{} changed on disk and cannot be read yet, so it was not saved.\n\
+             Finish the change on disk (a git merge, for example) and save again, \
+             or use Save As to keep your version elsewhere.",
+            path.display()
+        )));
+        return true;
+    }
+    false
+}
+
+fn apply_merge(model: &mut AppModel, disk_content: String, merged: Bibliography) {
+    model.push_snapshot();
+    let disk = loader::load(&disk_content);
+    let mut malformed = disk.malformed;
+    // Text still there stays fixed or deleted
+    for block in &mut malformed {
+        block.resolution = model
+            .definitions
+            .malformed
+            .iter()
+            .find(|old| old.text == block.text)
+            .and_then(|old| old.resolution.clone());
+    }
+    model.definitions.rebase(&disk_content, &disk.bib);
+    model.definitions.malformed = malformed;
+    model.entry_ids.rebase(&disk.bib);
+    model.bibliography = merged;
+    // The disk version becomes the new base, so saving keeps its layout
+    model.original_file_content = Some(disk_content);
+    model.is_dirty = true;
+    library::refresh_ui_list(model);
+}
+
+fn conflict_view(conflict: &Conflict) -> ConflictView {
+    let render = |entry: &Option<Entry>| match entry {
+        Some(entry) => entry
+            .to_bibtex_string()
+            .unwrap_or_else(|e| format!("% Error generating BibTeX: {}", e)),
+        None => "(deleted)".to_string(),
+    };
+    let summary = match (&conflict.base, &conflict.mine, &conflict.disk) {
+        (None, _, _) => "Added on both sides",
+        (Some(_), None, _) => "You deleted it, it was edited on disk",
+        (Some(_), _, None) => "You edited it, it was deleted on disk",
+        _ => "Edited on both sides",
+    };
+    ConflictView {
+        key: conflict.key.clone(),
+        summary: summary.to_string(),
+        mine: render(&conflict.mine),
+        disk: render(&conflict.disk),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str =
+        "@misc{a, title = {A}, note = {n}}\n@misc{b, title = {B}}\n@misc{c, title = {C}}\n";
+
+    fn bib(source: &str) -> Bibliography {
+        Bibliography::parse(source).unwrap()
+    }
+
+    fn field(bib: &Bibliography, key: &str, name: &str) -> Option<String> {
+        field_text(bib.get(key)?, name)
+    }
+
+    #[test]
+    fn takes_changes_from_either_side() {
+        let mine = bib(&BASE.replace("{B}", "{B2}"));
+        let disk = bib(&format!(
+            "{}@misc{{d, title = {{D}}}}\n",
+            BASE.replace("{C}", "{C2}")
+        ));
+        let outcome = three_way_merge(&bib(BASE), &mine, &disk);
+        assert!(outcome.conflicts.is_empty());
+        assert_eq!(outcome.from_disk, 2);
+
+        let merged = outcome.finish(&[]);
+        let keys: Vec<&str> = merged.iter().map(|e| e.key.as_str()).collect();
+        assert_eq!(keys, ["a", "b", "c", "d"]);
+        assert_eq!(field(&merged, "b", "title").as_deref(), Some("B2"));
+        assert_eq!(field(&merged, "c", "title").as_deref(), Some("C2"));
+    }
+
+    #[test]
+    fn merges_different_fields_of_one_entry() {
+        let mine = bib(&BASE.replace("{A}", "{A2}"));
+        let disk = bib(&BASE.replace("{n}", "{n2}"));
+        let outcome = three_way_merge(&bib(BASE), &mine, &disk);
+        assert!(outcome.conflicts.is_empty());
+
+        let merged = outcome.finish(&[]);
+        assert_eq!(field(&merged, "a", "title").as_deref(), Some("A2"));
+        assert_eq!(field(&merged, "a", "note").as_deref(), Some("n2"));
+    }
+
+    #[test]
+    fn the_same_field_changed_twice_is_a_conflict() {
+        let mine = bib(&BASE.replace("{A}", "{Mine}"));
+        let disk = bib(&BASE.replace("{A}", "{Disk}"));
+        let outcome = three_way_merge(&bib(BASE), &mine, &disk);
+        assert_eq!(outcome.conflicts.len(), 1);
+        assert_eq!(outcome.conflicts[0].key, "a");
+
+        let merged = outcome.finish(&[Side::Disk]);
+        assert_eq!(field(&merged, "a", "title").as_deref(), Some("Disk"));
+    }
+
+    #[test]
+    fn deletions_merge_unless_the_other_side_edited() {
+        // Deleted on disk, untouched here: gone
+        let disk = bib("@misc{a, title = {A}, note = {n}}\n@misc{c, title = {C}}\n");
+        let outcome = three_way_merge(&bib(BASE), &bib(BASE), &disk);
+        assert!(outcome.conflicts.is_empty());
+        assert!(outcome.finish(&[]).get("b").is_none());
+
+        // Deleted on disk, edited here: asked, and kept by default
+        let mine = bib(&BASE.replace("{B}", "{B2}"));
+        let outcome = three_way_merge(&bib(BASE), &mine, &disk);
+        assert_eq!(outcome.conflicts.len(), 1);
+        assert!(outcome.conflicts[0].disk.is_none());
+        assert_eq!(
+            field(&outcome.finish(&[]), "b", "title").as_deref(),
+            Some("B2")
+        );
+    }
+
+    #[test]
+    fn the_same_addition_on_both_sides_is_not_a_conflict() {
+        let added = format!("{}@misc{{d, title = {{D}}}}\n", BASE);
+        let outcome = three_way_merge(&bib(BASE), &bib(&added), &bib(&added));
+        assert!(outcome.conflicts.is_empty());
+        assert_eq!(outcome.finish(&[]).len(), 4);
+    }
+}
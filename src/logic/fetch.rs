@@ -20,8 +20,9 @@ pub fn handle_fetch_doi(model: &mut AppModel, sender: ComponentSender<AppModel>,
     .emit(SidebarMsg::SetStatus(format!("Fetching DOI: {}...", doi)));
 
   let input = sender.input_sender().clone();
+  let net = model.config.network.clone();
   sender.command(move |_out, _shutdown| async move {
-    let result = match api::fetch_doi(&doi).await {
+    let result = match api::fetch_doi(&doi, &net).await {
       Ok(bib) => AppMsg::FetchSuccess(bib),
       Err(e) => AppMsg::FetchError(e.to_string()),
     };
@@ -42,8 +43,9 @@ pub fn handle_fetch_search(model: &mut AppModel, sender: ComponentSender<AppMode
   )));
 
   let input = sender.input_sender().clone();
+  let net = model.config.network.clone();
   sender.command(move |_out, _shutdown| async move {
-    match api::search_crossref_suggestions(&query).await {
+    match api::search_crossref_suggestions(&query, &net).await {
       Ok(items) => input.send(AppMsg::SearchResultsLoaded(items)).unwrap(),
       Err(e) => input.send(AppMsg::FetchError(e.to_string())).unwrap(),
     }
@@ -1,12 +1,29 @@
 // src/logic/formatter.rs
 
-use crate::core::keygen::KeyGenConfig;
-use biblatex::Entry;
+use crate::core::config::{Delimiter, Dialect, FormatConfig};
+use crate::core::keygen;
+use crate::core::latex::{self, TextEncoding};
+use crate::logic::dialect;
+use crate::logic::strings::Definitions;
+use biblatex::{Chunk, Entry};
 use std::fmt::Write;
 
-pub fn format_entry(entry: &Entry, config: &KeyGenConfig) -> String {
+/// Writes `entry` in the configured style. Fields that used a macro in the
+/// file (and still read the same) are written as that macro reference.
+pub fn format_entry(entry: &Entry, defs: &Definitions, config: &FormatConfig) -> String {
     let mut out = String::new();
 
+    // 0. Dialect: convert a copy, the library keeps its own fields
+    let converted;
+    let entry = if config.dialect == Dialect::Keep {
+        entry
+    } else {
+        let mut copy = entry.clone();
+        dialect::convert_entry(&mut copy, config.dialect);
+        converted = copy;
+        &converted
+    };
+
     // 1. Indentation
     let indent = if config.indent_char == '\t' {
         "\t".repeat(config.indent_width as usize)
@@ -15,28 +32,53 @@ pub fn format_entry(entry: &Entry, config: &KeyGenConfig) -> String {
     };
 
     // 2. Header
-    let _ = writeln!(out, "@{}{{{},", entry.entry_type.to_bibtex(), entry.key);
-
-    // 3. Fields
-    let mut written_fields = std::collections::HashSet::new();
+    let _ = writeln!(
+        out,
+        "@{}{{{},",
+        config
+            .type_case
+            .apply(&keygen::type_name(&entry.entry_type)),
+        entry.key
+    );
 
-    // Priority Fields
-    for key in &config.field_order {
-        if let Some(chunks) = entry.fields.get(key) {
-            write_field(&mut out, key, chunks, &indent);
-            written_fields.insert(key.clone());
-        }
-    }
+    // 3. Fields: priority order first, then the rest alphabetically. A
+    //    field listed under its other dialect's name ("journal" for
+    //    "journaltitle") takes that place.
+    let rank = |key: &str| {
+        config
+            .field_order
+            .iter()
+            .position(|f| f == key)
+            .or_else(|| {
+                let other = dialect::counterpart(key)?;
+                config.field_order.iter().position(|f| f == other)
+            })
+            .unwrap_or(usize::MAX)
+    };
+    let mut keys: Vec<&String> = entry.fields.keys().collect();
+    keys.sort_by_key(|key| (rank(key), key.as_str()));
 
-    // Remaining Fields
-    let mut remaining_keys: Vec<_> = entry.fields.keys().collect();
-    remaining_keys.sort();
+    let width = if config.align_equals {
+        keys.iter()
+            .map(|key| key.chars().count())
+            .max()
+            .unwrap_or(0)
+    } else {
+        0
+    };
 
-    for key in remaining_keys {
-        if !written_fields.contains(key) {
-            let chunks = entry.fields.get(key).unwrap();
-            write_field(&mut out, key, chunks, &indent);
-        }
+    for (i, key) in keys.iter().enumerate() {
+        let chunks = &entry.fields[key.as_str()];
+        let last = i + 1 == keys.len();
+        let raw = defs.raw_value(&entry.key, key, chunks).or_else(|| {
+            let other = dialect::counterpart(key)?;
+            defs.raw_value(&entry.key, other, chunks)
+        });
+        let value = match raw {
+            Some(raw) => raw.to_string(),
+            None => format_value(key, chunks, config),
+        };
+        write_field(&mut out, key, value, &indent, width, last, config);
     }
 
     // 4. Footer
@@ -48,20 +90,498 @@ pub fn format_entry(entry: &Entry, config: &KeyGenConfig) -> String {
     out.trim().to_string()
 }
 
+/// Fields BibLaTeX reads verbatim: no macros, so only braces are
+/// escaped and backslashes are written as they are.
+pub const VERBATIM_FIELDS: &[&str] = &[
+    "doi", "eprint", "file", "pdf", "uri", "url", "urlraw", "verba", "verbb", "verbc",
+];
+
+/// Fields written without delimiters when `bare_numbers` is on and the
+/// value is all digits.
+const BARE_NUMBER_FIELDS: &[&str] = &["volume", "year"];
+
+/// Fields a style may change the case of, so capitalized words in them are
+/// wrapped in braces.
+const TITLE_FIELDS: &[&str] = &[
+    "booksubtitle",
+    "booktitle",
+    "booktitleaddon",
+    "eventtitle",
+    "mainsubtitle",
+    "maintitle",
+    "maintitleaddon",
+    "origtitle",
+    "shorttitle",
+    "subtitle",
+    "title",
+    "titleaddon",
+];
+
 fn write_field(
     out: &mut String,
     key: &str,
-    chunks: &[biblatex::Spanned<biblatex::Chunk>],
+    mut value: String,
     indent: &str,
+    width: usize,
+    last: bool,
+    config: &FormatConfig,
 ) {
-    let mut s = String::new();
+    let comma = if last && !config.trailing_comma {
+        ""
+    } else {
+        ","
+    };
+
+    if config.wrap_width > 0 && !VERBATIM_FIELDS.contains(&key.to_lowercase().as_str()) {
+        // Continuation lines start under the first character of the value
+        let name_width = width.max(key.chars().count()) + 3;
+        let first_col = display_width(indent) + name_width;
+        let continuation = format!("{}{}", indent, " ".repeat(name_width + 1));
+        value = wrap_value(
+            &value,
+            first_col,
+            &continuation,
+            config.wrap_width,
+            comma.len(),
+        );
+    }
+
+    // indent key = {value},
+    let _ = writeln!(
+        out,
+        "{}{:<width$} = {}{}",
+        indent,
+        config.field_case.apply(key),
+        value,
+        comma,
+        width = width
+    );
+}
+
+/// Serializes a field value with its delimiters, so that parsing it again
+/// gives back the same text: `Verbatim` chunks keep their protecting
+/// braces, `Math` keeps its `$...$`, and special characters are escaped.
+pub fn format_value(
+    key: &str,
+    chunks: &[biblatex::Spanned<biblatex::Chunk>],
+    config: &FormatConfig,
+) -> String {
+    let key = key.to_lowercase();
+
+    if config.bare_numbers && BARE_NUMBER_FIELDS.contains(&key.as_str()) {
+        if let [chunk] = chunks {
+            if let Chunk::Normal(t) = &chunk.v {
+                if !t.is_empty() && t.chars().all(|c| c.is_ascii_digit()) {
+                    return t.clone();
+                }
+            }
+        }
+    }
+
+    let content = if VERBATIM_FIELDS.contains(&key.as_str()) {
+        let raw: String = chunks.iter().map(|c| chunk_text(&c.v)).collect();
+        escape(&raw, true)
+    } else {
+        format_chunks(&key, chunks, config)
+    };
+
+    let quoted = config.delimiter == Delimiter::Quotes
+        || config
+            .quoted_fields
+            .iter()
+            .any(|f| f.eq_ignore_ascii_case(&key));
+    // The parser does not skip braced quotes inside a quoted value, so
+    // anything containing `"` keeps braces
+    if quoted && !content.contains('"') {
+        format!("\"{}\"", content)
+    } else {
+        format!("{{{}}}", content)
+    }
+}
+
+fn format_chunks(
+    key: &str,
+    chunks: &[biblatex::Spanned<biblatex::Chunk>],
+    config: &FormatConfig,
+) -> String {
+    let protect = config.protect_capitals && TITLE_FIELDS.contains(&key);
+    let join = config.unwrap_lines || config.wrap_width > 0;
+    let mut out = String::new();
     for chunk in chunks {
+        let mut t = if join {
+            unwrap(chunk_text(&chunk.v))
+        } else {
+            chunk_text(&chunk.v).to_string()
+        };
+        if config.encoding == TextEncoding::Unicode && !matches!(chunk.v, Chunk::Math(_)) {
+            t = latex::to_unicode(&t);
+        }
+        let t = &t;
+        // LaTeX spellings are added after escaping, so their backslashes,
+        // braces and `$` are written as they are
+        let encode = |text: String| match config.encoding {
+            TextEncoding::Latex => latex::to_latex(&text),
+            _ => text,
+        };
         match &chunk.v {
-            biblatex::Chunk::Normal(t) => s.push_str(t),
-            biblatex::Chunk::Verbatim(t) => s.push_str(t),
-            biblatex::Chunk::Math(t) => s.push_str(t),
+            Chunk::Normal(_) if protect => out.push_str(&encode(protect_capitals(t))),
+            Chunk::Normal(_) => out.push_str(&encode(escape(t, false))),
+            Chunk::Verbatim(_) => {
+                out.push('{');
+                out.push_str(&encode(escape(t, false)));
+                out.push('}');
+            }
+            Chunk::Math(_) => {
+                out.push('$');
+                // Math is TeX source; only unbalanced braces would break the file
+                if braces_balanced(t) {
+                    out.push_str(t);
+                } else {
+                    out.push_str(&t.replace('{', "\\{").replace('}', "\\}"));
+                }
+                out.push('$');
+            }
+        }
+    }
+    out
+}
+
+/// Joins a value that was split over several lines: every run of
+/// whitespace containing a line break becomes a single space.
+pub fn unwrap(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut run = String::new();
+    for c in text.chars() {
+        if c.is_whitespace() {
+            run.push(c);
+            continue;
+        }
+        flush_whitespace(&mut run, &mut out);
+        out.push(c);
+    }
+    flush_whitespace(&mut run, &mut out);
+    out
+}
+
+fn flush_whitespace(run: &mut String, out: &mut String) {
+    if run.contains('\n') {
+        out.push(' ');
+    } else {
+        out.push_str(run);
+    }
+    run.clear();
+}
+
+/// Breaks a delimited value at spaces so no line passes `width` columns.
+/// The value starts at column `first_col`; later lines start with
+/// `continuation`. Spaces inside brace groups and math are never used, so
+/// `{New York}`, `\url{...}` and `$a + b$` stay on one line. `tail` is the
+/// width of what follows the value on its last line (the comma).
+fn wrap_value(
+    value: &str,
+    first_col: usize,
+    continuation: &str,
+    width: usize,
+    tail: usize,
+) -> String {
+    let words = split_at_breaks(value);
+    if words.len() < 2 {
+        return value.to_string();
+    }
+
+    let cont_col = display_width(continuation);
+    let mut out = String::with_capacity(value.len() + 16);
+    let mut col = first_col;
+    for (i, word) in words.iter().enumerate() {
+        let len = word.chars().count();
+        let needed = if i + 1 == words.len() {
+            len + tail
+        } else {
+            len
+        };
+        if i > 0 {
+            if col + 1 + needed > width {
+                out.push('\n');
+                out.push_str(continuation);
+                col = cont_col;
+            } else {
+                out.push(' ');
+                col += 1;
+            }
+        }
+        out.push_str(word);
+        col += len;
+    }
+    out
+}
+
+/// Splits a delimited value at the spaces that are safe to turn into line
+/// breaks: outside brace groups, outside math, and not escaped.
+fn split_at_breaks(value: &str) -> Vec<&str> {
+    // The opening brace of a {value} does not count as a group
+    let mut depth: i32 = if value.starts_with('{') { -1 } else { 0 };
+    let mut math = false;
+    let mut escaped = false;
+    let mut words = Vec::new();
+    let mut start = 0;
+
+    for (i, c) in value.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '$' if depth == 0 => math = !math,
+            ' ' if depth == 0 && !math => {
+                if start < i {
+                    words.push(&value[start..i]);
+                }
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if start < value.len() {
+        words.push(&value[start..]);
+    }
+    words
+}
+
+/// Columns taken by an indent, counting a tab as eight.
+fn display_width(text: &str) -> usize {
+    text.chars().map(|c| if c == '\t' { 8 } else { 1 }).sum()
+}
+
+fn chunk_text(chunk: &Chunk) -> &str {
+    match chunk {
+        Chunk::Normal(t) | Chunk::Verbatim(t) | Chunk::Math(t) => t,
+    }
+}
+
+/// Backslash-escapes characters that would otherwise end the value, start
+/// a comment or a macro, or switch to math mode. Outside verbatim fields,
+/// TeX commands the parser kept in the text (`\textit{x}`, `\ldots{}`) are
+/// written back as they are, with their braces if those balance.
+fn escape(text: &str, verbatim: bool) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let raw_braces = !verbatim && has_command(&chars) && braces_balanced(text);
+    let mut out = String::with_capacity(text.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if !verbatim {
+            if let Some(len) = command_len(&chars[i..]) {
+                out.extend(&chars[i..i + len]);
+                i += len;
+                continue;
+            }
+        }
+        push_escaped(&mut out, chars[i], verbatim, raw_braces);
+        i += 1;
+    }
+    out
+}
+
+fn push_escaped(out: &mut String, c: char, verbatim: bool, raw_braces: bool) {
+    // `\\` would be a line break
+    if c == '\\' && !verbatim {
+        out.push_str("\\textbackslash{}");
+        return;
+    }
+    let special = match c {
+        '{' | '}' => !raw_braces,
+        '#' | '&' | '%' | '$' | '_' => !verbatim,
+        _ => false,
+    };
+    if special {
+        out.push('\\');
+    }
+    out.push(c);
+}
+
+/// Length of the TeX command at the start of `chars`: a backslash and a
+/// name (`\emph`) or a control symbol (`\'`, `\,`, `\-`). A backslash
+/// before a character that `push_escaped` escapes is not a command.
+fn command_len(chars: &[char]) -> Option<usize> {
+    if chars.first() != Some(&'\\') {
+        return None;
+    }
+    match chars.get(1) {
+        Some(c) if c.is_ascii_alphabetic() => Some(
+            1 + chars[1..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphabetic())
+                .count(),
+        ),
+        Some('{' | '}' | '#' | '&' | '%' | '$' | '_' | '\\') | None => None,
+        Some(_) => Some(2),
+    }
+}
+
+fn has_command(chars: &[char]) -> bool {
+    (0..chars.len()).any(|i| command_len(&chars[i..]).is_some())
+}
+
+/// Escapes `text` and wraps words with more than one capital, or a capital
+/// after the first letter ("DNA", "mRNA", "LaTeX"), in braces so styles
+/// that change case leave them alone. Words inside a command's braces are
+/// left as they are.
+fn protect_capitals(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let raw_braces = has_command(&chars) && braces_balanced(text);
+    let mut out = String::with_capacity(text.len() + 8);
+    let mut word = String::new();
+    let mut depth = 0;
+
+    let flush = |word: &mut String, out: &mut String, depth: i32| {
+        if depth == 0 && needs_protection(word) {
+            out.push('{');
+            out.push_str(word);
+            out.push('}');
+        } else {
+            out.push_str(word);
+        }
+        word.clear();
+    };
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_alphanumeric() {
+            word.push(c);
+            i += 1;
+            continue;
+        }
+        flush(&mut word, &mut out, depth);
+        if let Some(len) = command_len(&chars[i..]) {
+            out.extend(&chars[i..i + len]);
+            i += len;
+            continue;
+        }
+        if raw_braces {
+            match c {
+                '{' => depth += 1,
+                '}' => depth -= 1,
+                _ => {}
+            }
+        }
+        push_escaped(&mut out, c, false, raw_braces);
+        i += 1;
+    }
+    flush(&mut word, &mut out, depth);
+    out
+}
+
+fn needs_protection(word: &str) -> bool {
+    word.chars().skip(1).any(char::is_uppercase)
+}
+
+fn braces_balanced(text: &str) -> bool {
+    let mut depth = 0i32;
+    let mut escaped = false;
+    for c in text.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth < 0 {
+                    return false;
+                }
+            }
+            _ => {}
+        }
+    }
+    depth == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use biblatex::Bibliography;
+
+    /// Field values after loading `source`, saving it and loading it again.
+    fn round_trip(source: &str) -> (Vec<String>, Vec<String>) {
+        let values = |bib: &Bibliography| -> Vec<String> {
+            bib.iter()
+                .next()
+                .unwrap()
+                .fields
+                .values()
+                .map(|v| crate::core::bib_to_string(v))
+                .collect()
+        };
+        let loaded = Bibliography::parse(source).unwrap();
+        let written = format_entry(
+            loaded.iter().next().unwrap(),
+            &Definitions::default(),
+            &FormatConfig::default(),
+        );
+        let reloaded =
+            Bibliography::parse(&written).unwrap_or_else(|e| panic!("{:?} in\n{}", e, written));
+        (values(&loaded), values(&reloaded))
+    }
+
+    #[test]
+    fn escapes_what_would_end_or_change_the_value() {
+        assert_eq!(
+            escape("A & B 50% $x$ #1 a_b", false),
+            r"A \& B 50\% \$x\$ \#1 a\_b"
+        );
+        assert_eq!(escape("{ and }", false), r"\{ and \}");
+    }
+
+    #[test]
+    fn a_backslash_is_not_a_line_break() {
+        assert_eq!(escape("dir\\", false), r"dir\textbackslash{}");
+        assert_eq!(escape(r"50\%", false), r"50\textbackslash{}\%");
+    }
+
+    #[test]
+    fn commands_are_written_back_as_they_are() {
+        assert_eq!(
+            escape(r"\textit{Deep} learning & more", false),
+            r"\textit{Deep} learning \& more"
+        );
+        assert_eq!(escape(r"Caf\'e", false), r"Caf\'e");
+    }
+
+    #[test]
+    fn control_symbols_are_kept() {
+        assert_eq!(escape(r"10\,000 years", false), r"10\,000 years");
+        assert_eq!(escape(r"pre\-fix\/ \@ a\ b", false), r"pre\-fix\/ \@ a\ b");
+
+        let bib = Bibliography::parse(r"@misc{a, title = {10\,000 years}}").unwrap();
+        let written = format_entry(
+            bib.iter().next().unwrap(),
+            &Definitions::default(),
+            &FormatConfig::default(),
+        );
+        assert!(written.contains(r"title = {10\,000 years}"), "{}", written);
+    }
+
+    #[test]
+    fn verbatim_fields_keep_backslashes_and_specials() {
+        assert_eq!(
+            escape(r"C:\dir\file_1.pdf#page=2", true),
+            r"C:\dir\file_1.pdf#page=2"
+        );
+    }
+
+    #[test]
+    fn values_survive_a_round_trip() {
+        for source in [
+            r"@misc{a, title = {dir\textbackslash{}}}",
+            r"@misc{a, title = {10\,000 years \-- and\/ more}}",
+            r"@misc{a, note = {A \& B \{ \% \_ \$ \#}}",
+            r"@misc{a, title = {\textit{Deep} learning and $x^2$}}",
+            r"@misc{a, url = {C:\dir\f}, doi = {10.1000/a_b%20c}}",
+        ] {
+            let (loaded, reloaded) = round_trip(source);
+            assert_eq!(loaded, reloaded, "{}", source);
         }
     }
-    // indent key = {value},
-    let _ = writeln!(out, "{}{} = {{{}}},", indent, key, s);
 }
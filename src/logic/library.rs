@@ -4,41 +4,185 @@
 use crate::app::alert::AlertMsg;
 use crate::app::{AppModel, AppMsg};
 use crate::core;
+use crate::core::config::Dialect;
+use crate::core::keygen::{self, KeyGenerator, SuffixStyle};
+use crate::core::latex::{self, TextEncoding};
 use crate::logic::abbreviator;
+use crate::logic::dialect;
+use crate::logic::formatter::VERBATIM_FIELDS;
+use crate::logic::loader::Resolution;
+use crate::logic::sorter;
+use crate::logic::strings::Definitions;
 use crate::ui::details_dialog::DetailsDialogMsg;
 use crate::ui::row::{BibEntry, BibEntryOutput};
 use crate::ui::sidebar::SidebarMsg;
 use biblatex::{Bibliography, Chunk, Spanned};
 use relm4::{ComponentController, ComponentSender};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 
 // ----------------------------------------------------------------------------
 // 1. Helpers
 // ----------------------------------------------------------------------------
 
-fn ensure_unique(base_key: &str, bib: &Bibliography) -> String {
-    if bib.get(base_key).is_none() {
-        return base_key.to_string();
+/// Where `newcomer` goes among the entries keyed after the same base: its
+/// key or that key plus a suffix. The unlocked ones and the newcomer are
+/// suffixed again in collision order, as with Regenerate Keys, so the
+/// suffixes don't depend on which entry came first. Returns the key of the
+/// newcomer and the renames of the others.
+fn join_group(
+    entries: &[&biblatex::Entry],
+    newcomer: &biblatex::Entry,
+    style: SuffixStyle,
+) -> (String, Vec<(String, String)>) {
+    let base = newcomer.key.as_str();
+    let held = entries.iter().any(|entry| entry.key == base);
+    if !held && keygen::is_key_locked(newcomer) {
+        return (base.to_string(), Vec::new());
     }
-    let mut suffix_char = 'a';
-    loop {
-        let candidate = format!("{}{}", base_key, suffix_char);
-        if bib.get(&candidate).is_none() {
-            return candidate;
+
+    let mut taken = HashSet::new();
+    let mut members = Vec::new();
+
+    for entry in entries {
+        if !matches_generated(&entry.key, base, style) {
+            continue;
+        }
+        if keygen::is_key_locked(entry) {
+            taken.insert(entry.key.clone());
+        } else {
+            members.push(*entry);
+        }
+    }
+    if members.is_empty() && taken.is_empty() {
+        return (base.to_string(), Vec::new());
+    }
+
+    members.push(newcomer);
+    let mut order: Vec<usize> = (0..members.len()).collect();
+    order.sort_by_cached_key(|&i| collision_order(members[i]));
+
+    let mut key = String::new();
+    let mut renames = Vec::new();
+    let mut suffixes = (0..).map(|n| format!("{}{}", base, style.suffix(n)));
+    for i in order {
+        let new_key = suffixes
+            .by_ref()
+            .find(|candidate| !taken.contains(candidate))
+            .unwrap_or_default();
+        if i == members.len() - 1 {
+            key = new_key;
+        } else if new_key != members[i].key {
+            renames.push((members[i].key.clone(), new_key));
+        }
+    }
+
+    (key, renames)
+}
+
+/// A free key for a new or edited entry, renaming the entries it collides
+/// with as `join_group` decides.
+fn claim_key(model: &mut AppModel, entry: &biblatex::Entry) -> String {
+    let style = model.config.keygen.suffix_style;
+    let entries: Vec<&biblatex::Entry> = model.bibliography.iter().collect();
+    let (key, renames) = join_group(&entries, entry, style);
+    if renames.is_empty() {
+        return key;
+    }
+
+    // Rebuilt so the entries keep their place in the library
+    let positions: Vec<Option<usize>> = renames
+        .iter()
+        .map(|(old, _)| model.entries.iter().position(|row| row.key == *old))
+        .collect();
+    let mut bib = Bibliography::new();
+    for entry in model.bibliography.iter() {
+        let mut entry = entry.clone();
+        if let Some((old, new)) = renames.iter().find(|(old, _)| *old == entry.key) {
+            entry.key = new.clone();
+            record_old_key(&mut entry, old);
         }
-        if suffix_char == 'z' {
-            break;
+        bib.insert(entry);
+    }
+    model.bibliography = bib;
+
+    for ((_, new), idx) in renames.iter().zip(positions) {
+        if let (Some(idx), Some(entry)) = (idx, model.bibliography.get(new)) {
+            let mut rows = model.entries.guard();
+            rows.remove(idx);
+            rows.insert(idx, BibEntry::from_entry(entry));
         }
-        suffix_char = (suffix_char as u8 + 1) as char;
     }
-    let mut i = 1;
-    loop {
-        let candidate = format!("{}_{}", base_key, i);
-        if bib.get(&candidate).is_none() {
-            return candidate;
+    model.record_key_renames(&renames);
+
+    key
+}
+
+/// True if `key` is `generated` or `generated` plus a collision suffix
+/// ("Smith2020b", "Smith2020_3").
+fn matches_generated(key: &str, generated: &str, style: SuffixStyle) -> bool {
+    match key.strip_prefix(generated) {
+        Some("") => true,
+        Some(rest) => style.is_suffix(rest),
+        None => false,
+    }
+}
+
+/// Sort key for entries sharing a generated key: date, then title, then
+/// the original key. Entries without a date go last.
+fn collision_order(entry: &biblatex::Entry) -> (bool, String, String, String) {
+    let field = |name: &str| {
+        entry
+            .fields
+            .get(name)
+            .map(|v| core::bib_to_string(v).trim().to_string())
+            .unwrap_or_default()
+    };
+
+    let date = match field("date") {
+        d if !d.is_empty() => d,
+        _ => {
+            let year = field("year");
+            let month = dialect::month_number(&field("month"));
+            if year.is_empty() {
+                String::new()
+            } else {
+                format!("{}-{:02}", year, month)
+            }
         }
-        i += 1;
+    };
+
+    (
+        date.is_empty(),
+        date,
+        field("title").to_lowercase(),
+        entry.key.clone(),
+    )
+}
+
+/// Appends the previous key to the biblatex `ids` field so documents
+/// that still cite it keep compiling.
+fn record_old_key(entry: &mut biblatex::Entry, old_key: &str) {
+    if old_key.is_empty() || old_key == entry.key {
+        return;
     }
+
+    let mut ids: Vec<String> = entry
+        .fields
+        .get("ids")
+        .map(|v| core::bib_to_string(v))
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty() && *s != entry.key)
+        .collect();
+
+    if !ids.iter().any(|id| id == old_key) {
+        ids.push(old_key.to_string());
+    }
+
+    entry
+        .fields
+        .insert("ids".into(), make_normal_chunk(&ids.join(", ")));
 }
 
 fn make_normal_chunk(s: &str) -> Vec<Spanned<Chunk>> {
@@ -116,11 +260,41 @@ fn sanitize_entry_fields(entry: &mut biblatex::Entry) {
     }
 }
 
-// Helper to refresh UI without repeating code
-fn refresh_ui_list(model: &mut AppModel) {
-    model.entries.guard().clear();
+/// Converts the text of every field to `encoding`. Verbatim fields (url,
+/// doi, file) and fields saved as a @string reference are skipped.
+/// Returns true if anything changed.
+fn convert_entry_text(
+    entry: &mut biblatex::Entry,
+    encoding: TextEncoding,
+    defs: &Definitions,
+) -> bool {
+    let mut changed = false;
+    for (field, chunks) in entry.fields.iter_mut() {
+        if VERBATIM_FIELDS.contains(&field.to_lowercase().as_str())
+            || defs.raw_value(&entry.key, field, chunks).is_some()
+        {
+            continue;
+        }
+        if let Some(converted) = latex::convert_chunks(chunks, encoding) {
+            *chunks = converted;
+            changed = true;
+        }
+    }
+    changed
+}
+
+// Helper to refresh UI without repeating code. Text of the file that
+// could not be read as entries comes first, marked as errors.
+pub fn refresh_ui_list(model: &mut AppModel) {
+    let mut rows = model.entries.guard();
+    rows.clear();
+    for block in &model.definitions.malformed {
+        if block.is_open(&model.bibliography) {
+            rows.push_back(BibEntry::from_malformed(block));
+        }
+    }
     for entry in model.bibliography.iter() {
-        model.entries.guard().push_back(BibEntry::from_entry(entry));
+        rows.push_back(BibEntry::from_entry(entry));
     }
 }
 
@@ -136,7 +310,7 @@ pub fn add_entry(model: &mut AppModel, mut entry: biblatex::Entry) {
     sanitize_entry_fields(&mut entry);
 
     // 3. Logic: Abbreviate on add if configured
-    if model.key_config.abbreviate_journals {
+    if model.config.import.abbreviate_journals {
         if let Some(chunk_val) = entry.fields.get("journal") {
             let original = core::bib_to_string(chunk_val);
             let abbr = abbreviator::abbreviate_journal(&original);
@@ -158,23 +332,59 @@ pub fn add_entry(model: &mut AppModel, mut entry: biblatex::Entry) {
         }
     }
 
+    // 3b. Logic: Convert to Unicode / LaTeX if configured
+    if model.config.import.encoding != TextEncoding::Keep {
+        convert_entry_text(
+            &mut entry,
+            model.config.import.encoding,
+            &Definitions::default(),
+        );
+    }
+
+    // 3c. Logic: Note when the entry came in, for sorting by date added
+    if model.config.import.record_date_added
+        && !sorter::DATE_ADDED_FIELDS
+            .iter()
+            .any(|name| entry.fields.contains_key(*name))
+    {
+        entry.fields.insert(
+            "dateadded".into(),
+            make_normal_chunk(&core::backup::today()),
+        );
+    }
+
     // 4. Generate Key
     if entry.key.is_empty() {
-        entry.key = core::keygen::generate_key(&entry, &model.key_config);
+        entry.key = core::keygen::generate_key(&entry, &model.config.keygen);
     }
 
     // 5. Ensure Uniqueness
-    let unique_key = ensure_unique(&entry.key, &model.bibliography);
+    let unique_key = claim_key(model, &entry);
     entry.key = unique_key.clone();
 
     // 6. Insert
     model.bibliography.insert(entry.clone());
 
-    // 7. Update UI
+    // 7. Update UI: newest first, or where saving will put it
+    let index = if model.config.formatting.sort_on_save {
+        let order = &model.config.formatting.sort_order;
+        let bib = &model.bibliography;
+        model
+            .entries
+            .iter()
+            .position(|row| {
+                bib.get(&row.key).map_or(false, |other| {
+                    sorter::compare(&entry, other, order) == std::cmp::Ordering::Less
+                })
+            })
+            .unwrap_or(model.entries.len())
+    } else {
+        0
+    };
     model
         .entries
         .guard()
-        .push_front(BibEntry::from_entry(&entry));
+        .insert(index, BibEntry::from_entry(&entry));
     model.is_dirty = true;
     model.sidebar.emit(SidebarMsg::SetStatus(format!(
         "Added entry: {}",
@@ -185,11 +395,31 @@ pub fn add_entry(model: &mut AppModel, mut entry: biblatex::Entry) {
 pub fn handle_row_output(model: &mut AppModel, output: BibEntryOutput) {
     match output {
         BibEntryOutput::Delete(key) => {
+            // Not part of the library, so not part of undo either
+            if let Some(block) = model.definitions.malformed_mut(&key) {
+                block.resolution = Some(Resolution::Deleted);
+                if let Some(idx) = model.entries.iter().position(|e| e.key == key) {
+                    model.entries.guard().remove(idx);
+                }
+                model.is_dirty = true;
+                model.sidebar.emit(SidebarMsg::SetStatus(format!(
+                    "Deleted unreadable entry: {}. It is removed from the file when you save.",
+                    key
+                )));
+                return;
+            }
+
             // 1. SAVE STATE
             model.push_snapshot();
 
             // 2. Remove from Data
             model.bibliography.remove(&key);
+            // Made from unreadable text: the text goes with it
+            for block in &mut model.definitions.malformed {
+                if block.resolution == Some(Resolution::Fixed(key.clone())) {
+                    block.resolution = Some(Resolution::Deleted);
+                }
+            }
 
             // 3. Remove from UI (Find index first to avoid borrow error)
             let index_to_remove = model.entries.iter().position(|e| e.key == key);
@@ -202,9 +432,42 @@ pub fn handle_row_output(model: &mut AppModel, output: BibEntryOutput) {
                 .sidebar
                 .emit(SidebarMsg::SetStatus(format!("Deleted entry: {}", key)));
         }
+        BibEntryOutput::ToggleLock(key) => {
+            let Some(was_locked) = model.bibliography.get(&key).map(keygen::is_key_locked) else {
+                return;
+            };
+            model.push_snapshot();
+
+            let locked = !was_locked;
+            if let Some(entry) = model.bibliography.get_mut(&key) {
+                keygen::set_key_locked(entry, locked);
+            }
+
+            // Rebuild just this row
+            if let Some(idx) = model.entries.iter().position(|e| e.key == key) {
+                if let Some(entry) = model.bibliography.get(&key) {
+                    let mut guard = model.entries.guard();
+                    guard.remove(idx);
+                    guard.insert(idx, BibEntry::from_entry(entry));
+                }
+            }
+
+            model.is_dirty = true;
+            model.sidebar.emit(SidebarMsg::SetStatus(format!(
+                "{} key: {}",
+                if locked { "Locked" } else { "Unlocked" },
+                key
+            )));
+        }
         BibEntryOutput::Select(key) => {
             // Selection doesn't change state, so no snapshot needed
-            if let Some(entry) = model.bibliography.get(&key) {
+            if let Some(block) = model.definitions.malformed(&key) {
+                // Saving it from the editor once it reads fixes it
+                let content = block.annotated();
+                model
+                    .details_dialog
+                    .emit(DetailsDialogMsg::Open(key, content));
+            } else if let Some(entry) = model.bibliography.get(&key) {
                 let content = entry
                     .to_bibtex_string()
                     .unwrap_or_else(|e| format!("% Error generating BibTeX: {}", e));
@@ -231,6 +494,9 @@ pub fn finish_edit(
     let parsed = Bibliography::parse(&content);
 
     match parsed {
+        Ok(bib) if !bib.is_empty() && model.definitions.malformed(&old_key).is_some() => {
+            fix_malformed(model, &old_key, bib);
+        }
         Ok(bib) => {
             if let Some(mut new_entry) = bib.iter().next() {
                 // ✅ FIX: Take a Snapshot BEFORE applying changes
@@ -242,12 +508,14 @@ pub fn finish_edit(
                 model.bibliography.remove(&old_key);
 
                 // 3. Insert new entry (handle key change automatically)
-                let final_key = ensure_unique(&new_entry.key, &model.bibliography);
+                let final_key = claim_key(model, new_entry);
 
                 let mut entry_to_insert = new_entry.clone();
                 entry_to_insert.key = final_key.clone();
+                record_old_key(&mut entry_to_insert, &old_key);
 
                 model.bibliography.insert(entry_to_insert);
+                model.record_key_renames(&[(old_key.clone(), final_key.clone())]);
 
                 // 4. Update UI
                 refresh_ui_list(model);
@@ -270,35 +538,163 @@ pub fn finish_edit(
     }
 }
 
+/// Adds the entries the user made of text that could not be read. Saving
+/// writes the first where the text was, and the others at the end.
+fn fix_malformed(model: &mut AppModel, label: &str, bib: Bibliography) {
+    model.push_snapshot();
+
+    let mut keys = Vec::new();
+    for entry in bib.iter() {
+        let mut entry = entry.clone();
+        entry.key = claim_key(model, &entry);
+        keys.push(entry.key.clone());
+        model.bibliography.insert(entry);
+    }
+    if let Some(block) = model.definitions.malformed_mut(label) {
+        block.resolution = Some(Resolution::Fixed(keys[0].clone()));
+    }
+
+    refresh_ui_list(model);
+    model.sidebar.emit(SidebarMsg::SetStatus(format!(
+        "Saved entry: {}",
+        keys.join(", ")
+    )));
+    model.is_dirty = true;
+}
+
 // ----------------------------------------------------------------------------
 // 4. Batch Operations
 // ----------------------------------------------------------------------------
 
-pub fn regenerate_keys(model: &mut AppModel, _sender: ComponentSender<AppModel>) {
-    model.push_snapshot();
+/// Which entries `regenerate_keys` may touch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RegenScope {
+    /// Every entry that is not locked.
+    All,
+    /// Only unlocked entries whose key doesn't follow the current pattern.
+    Mismatched,
+}
+
+/// The new key of each of `entries` (None: it keeps its key) and the
+/// number of locked entries.
+fn assign_keys(
+    entries: &[&biblatex::Entry],
+    generator: &KeyGenerator,
+    scope: RegenScope,
+    style: SuffixStyle,
+) -> (Vec<Option<String>>, usize) {
+    // 1. Decide which entries keep their key. Kept keys are reserved
+    //    first, so regenerated keys can never collide with them.
+    let mut locked = 0;
+    let mut groups: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    let mut taken = HashSet::new();
 
+    for (i, entry) in entries.iter().enumerate() {
+        let generated = generator.generate(entry);
+        let keep = if keygen::is_key_locked(entry) {
+            locked += 1;
+            true
+        } else {
+            scope == RegenScope::Mismatched && matches_generated(&entry.key, &generated, style)
+        };
+
+        if keep {
+            taken.insert(entry.key.clone());
+        } else {
+            groups.entry(generated).or_default().push(i);
+        }
+    }
+
+    // 2. Assign keys per generated base. Colliding entries are suffixed
+    //    in a stable order, so "Smith2020a" stays the same paper across
+    //    sessions regardless of load order, and the first one gets "a" too.
+    let mut assigned: Vec<Option<String>> = vec![None; entries.len()];
+
+    for (base, mut members) in groups {
+        // A kept "Smith2020b" makes this a group: no bare "Smith2020" beside it
+        let kept_sibling = taken.iter().any(|key| matches_generated(key, &base, style));
+        if members.len() == 1 && !kept_sibling {
+            taken.insert(base.clone());
+            assigned[members[0]] = Some(base);
+            continue;
+        }
+
+        members.sort_by_cached_key(|&i| collision_order(entries[i]));
+        let mut suffixes = (0..).map(|n| format!("{}{}", base, style.suffix(n)));
+        for i in members {
+            let key = suffixes
+                .by_ref()
+                .find(|candidate| !taken.contains(candidate))
+                .unwrap_or_default();
+            taken.insert(key.clone());
+            assigned[i] = Some(key);
+        }
+    }
+
+    (assigned, locked)
+}
+
+pub fn regenerate_keys(
+    model: &mut AppModel,
+    scope: RegenScope,
+    _sender: ComponentSender<AppModel>,
+) {
+    // Validate the pattern up front: a typo should not silently
+    // rewrite every key with the fallback parts.
+    let generator = match KeyGenerator::new(&model.config.keygen) {
+        Ok(g) => g,
+        Err(e) => {
+            model
+                .alert
+                .emit(AlertMsg::Show(format!("Invalid key pattern:\n{}", e)));
+            return;
+        }
+    };
+
+    let entries: Vec<&biblatex::Entry> = model.bibliography.iter().collect();
+    let (assigned, locked) = assign_keys(
+        &entries,
+        &generator,
+        scope,
+        model.config.keygen.suffix_style,
+    );
+
+    // 3. Rebuild the library in its original order
     let mut new_bib = Bibliography::new();
-    let config = &model.key_config;
-    let mut count = 0;
+    let mut renames = Vec::new();
 
-    for entry in model.bibliography.iter() {
-        let mut new_entry = entry.clone();
-        let new_key = crate::core::keygen::generate_key(&new_entry, config);
+    for (entry, new_key) in entries.iter().zip(assigned) {
+        let mut new_entry = (*entry).clone();
 
-        let unique_key = ensure_unique(&new_key, &new_bib);
-        new_entry.key = unique_key;
+        if let Some(new_key) = new_key {
+            if new_key != entry.key {
+                renames.push((entry.key.clone(), new_key.clone()));
+                new_entry.key = new_key;
+                record_old_key(&mut new_entry, &entry.key);
+            }
+        }
 
         new_bib.insert(new_entry);
-        count += 1;
     }
 
+    if renames.is_empty() {
+        model.sidebar.emit(SidebarMsg::SetStatus(format!(
+            "All keys are up to date ({} locked).",
+            locked
+        )));
+        return;
+    }
+
+    model.push_snapshot();
     model.bibliography = new_bib;
     refresh_ui_list(model);
+    model.record_key_renames(&renames);
 
     model.is_dirty = true;
     model.sidebar.emit(SidebarMsg::SetStatus(format!(
-        "Regenerated {} keys.",
-        count
+        "Regenerated {} keys ({} locked).",
+        renames.len(),
+        locked
     )));
 }
 
@@ -406,3 +802,262 @@ pub fn unabbreviate_all_entries(model: &mut AppModel) {
         ));
     }
 }
+
+/// Edit-menu batch action: spells the text of every entry as Unicode or as
+/// LaTeX. Math is left as it is.
+pub fn convert_all_entries(model: &mut AppModel, encoding: TextEncoding) {
+    model.push_snapshot();
+
+    let mut count = 0;
+    let keys: Vec<String> = model.bibliography.iter().map(|e| e.key.clone()).collect();
+
+    for key in keys {
+        if let Some(entry) = model.bibliography.get_mut(&key) {
+            if convert_entry_text(entry, encoding, &model.definitions) {
+                count += 1;
+            }
+        }
+    }
+
+    let target = match encoding {
+        TextEncoding::Latex => "LaTeX",
+        _ => "Unicode",
+    };
+    if count > 0 {
+        refresh_ui_list(model);
+        model.is_dirty = true;
+        model.sidebar.emit(SidebarMsg::SetStatus(format!(
+            "Converted {} entries to {}.",
+            count, target
+        )));
+    } else {
+        model.undo_stack.pop_back();
+        model.sidebar.emit(SidebarMsg::SetStatus(format!(
+            "Nothing to convert to {}.",
+            target
+        )));
+    }
+}
+
+/// Edit-menu batch action: renames fields and types of every entry to
+/// BibTeX or BibLaTeX.
+pub fn convert_dialect_all(model: &mut AppModel, target: Dialect) {
+    model.push_snapshot();
+
+    let mut count = 0;
+    let keys: Vec<String> = model.bibliography.iter().map(|e| e.key.clone()).collect();
+
+    for key in keys {
+        if let Some(entry) = model.bibliography.get_mut(&key) {
+            if dialect::convert_entry(entry, target) {
+                count += 1;
+            }
+        }
+    }
+
+    let name = match target {
+        Dialect::Biblatex => "BibLaTeX",
+        _ => "BibTeX",
+    };
+    if count > 0 {
+        refresh_ui_list(model);
+        model.is_dirty = true;
+        model.sidebar.emit(SidebarMsg::SetStatus(format!(
+            "Converted {} entries to {}.",
+            count, name
+        )));
+    } else {
+        model.undo_stack.pop_back();
+        model.sidebar.emit(SidebarMsg::SetStatus(format!(
+            "All entries are already {}.",
+            name
+        )));
+    }
+}
+
+/// Edit-menu action: puts the entries in the configured sort order. The
+/// next save writes them in this order too.
+pub fn sort_library(model: &mut AppModel) {
+    let order = model.config.formatting.sort_order.clone();
+    if order.is_empty() {
+        model.alert.emit(AlertMsg::ShowInfo(
+            "No sort order is set. Choose one under Preferences > Formatting.".into(),
+        ));
+        return;
+    }
+
+    model.push_snapshot();
+    if sorter::sort_library(&mut model.bibliography, &order) {
+        model.entry_ids.use_library_order();
+        refresh_ui_list(model);
+        model.is_dirty = true;
+        let criteria: Vec<&str> = order.iter().map(|key| key.label()).collect();
+        model.sidebar.emit(SidebarMsg::SetStatus(format!(
+            "Sorted {} entries by {}.",
+            model.bibliography.len(),
+            criteria.join(", then ").to_lowercase()
+        )));
+    } else {
+        model.undo_stack.pop_back();
+        model.sidebar.emit(SidebarMsg::SetStatus(
+            "The library is already in sort order.".into(),
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::keygen::KeyGenConfig;
+
+    fn generator() -> KeyGenerator {
+        KeyGenerator::new(&KeyGenConfig {
+            pattern: "[auth][year]".into(),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn regenerate(source: &str, scope: RegenScope) -> Vec<Option<String>> {
+        let bib = Bibliography::parse(source).unwrap();
+        let entries: Vec<&biblatex::Entry> = bib.iter().collect();
+        assign_keys(&entries, &generator(), scope, SuffixStyle::Letters).0
+    }
+
+    fn keys(assigned: &[Option<String>]) -> Vec<&str> {
+        assigned
+            .iter()
+            .map(|k| k.as_deref().unwrap_or("-"))
+            .collect()
+    }
+
+    fn join(source: &str, newcomer: &str) -> (String, Vec<(String, String)>) {
+        let bib = Bibliography::parse(source).unwrap();
+        let entries: Vec<&biblatex::Entry> = bib.iter().collect();
+        let newcomer = Bibliography::parse(newcomer).unwrap();
+        join_group(
+            &entries,
+            newcomer.iter().next().unwrap(),
+            SuffixStyle::Letters,
+        )
+    }
+
+    fn pair(old: &str, new: &str) -> (String, String) {
+        (old.to_string(), new.to_string())
+    }
+
+    #[test]
+    fn a_free_key_is_kept() {
+        let source = "@article{Doe2019, year = 2019}\n";
+        assert_eq!(
+            join(source, "@article{Smith2020, year = 2020}"),
+            ("Smith2020".to_string(), vec![])
+        );
+    }
+
+    #[test]
+    fn a_newcomer_is_suffixed_by_date_not_by_arrival() {
+        let source = "@article{Smith2020, year = 2020, month = mar}\n";
+        assert_eq!(
+            join(source, "@article{Smith2020, year = 2020, month = jan}"),
+            (
+                "Smith2020a".to_string(),
+                vec![pair("Smith2020", "Smith2020b")]
+            )
+        );
+        assert_eq!(
+            join(source, "@article{Smith2020, year = 2020, month = may}"),
+            (
+                "Smith2020b".to_string(),
+                vec![pair("Smith2020", "Smith2020a")]
+            )
+        );
+    }
+
+    #[test]
+    fn a_newcomer_joins_a_suffixed_group() {
+        let source = "@article{Smith2020a, year = 2020, month = jan}\n\
+                      @article{Smith2020b, year = 2020, month = may}\n";
+        assert_eq!(
+            join(source, "@article{Smith2020, year = 2020, month = mar}"),
+            (
+                "Smith2020b".to_string(),
+                vec![pair("Smith2020b", "Smith2020c")]
+            )
+        );
+    }
+
+    #[test]
+    fn locked_siblings_keep_their_keys() {
+        let source = "@article{Smith2020, year = 2020, month = may, keylock = {true}}\n";
+        assert_eq!(
+            join(source, "@article{Smith2020, year = 2020, month = jan}"),
+            ("Smith2020a".to_string(), vec![])
+        );
+    }
+
+    #[test]
+    fn only_generated_suffixes_match() {
+        let letters = SuffixStyle::Letters;
+        assert!(matches_generated("Smith2020", "Smith2020", letters));
+        assert!(matches_generated("Smith2020b", "Smith2020", letters));
+        assert!(matches_generated("Smith2020zz", "Smith2020", letters));
+        assert!(!matches_generated("Smith2020deep", "Smith2020", letters));
+        assert!(!matches_generated("Smith2020B", "Smith2020", letters));
+        assert!(!matches_generated("Doe2020a", "Smith2020", letters));
+
+        assert!(matches_generated(
+            "Smith2020_3",
+            "Smith2020",
+            SuffixStyle::Numbers
+        ));
+        assert!(!matches_generated(
+            "Smith2020_03",
+            "Smith2020",
+            SuffixStyle::Numbers
+        ));
+        assert!(!matches_generated(
+            "Smith2020_0",
+            "Smith2020",
+            SuffixStyle::Numbers
+        ));
+        assert!(matches_generated(
+            "Smith2020-12",
+            "Smith2020",
+            SuffixStyle::Dashed
+        ));
+    }
+
+    #[test]
+    fn collisions_are_suffixed_by_date_then_title() {
+        let source = "@article{x, author = {Smith, J.}, year = 2020, month = mar, title = {Alpha}}\n\
+                      @article{y, author = {Doe, A.}, year = 2019, title = {Other}}\n\
+                      @article{z, author = {Smith, J.}, year = 2020, month = jan, title = {Zeta}}\n\
+                      @article{w, author = {Smith, J.}, year = 2020, month = mar, title = {Beta}}\n";
+        assert_eq!(
+            keys(&regenerate(source, RegenScope::All)),
+            ["Smith2020b", "Doe2019", "Smith2020a", "Smith2020c"]
+        );
+    }
+
+    #[test]
+    fn locked_keys_are_kept_and_not_reused() {
+        let source = "@article{Smith2020a, author = {Smith, J.}, year = 2020, keylock = {true}}\n\
+                      @article{p, author = {Smith, J.}, year = 2020, title = {P}}\n\
+                      @article{q, author = {Smith, J.}, year = 2020, title = {Q}}\n";
+        assert_eq!(
+            keys(&regenerate(source, RegenScope::All)),
+            ["-", "Smith2020b", "Smith2020c"]
+        );
+    }
+
+    #[test]
+    fn mismatched_scope_keeps_keys_that_follow_the_pattern() {
+        let source = "@article{Smith2020b, author = {Smith, J.}, year = 2020}\n\
+                      @article{Smith2020deep, author = {Smith, J.}, year = 2020, title = {Deep}}\n";
+        assert_eq!(
+            keys(&regenerate(source, RegenScope::Mismatched)),
+            ["-", "Smith2020a"]
+        );
+    }
+}
@@ -0,0 +1,368 @@
+// src/logic/loader.rs
+//
+// Loading that survives malformed entries. A file the parser reads as a
+// whole loads as it always did. Otherwise the file is split into blocks
+// (see `scanner`) and read in batches, halved until the entries that fail
+// are on their own, so one bad brace costs one entry instead of the whole
+// library. Whatever still cannot be
+// read is kept as written: it is listed as an error row, and saving copies
+// it untouched until it is fixed.
+
+use crate::logic::scanner::{self, Block, Problem};
+use biblatex::{Bibliography, Entry};
+use std::collections::HashSet;
+
+/// Text that could not be read as an entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Malformed {
+    /// Stands in for the citation key in the list: "key (line N)", or
+    /// "line N" if no key could be read. Never a valid key.
+    pub label: String,
+    /// Lowercase entry type, if one could be read.
+    pub kind: String,
+    /// The text as written, from the `@`.
+    pub text: String,
+    /// Byte offset of `text` in the file it was loaded from.
+    pub start: usize,
+    /// What the parser could not read, and where in `text` (line, column).
+    pub error: String,
+    pub at: (usize, usize),
+    pub resolution: Option<Resolution>,
+}
+
+/// What became of malformed text since the file was loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Resolution {
+    /// Edited into the entry with this key; saving writes the entry in its
+    /// place. Undoing the edit makes the text malformed again.
+    Fixed(String),
+    /// Saving drops the text.
+    Deleted,
+}
+
+impl Malformed {
+    /// True while the text is still what the file will have: not fixed
+    /// into an entry of `bib`, nor deleted.
+    pub fn is_open(&self, bib: &Bibliography) -> bool {
+        match &self.resolution {
+            None => true,
+            Some(Resolution::Fixed(key)) => bib.get(key).is_none(),
+            Some(Resolution::Deleted) => false,
+        }
+    }
+
+    /// The text for the entry editor, after a comment saying what is wrong.
+    pub fn annotated(&self) -> String {
+        format!(
+            "% Could not be read: {} (line {}, column {} below).\n\
+             % Fix the entry and save it to add it to the library.\n\n{}",
+            self.error, self.at.0, self.at.1, self.text
+        )
+    }
+}
+
+/// A library as read from a file.
+#[derive(Debug)]
+pub struct Loaded {
+    pub bib: Bibliography,
+    /// In file order.
+    pub malformed: Vec<Malformed>,
+}
+
+/// Reads `content`, entry by entry if it does not parse as a whole.
+pub fn load(content: &str) -> Loaded {
+    if let Ok(bib) = Bibliography::parse(content) {
+        return Loaded {
+            bib,
+            malformed: Vec::new(),
+        };
+    }
+
+    let scan = scanner::scan(content);
+
+    // Macros are defined for the whole file, so every entry is parsed
+    // after all the definitions that parse
+    let string_blocks: Vec<&Block> = scan.blocks.iter().filter(|b| b.kind == "string").collect();
+    let mut strings = String::new();
+    read_strings(content, &mut strings, &string_blocks);
+
+    let entry_blocks: Vec<&Block> = scan
+        .blocks
+        .iter()
+        .filter(|b| !matches!(b.kind.as_str(), "string" | "preamble" | "comment"))
+        .collect();
+    let mut read = Read {
+        readable: strings.clone(),
+        ..Read::default()
+    };
+    read_entries(content, &strings, &entry_blocks, &mut read);
+    let Read {
+        readable,
+        entries,
+        mut found,
+        ..
+    } = read;
+
+    for problem in &scan.problems {
+        if let Problem::Unreadable {
+            reason, span, at, ..
+        } = problem
+        {
+            let text = content[span.clone()].trim_end();
+            found.push((
+                span.start,
+                malformed(content, span.start, text, reason, at - span.start),
+            ));
+        }
+    }
+    found.sort_by_key(|(start, _)| *start);
+
+    // Parsed again as a whole, so crossrefs between entries are resolved
+    let bib = Bibliography::parse(&readable).unwrap_or_else(|_| {
+        let mut bib = Bibliography::new();
+        for entry in entries {
+            bib.insert(entry);
+        }
+        bib
+    });
+
+    let mut labels = HashSet::new();
+    let malformed = found
+        .into_iter()
+        .map(|(_, mut m)| {
+            // Two on one line
+            let mut label = m.label.clone();
+            let mut n = 1;
+            while !labels.insert(label.clone()) {
+                n += 1;
+                label = format!("{} #{}", m.label, n);
+            }
+            m.label = label;
+            m
+        })
+        .collect();
+    Loaded { bib, malformed }
+}
+
+/// The blocks as one text, each on a line of its own.
+fn joined(content: &str, blocks: &[&Block]) -> String {
+    let mut text = String::new();
+    for block in blocks {
+        text.push_str(&content[block.start..block.end]);
+        text.push('\n');
+    }
+    text
+}
+
+/// Adds the @string blocks that parse to `strings`, each read after the
+/// ones before it. Blocks are tried in halves, so a file whose strings all
+/// read takes one parse, and each bad one a few more.
+fn read_strings(content: &str, strings: &mut String, blocks: &[&Block]) {
+    let text = joined(content, blocks);
+    if blocks.is_empty() || Bibliography::parse(&format!("{}{}", strings, text)).is_ok() {
+        strings.push_str(&text);
+        return;
+    }
+    if blocks.len() > 1 {
+        let (first, rest) = blocks.split_at(blocks.len() / 2);
+        read_strings(content, strings, first);
+        read_strings(content, strings, rest);
+    }
+}
+
+/// The entries read so far, and what could not be read.
+#[derive(Default)]
+struct Read {
+    /// The strings and the entry blocks that read, parsed again as a whole.
+    readable: String,
+    entries: Vec<Entry>,
+    keys: HashSet<String>,
+    found: Vec<(usize, Malformed)>,
+}
+
+/// Reads entry blocks after `strings`, like `read_strings`: a batch that
+/// parses with one new entry per block is taken whole, any other is split
+/// until the blocks that fail are on their own.
+fn read_entries(content: &str, strings: &str, blocks: &[&Block], read: &mut Read) {
+    if let [block] = blocks {
+        let text = &content[block.start..block.end];
+        match Bibliography::parse(&format!("{}{}", strings, text)) {
+            Ok(bib) => {
+                for entry in bib.iter() {
+                    // A second entry with a key is saved as written, like
+                    // the scanner reports it
+                    if read.keys.insert(entry.key.clone()) {
+                        read.readable.push_str(text);
+                        read.readable.push('\n');
+                        read.entries.push(entry.clone());
+                    }
+                }
+            }
+            Err(e) => {
+                let at = e.span.start.saturating_sub(strings.len());
+                read.found.push((
+                    block.start,
+                    malformed(content, block.start, text, &e.kind.to_string(), at),
+                ));
+            }
+        }
+        return;
+    }
+    if blocks.is_empty() {
+        return;
+    }
+
+    let text = joined(content, blocks);
+    if let Ok(bib) = Bibliography::parse(&format!("{}{}", strings, text)) {
+        let new = bib.iter().filter(|e| !read.keys.contains(&e.key)).count();
+        if bib.len() == blocks.len() && new == blocks.len() {
+            read.keys.extend(bib.iter().map(|e| e.key.clone()));
+            read.entries.extend(bib.iter().cloned());
+            read.readable.push_str(&text);
+            return;
+        }
+    }
+    let (first, rest) = blocks.split_at(blocks.len() / 2);
+    read_entries(content, strings, first, read);
+    read_entries(content, strings, rest, read);
+}
+
+fn malformed(content: &str, start: usize, text: &str, error: &str, at: usize) -> Malformed {
+    let (kind, key) = head(text);
+    let kind = if kind.is_empty() { "unreadable" } else { kind };
+    let line = position(content, start).0;
+    let label = if key.is_empty() {
+        format!("line {}", line)
+    } else {
+        format!("{} (line {})", key, line)
+    };
+    Malformed {
+        label,
+        kind: kind.to_lowercase(),
+        text: text.to_string(),
+        start,
+        error: error.to_string(),
+        at: position(text, at.min(text.len())),
+        resolution: None,
+    }
+}
+
+/// Entry type and citation key of `@type{key, ...`, as far as they read.
+fn head(text: &str) -> (&str, &str) {
+    let rest = text.strip_prefix('@').unwrap_or(text);
+    let Some(open) = rest.find(&['{', '('][..]) else {
+        return ("", "");
+    };
+    let kind = rest[..open].trim();
+    let key = rest[open + 1..]
+        .split(&[',', '}', ')', '\n'][..])
+        .next()
+        .unwrap_or("")
+        .trim();
+    if key.contains(char::is_whitespace) {
+        return (kind, "");
+    }
+    (kind, key)
+}
+
+/// 1-based line and column of byte `offset`.
+fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = text.get(..offset).unwrap_or(text);
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::FormatConfig;
+    use crate::logic::merger::{self, EntryIds};
+    use crate::logic::strings::Definitions;
+
+    const BROKEN: &str = "@string{j = {Journal}}\n\n\
+                          @article{a, title = {A}, journal = j}\n\n\
+                          @article{b, title = {B}, year = {2001}\n\n\
+                          @book{c, title = {C}}\n";
+
+    fn keys(bib: &Bibliography) -> Vec<&str> {
+        bib.iter().map(|e| e.key.as_str()).collect()
+    }
+
+    #[test]
+    fn a_readable_file_loads_as_a_whole() {
+        let loaded = load("@misc{a, note = {1}}\n@misc{b, note = {2}}\n");
+        assert_eq!(keys(&loaded.bib), ["a", "b"]);
+        assert!(loaded.malformed.is_empty());
+    }
+
+    #[test]
+    fn one_bad_entry_costs_one_entry() {
+        let loaded = load(BROKEN);
+        assert_eq!(keys(&loaded.bib), ["a", "c"]);
+        // Macros are still expanded in the entries that load
+        let journal = loaded.bib.get("a").unwrap().fields.get("journal").unwrap();
+        assert_eq!(crate::core::bib_to_string(journal), "Journal");
+
+        let [block] = &loaded.malformed[..] else {
+            panic!("expected one malformed block: {:?}", loaded.malformed);
+        };
+        assert_eq!(block.label, "b (line 5)");
+        assert_eq!(block.kind, "article");
+        assert_eq!(block.text, "@article{b, title = {B}, year = {2001}");
+        assert_eq!(&BROKEN[block.start..][..block.text.len()], block.text);
+        assert_eq!(block.resolution, None);
+    }
+
+    #[test]
+    fn saving_unchanged_gives_the_file_back() {
+        let loaded = load(BROKEN);
+        let mut defs = Definitions::scan(BROKEN, &loaded.bib);
+        defs.malformed = loaded.malformed;
+        let saved = merger::merge_bibliography_into_source(
+            BROKEN,
+            &loaded.bib,
+            &defs,
+            &EntryIds::assign(&loaded.bib),
+            &FormatConfig::default(),
+        );
+        assert_eq!(saved, BROKEN);
+    }
+
+    #[test]
+    fn labels_name_the_key_and_line() {
+        let loaded = load("@misc{x, a = {\n@misc{x, b = {\n");
+        let labels: Vec<&str> = loaded.malformed.iter().map(|m| m.label.as_str()).collect();
+        assert_eq!(labels, ["x (line 1)", "x (line 2)"]);
+    }
+
+    #[test]
+    fn a_large_file_loads_around_its_bad_blocks() {
+        let mut source = String::new();
+        for i in 0..40 {
+            source.push_str(&format!("@string{{s{} = {{S{}}}}}\n", i, i));
+        }
+        source.push_str("@string{bad = undefined}\n");
+        for i in 0..200 {
+            match i {
+                50 => source.push_str("@misc{e50, note = {unclosed}\n"),
+                120 => source.push_str("@misc{e120, note = bad}\n"),
+                150 => source.push_str("@misc{e3, note = {again}}\n"),
+                _ => source.push_str(&format!("@misc{{e{}, note = s{}}}\n", i, i % 40)),
+            }
+        }
+
+        let loaded = load(&source);
+        assert_eq!(loaded.bib.len(), 197);
+        let note = loaded.bib.get("e199").unwrap().fields.get("note").unwrap();
+        assert_eq!(crate::core::bib_to_string(note), "S39");
+        assert_eq!(
+            crate::core::bib_to_string(loaded.bib.get("e3").unwrap().fields.get("note").unwrap()),
+            "S3"
+        );
+        let labels: Vec<&str> = loaded.malformed.iter().map(|m| m.label.as_str()).collect();
+        assert_eq!(labels, ["e50 (line 92)", "e120 (line 162)"]);
+    }
+}
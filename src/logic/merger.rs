@@ -1,189 +1,640 @@
 // src/logic/merger.rs
 
-use crate::core::keygen::KeyGenConfig;
-use biblatex::Bibliography;
+use crate::core::config::{Dialect, FormatConfig};
+use crate::core::latex::TextEncoding;
+use crate::logic::loader::{Malformed, Resolution};
+use crate::logic::scanner;
+use crate::logic::sorter;
+use crate::logic::strings::{Definitions, StringDef};
+use biblatex::{Bibliography, Entry};
 use std::collections::{HashMap, HashSet};
 
-struct EntrySpan {
-    key: String,
-    start: usize, // Byte index
-    end: usize,   // Byte index
+/// Identity of entries across key changes. Every entry gets an ID when the
+/// file is loaded or saved; a rename moves the ID to the new key, so the
+/// merger still finds the block the entry came from. The entry as it was
+/// then is kept too, so unmodified entries are saved byte for byte.
+#[derive(Debug, Clone, Default)]
+pub struct EntryIds {
+    next: u64,
+    /// Lowercase current key -> ID
+    ids: HashMap<String, u64>,
+    /// ID -> lowercase key of the entry's block in the source
+    source_keys: HashMap<u64, String>,
+    /// ID -> the entry as its block in the source reads
+    originals: HashMap<u64, Entry>,
+    /// Write the entries in library order instead of file order
+    library_order: bool,
+}
+
+impl EntryIds {
+    /// IDs for the entries of a file that was just loaded or saved, whose
+    /// keys are the keys in the source.
+    pub fn assign(bib: &Bibliography) -> Self {
+        let mut ids = Self::default();
+        for entry in bib.iter() {
+            let key = entry.key.to_lowercase();
+            if ids.ids.contains_key(&key) {
+                continue;
+            }
+            ids.ids.insert(key.clone(), ids.next);
+            ids.source_keys.insert(ids.next, key);
+            ids.originals.insert(ids.next, entry.clone());
+            ids.next += 1;
+        }
+        ids
+    }
+
+    /// `bib` was just saved, so every entry's block now has its key. The
+    /// IDs stay: undo snapshots taken before the save still find their
+    /// blocks, and an entry undone to what was saved is not rewritten.
+    pub fn saved(&mut self, bib: &Bibliography) {
+        let mut saved = Self {
+            next: self.next,
+            ..Self::default()
+        };
+        for entry in bib.iter() {
+            let key = entry.key.to_lowercase();
+            if saved.ids.contains_key(&key) {
+                continue;
+            }
+            let id = self.ids.get(&key).copied().unwrap_or_else(|| {
+                saved.next += 1;
+                saved.next - 1
+            });
+            saved.ids.insert(key.clone(), id);
+            saved.source_keys.insert(id, key);
+            saved.originals.insert(id, entry.clone());
+        }
+        *self = saved;
+    }
+
+    /// The source is now `disk` (merged from outside). Entries keep their
+    /// identity if their block is still there; new blocks get new IDs.
+    pub fn rebase(&mut self, disk: &Bibliography) {
+        let mut rebased = Self {
+            next: self.next,
+            library_order: self.library_order,
+            ..Self::default()
+        };
+        for entry in disk.iter() {
+            let key = entry.key.to_lowercase();
+            if rebased.source_keys.values().any(|k| *k == key) {
+                continue;
+            }
+            let id = self
+                .source_keys
+                .iter()
+                .find(|(_, k)| **k == key)
+                .map(|(id, _)| *id)
+                .unwrap_or_else(|| {
+                    rebased.next += 1;
+                    rebased.next - 1
+                });
+            rebased.source_keys.insert(id, key);
+            rebased.originals.insert(id, entry.clone());
+        }
+        for (key, id) in &self.ids {
+            if rebased.source_keys.contains_key(id) {
+                rebased.ids.insert(key.clone(), *id);
+            }
+        }
+        for (id, key) in &rebased.source_keys {
+            rebased.ids.entry(key.clone()).or_insert(*id);
+        }
+        *self = rebased;
+    }
+
+    /// Makes every entry count as modified, so the next save formats the
+    /// whole file.
+    pub fn mark_all_modified(&mut self) {
+        self.originals.clear();
+    }
+
+    /// Makes the next save write the entries in the order of the library
+    /// (after it was sorted) rather than where their blocks are.
+    pub fn use_library_order(&mut self) {
+        self.library_order = true;
+    }
+
+    /// Moves IDs along with renames that happened at the same time, so
+    /// swaps (A -> B, B -> A) keep both identities.
+    pub fn rename(&mut self, renames: &[(String, String)]) {
+        let moved: Vec<(String, u64)> = renames
+            .iter()
+            .filter_map(|(old, new)| {
+                let id = self.ids.remove(&old.to_lowercase())?;
+                Some((new.to_lowercase(), id))
+            })
+            .collect();
+        self.ids.extend(moved);
+    }
+
+    /// Takes the keys of an undo snapshot back, so entries renamed since
+    /// find their blocks again.
+    pub fn restore(&mut self, earlier: &EntryIds) {
+        self.ids = earlier.ids.clone();
+        self.library_order = earlier.library_order;
+    }
+
+    /// Key of the source block the entry now called `key` came from.
+    pub fn source_key(&self, key: &str) -> Option<&str> {
+        let id = self.ids.get(&key.to_lowercase())?;
+        self.source_keys.get(id).map(String::as_str)
+    }
+
+    /// True if `entry` was added, or differs from its block in the source
+    /// in anything but its key.
+    pub fn is_modified(&self, entry: &Entry) -> bool {
+        let original = self
+            .ids
+            .get(&entry.key.to_lowercase())
+            .and_then(|id| self.originals.get(id));
+        match original {
+            Some(original) => !same_content(original, entry),
+            None => true,
+        }
+    }
+}
+
+/// Same type and field values. Spans are ignored: they only say where the
+/// text was parsed from.
+fn same_content(a: &Entry, b: &Entry) -> bool {
+    a.entry_type == b.entry_type
+        && a.fields.len() == b.fields.len()
+        && a.fields.iter().all(|(name, chunks)| {
+            b.fields.get(name).map_or(false, |other| {
+                chunks.len() == other.len() && chunks.iter().zip(other).all(|(x, y)| x.v == y.v)
+            })
+        })
+}
+
+/// The library as a recovery journal. Written into `original` like a save,
+/// so untouched entries keep their bytes, but without what saving does to
+/// values (capitals, encoding, dialect, order): restoring it gives back
+/// the library as it was in memory.
+pub fn journal(original: &str, bib: &Bibliography, defs: &Definitions, ids: &EntryIds) -> String {
+    let neutral = FormatConfig {
+        protect_capitals: false,
+        encoding: TextEncoding::Keep,
+        dialect: Dialect::Keep,
+        sort_on_save: false,
+        unwrap_lines: false,
+        wrap_width: 0,
+        ..FormatConfig::default()
+    };
+    merge_bibliography_into_source(original, bib, defs, ids, &neutral)
 }
 
 pub fn merge_bibliography_into_source(
     original: &str,
     bib: &Bibliography,
-    config: &KeyGenConfig,
+    defs: &Definitions,
+    ids: &EntryIds,
+    config: &FormatConfig,
 ) -> String {
-    let spans = scan_entry_spans(original);
-
-    // Fallback if parsing fails (should rarely happen with scan_entry_spans)
-    if spans.is_empty() && !bib.is_empty() {
-        return generate_clean_bibliography(bib, config);
+    let original = apply_fixes(original, bib, defs, config);
+    let original = original.as_str();
+
+    // Text the scanner cannot read lies between the blocks, so it is
+    // copied as it is
+    let spans = scanner::scan(original).blocks;
+
+    // Nothing to merge into: keep what is there and write the library after it
+    if spans.is_empty() && (!bib.is_empty() || !defs.is_empty()) {
+        let clean = generate_clean_bibliography(bib, defs, config);
+        if original.trim().is_empty() {
+            // Not from a file (autosave): what could not be read goes too
+            let mut clean = clean;
+            for block in defs.malformed.iter().filter(|b| b.is_open(bib)) {
+                clean.push_str(&block.text);
+                clean.push('\n');
+            }
+            return clean;
+        }
+        return format!("{}\n\n{}", original.trim_end(), clean);
     }
 
-    let mut sorted_spans = spans.values().collect::<Vec<_>>();
-    sorted_spans.sort_by_key(|s| s.start);
+    // Entries from the file take the block they came from, even after a
+    // rename. New entries take a block with their key, if one is left.
+    let mut bib_lookup: HashMap<String, &Entry> = HashMap::new();
+    for entry in bib.iter() {
+        if ids.source_key(&entry.key).is_none() {
+            bib_lookup.insert(entry.key.to_lowercase(), entry);
+        }
+    }
+    for entry in bib.iter() {
+        if let Some(source) = ids.source_key(&entry.key) {
+            bib_lookup.insert(source.to_string(), entry);
+        }
+    }
 
-    let mut output = String::with_capacity(original.len());
-    let mut last_pos = 0;
+    // What becomes of each entry block. A second block with a key, and one
+    // the parser rejected, are left untouched; blocks of deleted entries
+    // are dropped.
+    let malformed: HashSet<&str> = defs
+        .malformed
+        .iter()
+        .filter(|block| block.is_open(bib))
+        .map(|block| block.text.as_str())
+        .collect();
     let mut processed_keys = HashSet::new();
+    let mut slots: Vec<Slot> = Vec::with_capacity(spans.len());
+    let mut sources: HashMap<String, usize> = HashMap::new();
+    let mut in_place: Vec<&Entry> = Vec::new();
+    for (i, span) in spans.iter().enumerate() {
+        if matches!(span.kind.as_str(), "preamble" | "comment" | "string") {
+            slots.push(Slot::Other);
+            continue;
+        }
+        let key_lower = span.key.to_lowercase();
+        if processed_keys.contains(&key_lower)
+            || malformed.contains(&original[span.start..span.end])
+        {
+            slots.push(Slot::Untouched);
+        } else if let Some(entry) = bib_lookup.get(&key_lower) {
+            processed_keys.insert(key_lower);
+            sources.insert(entry.key.to_lowercase(), i);
+            slots.push(Slot::Entry(in_place.len()));
+            in_place.push(entry);
+        } else {
+            slots.push(Slot::Deleted);
+        }
+    }
 
-    let mut bib_lookup = HashMap::new();
-    for entry in bib.iter() {
-        bib_lookup.insert(entry.key.to_lowercase(), entry);
+    // Entries not in the file yet go at the end (e.g. created via UI)
+    let mut order: Vec<&Entry> = in_place.clone();
+    order.extend(
+        bib.iter()
+            .filter(|entry| !sources.contains_key(&entry.key.to_lowercase())),
+    );
+    // Sorted, the entries fill the same blocks and the end in a new order
+    if config.sort_on_save {
+        order.sort_by(|a, b| sorter::compare(a, b, &config.sort_order));
+    } else if ids.library_order {
+        order = bib.iter().collect();
     }
 
-    for span in sorted_spans {
+    let write_entry = |entry: &Entry| -> String {
+        let Some(&i) = sources.get(&entry.key.to_lowercase()) else {
+            return crate::logic::formatter::format_entry(entry, defs, config);
+        };
+        let span = &spans[i];
+        let block = &original[span.start..span.end];
+        if ids.is_modified(entry) {
+            crate::logic::formatter::format_entry(entry, defs, config)
+        } else if span.key == entry.key {
+            // Untouched: keep the bytes as they are
+            block.to_string()
+        } else {
+            // Only the key changed
+            replace_key(block, &entry.key)
+                .unwrap_or_else(|| crate::logic::formatter::format_entry(entry, defs, config))
+        }
+    };
+
+    let mut output = String::with_capacity(original.len());
+    let mut last_pos = 0;
+
+    // Strings not in the source yet go after the last existing one, or
+    // before the first block: they must be defined before any entry uses them
+    let last_string = spans.iter().rposition(|s| s.kind == "string");
+    let in_source: HashSet<String> = spans
+        .iter()
+        .filter(|s| s.kind == "string")
+        .map(|s| s.key.to_lowercase())
+        .collect();
+    let added: Vec<&StringDef> = defs
+        .strings
+        .iter()
+        .filter(|def| !in_source.contains(&def.name.to_lowercase()))
+        .collect();
+
+    for (i, span) in spans.iter().enumerate() {
         // Write text BEFORE the entry (preserves existing newlines exactly)
         // This text often contains the "\n" or "\n\n" from the previous save.
         output.push_str(&original[last_pos..span.start]);
+        last_pos = span.end;
 
-        let key_lower = span.key.to_lowercase();
-
-        if let Some(entry) = bib_lookup.get(&key_lower) {
-            // Write formatted entry
-            let serialized = crate::logic::formatter::format_entry(entry, config);
-
-            // ✅ FIX: .trim() removes any trailing newline coming from the formatter.
-            // We rely on the "text BEFORE the entry" (pushed above) to provide the separation.
-            output.push_str(serialized.trim());
+        if last_string.is_none() && i == 0 {
+            for def in &added {
+                output.push_str(&def.to_bibtex());
+                output.push_str("\n\n");
+            }
+        }
 
-            processed_keys.insert(key_lower);
-        } else {
+        match (span.kind.as_str(), slots[i]) {
+            // Kept exactly as written
+            ("preamble" | "comment", _) => output.push_str(&original[span.start..span.end]),
+            ("string", _) => {
+                // Removed in MkBib: drop it
+                if let Some(def) = defs.string(&span.key) {
+                    if def.is_modified() {
+                        output.push_str(&def.to_bibtex());
+                    } else {
+                        output.push_str(&original[span.start..span.end]);
+                    }
+                }
+            }
+            (_, Slot::Untouched) => output.push_str(&original[span.start..span.end]),
+            (_, Slot::Entry(n)) => {
+                // ✅ FIX: .trim() removes any trailing newline coming from the formatter.
+                // We rely on the "text BEFORE the entry" (pushed above) to provide the separation.
+                output.push_str(write_entry(order[n]).trim());
+            }
             // Entry Deleted: we skip writing the original span (deleting it)
+            _ => {}
         }
 
-        last_pos = span.end;
+        if last_string == Some(i) {
+            for def in &added {
+                output.push('\n');
+                output.push_str(&def.to_bibtex());
+            }
+        }
     }
 
     // Write tail
     output.push_str(&original[last_pos..]);
 
     // Append NEW entries (e.g. created via UI)
-    for entry in bib.iter() {
-        if !processed_keys.contains(&entry.key.to_lowercase()) {
-            let serialized = crate::logic::formatter::format_entry(entry, config);
+    for entry in &order[in_place.len()..] {
+        let serialized = write_entry(*entry);
+
+        // Only add a newline if the file doesn't end with one
+        if !output.ends_with('\n') {
+            output.push('\n');
+        }
+
+        // ✅ FIX: .trim() here too, just to be safe.
+        output.push_str(serialized.trim());
+        // We do NOT add a trailing newline here, to satisfy your request.
+    }
 
-            // Only add a newline if the file doesn't end with one
+    // Unreadable text from another file (a restored backup) is kept too
+    for block in defs.malformed.iter().filter(|b| b.is_open(bib)) {
+        if !original.contains(&block.text) {
             if !output.ends_with('\n') {
                 output.push('\n');
             }
-
-            // ✅ FIX: .trim() here too, just to be safe.
-            output.push_str(serialized.trim());
-            // We do NOT add a trailing newline here, to satisfy your request.
+            output.push_str(block.text.trim());
         }
     }
 
     output
 }
 
-fn generate_clean_bibliography(bib: &Bibliography, config: &KeyGenConfig) -> String {
-    let mut out = String::new();
-    for entry in bib.iter() {
+/// What saving does with a block of the source.
+#[derive(Clone, Copy)]
+enum Slot {
+    /// @string, @preamble or @comment
+    Other,
+    /// Holds the n-th entry of the write order
+    Entry(usize),
+    /// A second block with a key already written, or one that could not
+    /// be read
+    Untouched,
+    Deleted,
+}
+
+/// `original` with the malformed text that was fixed replaced by its
+/// entry, so the entry takes its place, and the deleted text removed.
+fn apply_fixes(
+    original: &str,
+    bib: &Bibliography,
+    defs: &Definitions,
+    config: &FormatConfig,
+) -> String {
+    let mut blocks: Vec<&Malformed> = defs.malformed.iter().collect();
+    blocks.sort_by_key(|block| block.start);
+
+    let mut fixed = original.to_string();
+    // How far the text after the last replacement moved, and where the
+    // next block may start. The same text can be in the file twice, so
+    // each block is looked for where it was, then after the one before it
+    let mut shift: isize = 0;
+    let mut pos = 0;
+    for block in blocks {
+        let at_start = block
+            .start
+            .checked_add_signed(shift)
+            .filter(|&start| start >= pos)
+            .filter(|&start| {
+                fixed
+                    .get(start..)
+                    .map_or(false, |rest| rest.starts_with(&block.text))
+            });
+        let Some(start) = at_start.or_else(|| Some(pos + fixed.get(pos..)?.find(&block.text)?))
+        else {
+            continue;
+        };
+        let mut end = start + block.text.len();
+        let replacement = match &block.resolution {
+            Some(Resolution::Fixed(key)) => bib.get(key).map(|entry| {
+                crate::logic::formatter::format_entry(entry, defs, config)
+                    .trim()
+                    .to_string()
+            }),
+            Some(Resolution::Deleted) => {
+                // With the blank lines after it
+                let rest = &fixed[end..];
+                end += rest.len() - rest.trim_start().len();
+                Some(String::new())
+            }
+            None => None,
+        };
+        let Some(replacement) = replacement else {
+            pos = end;
+            continue;
+        };
+        fixed.replace_range(start..end, &replacement);
+        shift += replacement.len() as isize - (end - start) as isize;
+        pos = start + replacement.len();
+    }
+    fixed
+}
+
+fn generate_clean_bibliography(
+    bib: &Bibliography,
+    defs: &Definitions,
+    config: &FormatConfig,
+) -> String {
+    // Preambles and strings first, so the entries can use them
+    let mut out = defs.header();
+    let mut entries: Vec<&Entry> = bib.iter().collect();
+    if config.sort_on_save {
+        entries.sort_by(|a, b| sorter::compare(a, b, &config.sort_order));
+    }
+    for entry in entries {
         // ✅ FIX: Trim here too so we control the spacing explicitly
-        out.push_str(crate::logic::formatter::format_entry(entry, config).trim());
+        out.push_str(crate::logic::formatter::format_entry(entry, defs, config).trim());
         // Minimal separation for clean file generation
         out.push('\n');
     }
+    for comment in &defs.comments {
+        out.push_str(comment);
+        out.push('\n');
+    }
     out
 }
 
-/// Robust scanner using char_indices for correct Byte Offsets
-fn scan_entry_spans(text: &str) -> HashMap<String, EntrySpan> {
-    let mut spans = HashMap::new();
-    let mut chars_iter = text.char_indices().peekable();
+/// `block` with its citation key replaced by `key`.
+fn replace_key(block: &str, key: &str) -> Option<String> {
+    let open = block.find(&['{', '('][..])? + 1;
+    let rest = &block[open..];
+    let start = open + (rest.len() - rest.trim_start().len());
+    let end = start + block[start..].find(',')?;
+    let end = start + block[start..end].trim_end().len();
+    Some(format!("{}{}{}", &block[..start], key, &block[end..]))
+}
 
-    while let Some((idx, c)) = chars_iter.next() {
-        if c == '@' {
-            let start = idx;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logic::loader::{self, Resolution};
+    use biblatex::{Chunk, Spanned};
+
+    const SOURCE: &str = "% My library\n\n\
+                          @article{a,\n  title = {A},   year = 2001}\n\n\
+                          @book{ b ,title={B}}\n\n\
+                          @misc{c, note = {C}}\n";
+
+    fn save(source: &str, bib: &Bibliography, ids: &EntryIds) -> String {
+        let loaded = loader::load(source);
+        let mut defs = Definitions::scan(source, &loaded.bib);
+        defs.malformed = loaded.malformed;
+        merge_bibliography_into_source(source, bib, &defs, ids, &FormatConfig::default())
+    }
 
-            // 1. Skip Type
-            while let Some((_, c)) = chars_iter.peek() {
-                if c.is_whitespace() || *c == '{' || *c == '(' {
-                    break;
-                }
-                chars_iter.next();
+    fn renamed(bib: &Bibliography, old: &str, new: &str) -> Bibliography {
+        let mut out = Bibliography::new();
+        for entry in bib.iter() {
+            let mut entry = entry.clone();
+            if entry.key == old {
+                entry.key = new.to_string();
             }
+            out.insert(entry);
+        }
+        out
+    }
 
-            while let Some((_, c)) = chars_iter.peek() {
-                if !c.is_whitespace() {
-                    break;
-                }
-                chars_iter.next();
-            }
+    fn set_field(bib: &mut Bibliography, key: &str, field: &str, text: &str) {
+        let chunks = vec![Spanned::new(Chunk::Normal(text.to_string()), 0..0)];
+        bib.get_mut(key)
+            .unwrap()
+            .fields
+            .insert(field.to_string(), chunks);
+    }
 
-            // 2. Delimiter
-            let mut open_delim = '{';
-            let mut close_delim = '}';
-
-            if let Some((_, c)) = chars_iter.peek() {
-                if *c == '{' {
-                    open_delim = '{';
-                    close_delim = '}';
-                    chars_iter.next();
-                } else if *c == '(' {
-                    open_delim = '(';
-                    close_delim = ')';
-                    chars_iter.next();
-                } else {
-                    continue;
-                }
-            } else {
-                continue;
-            }
+    #[test]
+    fn unchanged_library_is_saved_byte_for_byte() {
+        let bib = Bibliography::parse(SOURCE).unwrap();
+        assert_eq!(save(SOURCE, &bib, &EntryIds::assign(&bib)), SOURCE);
+    }
 
-            // 3. Parse Key
-            while let Some((_, c)) = chars_iter.peek() {
-                if !c.is_whitespace() {
-                    break;
-                }
-                chars_iter.next();
-            }
+    #[test]
+    fn only_the_edited_entry_is_rewritten() {
+        let mut bib = Bibliography::parse(SOURCE).unwrap();
+        let ids = EntryIds::assign(&bib);
+        set_field(&mut bib, "b", "title", "New");
+        let saved = save(SOURCE, &bib, &ids);
+
+        assert!(saved
+            .starts_with("% My library\n\n@article{a,\n  title = {A},   year = 2001}\n\n@book{b,"));
+        assert!(saved.contains("New"));
+        assert!(saved.ends_with("}\n\n@misc{c, note = {C}}\n"));
+    }
 
-            let key_start_opt = chars_iter.peek().map(|(i, _)| *i);
-            let mut key_end = 0;
+    #[test]
+    fn a_renamed_entry_keeps_its_block() {
+        let bib = Bibliography::parse(SOURCE).unwrap();
+        let mut ids = EntryIds::assign(&bib);
+        let bib = renamed(&bib, "a", "z");
+        ids.rename(&[("a".into(), "z".into())]);
+        assert_eq!(
+            save(SOURCE, &bib, &ids),
+            SOURCE.replace("@article{a,", "@article{z,")
+        );
+    }
 
-            if let Some(key_start) = key_start_opt {
-                while let Some((i, c)) = chars_iter.peek() {
-                    if *c == ',' || *c == close_delim || c.is_whitespace() {
-                        key_end = *i;
-                        break;
-                    }
-                    chars_iter.next();
-                }
+    #[test]
+    fn added_entries_go_last_and_deleted_ones_go() {
+        let mut bib = Bibliography::parse(SOURCE).unwrap();
+        let ids = EntryIds::assign(&bib);
+        bib.remove("b");
+        let added = Bibliography::parse("@misc{d, note = {D}}").unwrap();
+        bib.insert(added.get("d").unwrap().clone());
+
+        let saved = save(SOURCE, &bib, &ids);
+        assert!(!saved.contains("@book"));
+        assert!(saved.contains("@misc{c, note = {C}}"));
+        assert!(saved.find("@misc{c").unwrap() < saved.find("@misc{d").unwrap());
+    }
 
-                let key = text[key_start..key_end].trim().to_string();
-
-                // 4. Find Matching End
-                let mut depth = 1;
-                let mut end_pos = 0;
-
-                while let Some((i, c)) = chars_iter.next() {
-                    if c == open_delim {
-                        depth += 1;
-                    } else if c == close_delim {
-                        depth -= 1;
-                        if depth == 0 {
-                            end_pos = i + c.len_utf8();
-                            break;
-                        }
-                    }
-                }
+    #[test]
+    fn undoing_a_rename_after_saving_restores_the_file() {
+        let bib = Bibliography::parse(SOURCE).unwrap();
+        let mut ids = EntryIds::assign(&bib);
+        let before = ids.clone();
 
-                if depth == 0 && !key.is_empty() {
-                    spans.insert(
-                        key.clone(),
-                        EntrySpan {
-                            key,
-                            start,
-                            end: end_pos,
-                        },
-                    );
-                }
-            }
-        }
+        let bib_renamed = renamed(&bib, "b", "z");
+        ids.rename(&[("b".into(), "z".into())]);
+        let saved = save(SOURCE, &bib_renamed, &ids);
+        ids.saved(&bib_renamed);
+
+        ids.restore(&before);
+        assert_eq!(save(&saved, &bib, &ids), SOURCE);
+    }
+
+    #[test]
+    fn a_restored_journal_leaves_untouched_entries_unmodified() {
+        let source = "@article{a, title = {The DNA of X}}\n\n@misc{b, note = {B}}\n";
+        let mut bib = Bibliography::parse(source).unwrap();
+        let ids = EntryIds::assign(&bib);
+        set_field(&mut bib, "b", "note", "The RNA of Y");
+        let defs = Definitions::scan(source, &bib);
+        let journal = journal(source, &bib, &defs, &ids);
+
+        let restored = loader::load(&journal).bib;
+        let ids = EntryIds::assign(&Bibliography::parse(source).unwrap());
+        assert!(!ids.is_modified(restored.get("a").unwrap()));
+        let note = restored.get("b").unwrap().fields.get("note").unwrap();
+        assert_eq!(crate::core::bib_to_string(note), "The RNA of Y");
+    }
+
+    #[test]
+    fn deleting_unreadable_text_removes_the_right_copy() {
+        let source = "@misc{x, title = {T}\n\n@book{b, title = {B}}\n\n@misc{x, title = {T}\n";
+        let loaded = loader::load(source);
+        assert_eq!(loaded.malformed.len(), 2);
+        let mut defs = Definitions::scan(source, &loaded.bib);
+        defs.malformed = loaded.malformed;
+        defs.malformed[1].resolution = Some(Resolution::Deleted);
+
+        let saved = merge_bibliography_into_source(
+            source,
+            &loaded.bib,
+            &defs,
+            &EntryIds::assign(&loaded.bib),
+            &FormatConfig::default(),
+        );
+        assert_eq!(saved, "@misc{x, title = {T}\n\n@book{b, title = {B}}\n\n");
+    }
+
+    #[test]
+    fn unreadable_text_from_a_backup_is_kept() {
+        let source = "@book{b, title = {B}}\n";
+        let backup = "@book{b, title = {B}}\n\n@misc{x, title = {T}\n";
+        let loaded = loader::load(backup);
+        let mut defs = Definitions::scan(backup, &loaded.bib);
+        defs.malformed = loaded.malformed;
+
+        let saved = merge_bibliography_into_source(
+            source,
+            &loaded.bib,
+            &defs,
+            &EntryIds::assign(&Bibliography::parse(source).unwrap()),
+            &FormatConfig::default(),
+        );
+        assert_eq!(saved, "@book{b, title = {B}}\n@misc{x, title = {T}");
     }
-    spans
 }
@@ -1,7 +1,15 @@
 pub mod abbreviator;
 pub mod deduplicator;
+pub mod dialect;
+pub mod diff;
+pub mod external;
 pub mod fetch;
 pub mod formatter;
 pub mod library;
+pub mod loader;
 pub mod merger;
+pub mod scanner;
+pub mod sorter;
+pub mod strings;
+pub mod tex_rename;
 pub mod undo;
@@ -0,0 +1,453 @@
+// src/logic/scanner.rs
+//
+// Splits a .bib source into its top-level blocks, following the grammar
+// of the biblatex parser: `@type{key, name = value # value, ...}` with
+// braced, quoted or bare values. Saving uses the blocks to replace entries
+// in place, so anything the scanner cannot read is reported and kept as
+// written rather than guessed at.
+
+/// A top-level `@type{...}` or `@type(...)` block.
+#[derive(Debug, Clone)]
+pub struct Block<'s> {
+    /// Lowercase block type: "string", "comment", "article", ...
+    pub kind: String,
+    /// Citation key of an entry, name of a @string; empty otherwise.
+    pub key: String,
+    /// Text between the delimiters.
+    pub body: &'s str,
+    /// Byte range of the whole block, from the `@`.
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Something in the source that saving leaves alone.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Problem {
+    /// Text from `line` on that does not read as a block.
+    Unreadable {
+        line: usize,
+        reason: &'static str,
+        /// Byte range of the text, up to where reading starts again.
+        span: std::ops::Range<usize>,
+        /// Byte offset where reading failed.
+        at: usize,
+    },
+    /// An entry whose key an earlier entry already has.
+    DuplicateKey { key: String, line: usize },
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Problem::Unreadable { line, reason, .. } => write!(f, "line {}: {}", line, reason),
+            Problem::DuplicateKey { key, line } => {
+                write!(f, "line {}: duplicate key \"{}\"", line, key)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Scan<'s> {
+    /// Blocks in file order.
+    pub blocks: Vec<Block<'s>>,
+    pub problems: Vec<Problem>,
+}
+
+impl Scan<'_> {
+    /// A message listing the problems, or None if there are none.
+    pub fn report(&self) -> Option<String> {
+        const SHOWN: usize = 10;
+        if self.problems.is_empty() {
+            return None;
+        }
+        let mut text = String::from(
+            "Some parts of this file could not be read as entries.\n\
+             They are saved exactly as written:\n",
+        );
+        for problem in self.problems.iter().take(SHOWN) {
+            text.push_str(&format!("\n• {}", problem));
+        }
+        if self.problems.len() > SHOWN {
+            text.push_str(&format!("\n… and {} more", self.problems.len() - SHOWN));
+        }
+        Some(text)
+    }
+}
+
+/// Scans `text` for blocks. Text between blocks is a comment, as in
+/// BibTeX; `%` lines there are skipped, and an `@` inside a word (an email
+/// address) does not start a block.
+pub fn scan(text: &str) -> Scan<'_> {
+    let mut scanner = Scanner {
+        text,
+        bytes: text.as_bytes(),
+        pos: 0,
+    };
+    let mut scan = Scan::default();
+    let mut keys = std::collections::HashSet::new();
+
+    while scanner.pos < scanner.bytes.len() {
+        let start = scanner.pos;
+        match scanner.bytes[start] {
+            b'%' => scanner.skip_line(),
+            b'@' if start == 0 || !is_word_byte(scanner.bytes[start - 1]) => {
+                match scanner.block() {
+                    Ok(Some(block)) => {
+                        let entry =
+                            !matches!(block.kind.as_str(), "string" | "preamble" | "comment");
+                        if entry && !keys.insert(block.key.to_lowercase()) {
+                            scan.problems.push(Problem::DuplicateKey {
+                                key: block.key.clone(),
+                                line: line_of(text, start),
+                            });
+                        }
+                        scan.blocks.push(block);
+                    }
+                    // Not a block after all: "@" in free text
+                    Ok(None) => scanner.pos = start + 1,
+                    Err(reason) => {
+                        let at = scanner.pos;
+                        scanner.pos = start + 1;
+                        scanner.skip_to_next_block();
+                        scan.problems.push(Problem::Unreadable {
+                            line: line_of(text, start),
+                            reason,
+                            span: start..scanner.pos,
+                            at,
+                        });
+                    }
+                }
+            }
+            _ => scanner.pos += 1,
+        }
+    }
+    scan
+}
+
+struct Scanner<'s> {
+    text: &'s str,
+    bytes: &'s [u8],
+    pos: usize,
+}
+
+type Result<T> = std::result::Result<T, &'static str>;
+
+impl<'s> Scanner<'s> {
+    /// Reads the block at the `@` under the cursor. Ok(None) if the `@` is
+    /// not followed by a type and an opening delimiter.
+    fn block(&mut self) -> Result<Option<Block<'s>>> {
+        let start = self.pos;
+        self.pos += 1;
+        let kind = self.ident().to_lowercase();
+        self.skip_whitespace();
+        let close = match self.peek() {
+            Some(b'{') if !kind.is_empty() => b'}',
+            Some(b'(') if !kind.is_empty() => b')',
+            _ => return Ok(None),
+        };
+        self.pos += 1;
+        let body_start = self.pos;
+
+        let key = match kind.as_str() {
+            // Free text, kept as written
+            "comment" | "preamble" => {
+                self.balanced(close)?;
+                String::new()
+            }
+            "string" => {
+                // One `name = value` field; the name is read again there
+                self.skip_whitespace();
+                let name = self.ident();
+                self.pos -= name.len();
+                self.fields(close)?;
+                name.to_string()
+            }
+            _ => {
+                self.skip_whitespace();
+                let key = self.take_while(|b| {
+                    !matches!(b, b',' | b'}' | b')')
+                        && !b.is_ascii_whitespace()
+                        && !b.is_ascii_control()
+                });
+                if key.is_empty() {
+                    return Err("entry without a citation key");
+                }
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(b',') => {
+                        self.pos += 1;
+                        self.fields(close)?;
+                    }
+                    Some(c) if c == close => {}
+                    Some(_) => return Err("expected a comma after the citation key"),
+                    None => return Err("entry is not closed"),
+                }
+                key.to_string()
+            }
+        };
+
+        let body_end = self.pos;
+        self.pos += 1;
+        Ok(Some(Block {
+            kind,
+            key,
+            body: &self.text[body_start..body_end],
+            start,
+            end: self.pos,
+        }))
+    }
+
+    /// `name = value, ...` up to the closing delimiter, which is left under
+    /// the cursor.
+    fn fields(&mut self, close: u8) -> Result<()> {
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(c) if c == close => return Ok(()),
+                None => return Err("entry is not closed"),
+                _ => {}
+            }
+            if self.ident().is_empty() {
+                return Err("expected a field name");
+            }
+            self.skip_whitespace();
+            if self.peek() != Some(b'=') {
+                return Err("expected '=' after a field name");
+            }
+            self.pos += 1;
+            self.value()?;
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(c) if c == close => return Ok(()),
+                None => return Err("entry is not closed"),
+                Some(_) => return Err("expected a comma between fields"),
+            }
+        }
+    }
+
+    /// `part # part # ...`, each part braced, quoted, or a macro name or
+    /// number.
+    fn value(&mut self) -> Result<()> {
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b'{') => {
+                    self.pos += 1;
+                    self.balanced(b'}')?;
+                    self.pos += 1;
+                }
+                Some(b'"') => self.quoted()?,
+                _ => {
+                    if self.ident().is_empty() {
+                        return Err("expected a field value");
+                    }
+                }
+            }
+            self.skip_whitespace();
+            if self.peek() != Some(b'#') {
+                return Ok(());
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Moves to the `close` that ends the braced text under the cursor.
+    /// A backslash escapes the next character, as in the parser.
+    fn balanced(&mut self, close: u8) -> Result<()> {
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            match c {
+                b'\\' => self.pos += 1,
+                c if c == close && depth == 0 => return Ok(()),
+                b'{' => depth += 1,
+                b'}' if depth > 0 => depth -= 1,
+                b'}' => return Err("unbalanced braces"),
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        Err("braces are not closed")
+    }
+
+    /// Moves past the `"..."` under the cursor. A quote inside braces
+    /// (`"a {"} b"`) does not end the value.
+    fn quoted(&mut self) -> Result<()> {
+        self.pos += 1;
+        let mut depth = 0;
+        while let Some(c) = self.peek() {
+            match c {
+                b'\\' => self.pos += 1,
+                b'"' if depth == 0 => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                b'{' => depth += 1,
+                b'}' if depth > 0 => depth -= 1,
+                b'}' => return Err("unbalanced braces"),
+                _ => {}
+            }
+            self.pos += 1;
+        }
+        Err("quotes are not closed")
+    }
+
+    /// An identifier as the parser reads them: entry types, field names,
+    /// macro names and numbers.
+    fn ident(&mut self) -> &'s str {
+        self.take_while(|b| {
+            !matches!(
+                b,
+                b'@' | b'{'
+                    | b'}'
+                    | b'"'
+                    | b'#'
+                    | b'\''
+                    | b'('
+                    | b')'
+                    | b','
+                    | b'='
+                    | b'%'
+                    | b'\\'
+                    | b'~'
+            ) && !b.is_ascii_whitespace()
+                && !b.is_ascii_control()
+        })
+    }
+
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> &'s str {
+        let start = self.pos;
+        while self.peek().map_or(false, &f) {
+            self.pos += 1;
+        }
+        &self.text[start..self.pos]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.take_while(|b| b.is_ascii_whitespace());
+    }
+
+    fn skip_line(&mut self) {
+        self.take_while(|b| b != b'\n');
+    }
+
+    /// After unreadable text: on to the next `@` that starts a line, where
+    /// the next block most likely begins.
+    fn skip_to_next_block(&mut self) {
+        while self.pos < self.bytes.len() {
+            self.skip_line();
+            self.pos += 1;
+            let indent = self.take_while(|b| b == b' ' || b == b'\t').len();
+            if self.peek() == Some(b'@') {
+                return;
+            }
+            self.pos -= indent;
+        }
+        self.pos = self.bytes.len();
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+}
+
+/// Bytes that make an `@` part of a word, as in `name@example.org`.
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-' | b'+') || b >= 0x80
+}
+
+fn line_of(text: &str, pos: usize) -> usize {
+    text[..pos].matches('\n').count() + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds_and_keys(scan: &Scan) -> Vec<(String, String)> {
+        scan.blocks
+            .iter()
+            .map(|b| (b.kind.clone(), b.key.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn splits_blocks_with_their_ranges() {
+        let text = "@string{j = {Journal}}\n\n@Article{a, title = {T}, journal = j}\n\
+                    @comment{free text}\n@misc(b, note = \"n\")\n";
+        let scan = scan(text);
+        assert!(scan.problems.is_empty());
+        assert_eq!(
+            kinds_and_keys(&scan),
+            [
+                ("string".into(), "j".into()),
+                ("article".into(), "a".into()),
+                ("comment".into(), String::new()),
+                ("misc".into(), "b".into()),
+            ]
+        );
+        let article = &scan.blocks[1];
+        assert_eq!(
+            &text[article.start..article.end],
+            "@Article{a, title = {T}, journal = j}"
+        );
+        assert_eq!(article.body, "a, title = {T}, journal = j");
+    }
+
+    #[test]
+    fn text_between_blocks_is_a_comment() {
+        let scan = scan("% @misc{x, title = {\nwrite to me@example.org\n@misc{a, note = {n}}\n");
+        assert!(scan.problems.is_empty());
+        assert_eq!(kinds_and_keys(&scan), [("misc".into(), "a".into())]);
+    }
+
+    #[test]
+    fn values_follow_the_parser() {
+        let text = "@misc{a, title = \"a {\"} b\", note = {x \\} y} # \" z\", year = 2020}";
+        let scan = scan(text);
+        assert!(scan.problems.is_empty(), "{:?}", scan.problems);
+        assert_eq!(scan.blocks.len(), 1);
+        assert_eq!(scan.blocks[0].end, text.len());
+    }
+
+    #[test]
+    fn reading_starts_again_after_unreadable_text() {
+        let text = "@misc{a, title = {T}\n\n@misc{b, title = {B}}\n";
+        let scan = scan(text);
+        assert_eq!(kinds_and_keys(&scan), [("misc".into(), "b".into())]);
+        match &scan.problems[..] {
+            [Problem::Unreadable { line, span, .. }] => {
+                assert_eq!(*line, 1);
+                assert_eq!(&text[span.clone()], "@misc{a, title = {T}\n\n");
+            }
+            other => panic!("unexpected problems: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn quoted_values_need_balanced_braces() {
+        let scan = scan("@misc{a, title = \"a } b\"}\n");
+        assert!(scan.blocks.is_empty());
+        assert!(matches!(
+            scan.problems[..],
+            [Problem::Unreadable {
+                reason: "unbalanced braces",
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn reports_duplicate_keys() {
+        let scan = scan("@misc{a, note = {1}}\n@book{A, note = {2}}\n");
+        assert_eq!(scan.blocks.len(), 2);
+        assert_eq!(
+            scan.problems,
+            [Problem::DuplicateKey {
+                key: "A".into(),
+                line: 2
+            }]
+        );
+    }
+}
@@ -0,0 +1,91 @@
+// src/logic/sorter.rs
+//
+// The library sort order: entries are compared by a list of criteria,
+// each one breaking the ties of the one before. Sorting is stable, so
+// entries equal in every criterion keep their order.
+
+use crate::core;
+use crate::core::config::SortKey;
+use crate::core::transliterate::{transliterate, Transliteration};
+use biblatex::{Bibliography, Entry};
+use std::cmp::Ordering;
+
+/// Fields that record when an entry was added: ours first, then those of
+/// BibDesk, JabRef and older JabRef versions.
+pub const DATE_ADDED_FIELDS: &[&str] = &["dateadded", "date-added", "creationdate", "timestamp"];
+
+pub fn compare(a: &Entry, b: &Entry, order: &[SortKey]) -> Ordering {
+    order
+        .iter()
+        .map(|key| compare_by(a, b, *key))
+        .find(|o| o.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// Sorts the library in place. Returns false if it was in order already.
+pub fn sort_library(bib: &mut Bibliography, order: &[SortKey]) -> bool {
+    let mut entries: Vec<Entry> = bib.iter().cloned().collect();
+    entries.sort_by(|a, b| compare(a, b, order));
+    if entries.iter().zip(bib.iter()).all(|(a, b)| a.key == b.key) {
+        return false;
+    }
+
+    let mut sorted = Bibliography::new();
+    for entry in entries {
+        sorted.insert(entry);
+    }
+    *bib = sorted;
+    true
+}
+
+fn compare_by(a: &Entry, b: &Entry, key: SortKey) -> Ordering {
+    match key {
+        SortKey::Key => a.key.to_lowercase().cmp(&b.key.to_lowercase()),
+        SortKey::Author => missing_last(first_author(a), first_author(b)),
+        SortKey::Year => missing_last(year(a), year(b)),
+        SortKey::EntryType => {
+            core::keygen::type_name(&a.entry_type).cmp(&core::keygen::type_name(&b.entry_type))
+        }
+        // Entries without a date were there before dates were recorded
+        SortKey::DateAdded => date_added(a).cmp(&date_added(b)),
+    }
+}
+
+/// Orders by text, with empty text after everything else.
+fn missing_last(a: String, b: String) -> Ordering {
+    (a.is_empty(), a).cmp(&(b.is_empty(), b))
+}
+
+fn field(entry: &Entry, name: &str) -> String {
+    entry
+        .fields
+        .get(name)
+        .map(|v| core::bib_to_string(v).trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Last name of the first author, without accents, so "Ångström" sorts
+/// with the A's.
+fn first_author(entry: &Entry) -> String {
+    let name = entry
+        .author()
+        .ok()
+        .and_then(|authors| authors.first().map(|p| p.name.clone()))
+        .unwrap_or_default();
+    transliterate(&name, Transliteration::Ascii).to_lowercase()
+}
+
+fn year(entry: &Entry) -> String {
+    match field(entry, "year") {
+        year if !year.is_empty() => year,
+        _ => field(entry, "date").chars().take(4).collect(),
+    }
+}
+
+fn date_added(entry: &Entry) -> String {
+    DATE_ADDED_FIELDS
+        .iter()
+        .map(|name| field(entry, name))
+        .find(|date| !date.is_empty())
+        .unwrap_or_default()
+}
@@ -0,0 +1,430 @@
+// src/logic/strings.rs
+//
+// The parts of a .bib file that `Bibliography::parse` expands or drops:
+// @string definitions, @preamble and @comment blocks, and the fields that
+// refer to a macro (`journal = prl`, `month = jan`, `title = pre # " 2"`).
+// They are scanned from the source next to the parse, so saving can write
+// the macro reference back instead of its expansion. Entries the parser
+// rejects are kept here too (see `loader`), so saving writes them back.
+
+use crate::app::alert::AlertMsg;
+use crate::app::AppModel;
+use crate::core;
+use crate::logic::loader::{Malformed, Resolution};
+use crate::logic::scanner;
+use crate::ui::sidebar::SidebarMsg;
+use biblatex::{Bibliography, Chunk, Spanned};
+use relm4::ComponentController;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Key of the scratch entry used to expand a value with the parser.
+const EXPAND_KEY: &str = "mkbib-expand";
+
+/// One `@string{name = value}` definition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StringDef {
+    pub name: String,
+    /// The value as BibTeX source: `{Physical Review Letters}`, `"..."`,
+    /// or an expression such as `aps # " Journals"`.
+    pub value: String,
+    /// The value as it was in the file; None for definitions added here.
+    pub original: Option<String>,
+}
+
+impl StringDef {
+    pub fn is_modified(&self) -> bool {
+        self.original.as_deref() != Some(self.value.as_str())
+    }
+
+    pub fn to_bibtex(&self) -> String {
+        format!("@string{{{} = {}}}", self.name, self.value)
+    }
+}
+
+/// A field whose value in the file uses a macro.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MacroRef {
+    /// The value as written, e.g. `prl` or `jan # "~1"`.
+    pub raw: String,
+    /// What the parser expanded it to. While the field still reads the
+    /// same, it is saved as `raw`; once edited, as the new text.
+    pub expanded: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Definitions {
+    pub strings: Vec<StringDef>,
+    /// Whole `@preamble{...}` blocks, as written.
+    pub preambles: Vec<String>,
+    /// Whole `@comment{...}` blocks, as written.
+    pub comments: Vec<String>,
+    /// Entry key -> lowercase field name -> macro reference.
+    pub references: BTreeMap<String, BTreeMap<String, MacroRef>>,
+    /// Text that could not be read as an entry when the file was loaded.
+    pub malformed: Vec<Malformed>,
+}
+
+impl Definitions {
+    /// Collects the definitions and macro references of `source`, with
+    /// expansions taken from `bib` (the parse of the same source).
+    pub fn scan(source: &str, bib: &Bibliography) -> Self {
+        let mut defs = Self::default();
+
+        for block in scanner::scan(source).blocks {
+            let text = &source[block.start..block.end];
+            match block.kind.as_str() {
+                "comment" => defs.comments.push(text.to_string()),
+                "preamble" => defs.preambles.push(text.to_string()),
+                "string" => {
+                    if let Some((name, value)) = block.body.split_once('=') {
+                        let value = value.trim().to_string();
+                        defs.strings.push(StringDef {
+                            name: name.trim().to_string(),
+                            original: Some(value.clone()),
+                            value,
+                        });
+                    }
+                }
+                _ => defs.scan_entry(block.body),
+            }
+        }
+
+        defs.capture(bib);
+        defs
+    }
+
+    fn scan_entry(&mut self, body: &str) {
+        let Some((key, fields)) = body.split_once(',') else {
+            return;
+        };
+        let mut refs = BTreeMap::new();
+        for field in split_top_level(fields, ',') {
+            let Some((name, value)) = field.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+            if uses_macro(value) {
+                refs.insert(
+                    name.trim().to_lowercase(),
+                    MacroRef {
+                        raw: value.to_string(),
+                        expanded: String::new(),
+                    },
+                );
+            }
+        }
+        if !refs.is_empty() {
+            self.references.insert(key.trim().to_string(), refs);
+        }
+    }
+
+    /// Records what each reference expands to in `bib`, dropping the ones
+    /// whose entry or field is not there.
+    fn capture(&mut self, bib: &Bibliography) {
+        self.references.retain(|key, refs| {
+            let Some(entry) = bib.get(key) else {
+                return false;
+            };
+            refs.retain(|field, reference| match entry.fields.get(field.as_str()) {
+                Some(chunks) => {
+                    reference.expanded = core::bib_to_string(chunks);
+                    true
+                }
+                None => false,
+            });
+            !refs.is_empty()
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+            && self.preambles.is_empty()
+            && self.comments.is_empty()
+            && self.malformed.is_empty()
+    }
+
+    pub fn string(&self, name: &str) -> Option<&StringDef> {
+        self.strings
+            .iter()
+            .find(|def| def.name.eq_ignore_ascii_case(name))
+    }
+
+    /// The macro reference to write for `field` of entry `key`, if the
+    /// field still holds what the reference expanded to.
+    pub fn raw_value(&self, key: &str, field: &str, chunks: &[Spanned<Chunk>]) -> Option<&str> {
+        let reference = self.references.get(key)?.get(&field.to_lowercase())?;
+        (reference.expanded == core::bib_to_string(chunks)).then_some(reference.raw.as_str())
+    }
+
+    /// Number of fields that refer to the macro `name`.
+    pub fn usage(&self, name: &str) -> usize {
+        self.references
+            .values()
+            .flat_map(|refs| refs.values())
+            .filter(|reference| macro_names(&reference.raw).any(|m| m.eq_ignore_ascii_case(name)))
+            .count()
+    }
+
+    /// Keeps macro references attached to entries whose key changed. The
+    /// renames happen at once, so swapping two keys works.
+    pub fn rename_entries(&mut self, renames: &[(String, String)]) {
+        let moved: Vec<_> = renames
+            .iter()
+            .filter_map(|(old, new)| Some((new, self.references.remove(old)?)))
+            .collect();
+        for (new_key, refs) in moved {
+            self.references.insert(new_key.clone(), refs);
+        }
+        for block in &mut self.malformed {
+            if let Some(Resolution::Fixed(key)) = &mut block.resolution {
+                if let Some((_, new)) = renames.iter().find(|(old, _)| *old == *key) {
+                    *key = new.clone();
+                }
+            }
+        }
+    }
+
+    /// The definitions are now in the file as they are here, and `source`
+    /// is the file. Malformed text that was fixed or deleted is gone from it.
+    pub fn mark_saved(&mut self, bib: &Bibliography, source: &str) {
+        for def in &mut self.strings {
+            def.original = Some(def.value.clone());
+        }
+        self.malformed.retain(|block| block.is_open(bib));
+        // Copied as it was and in the same order, but moved
+        let mut pos = 0;
+        for block in &mut self.malformed {
+            block.resolution = None;
+            if let Some(offset) = source.get(pos..).and_then(|rest| rest.find(&block.text)) {
+                block.start = pos + offset;
+                pos = block.start + block.text.len();
+            }
+        }
+    }
+
+    /// Malformed text by its label in the list.
+    pub fn malformed(&self, label: &str) -> Option<&Malformed> {
+        self.malformed.iter().find(|block| block.label == label)
+    }
+
+    pub fn malformed_mut(&mut self, label: &str) -> Option<&mut Malformed> {
+        self.malformed.iter_mut().find(|block| block.label == label)
+    }
+
+    /// Makes `source` the file saving writes into, so definitions that
+    /// differ from it count as changed.
+    pub fn compare_with(&mut self, source: &str) {
+        let base = Self::scan(source, &Bibliography::new());
+        for def in &mut self.strings {
+            def.original = base.string(&def.name).and_then(|d| d.original.clone());
+        }
+    }
+
+    /// Takes `source` (parsed as `bib`) as the new base after it changed on
+    /// disk, keeping the string definitions changed or added here.
+    pub fn rebase(&mut self, source: &str, bib: &Bibliography) {
+        let mine = std::mem::replace(self, Self::scan(source, bib));
+        for def in mine.strings.into_iter().filter(StringDef::is_modified) {
+            match self
+                .strings
+                .iter_mut()
+                .find(|d| d.name.eq_ignore_ascii_case(&def.name))
+            {
+                Some(theirs) => theirs.value = def.value,
+                None => self.strings.push(StringDef {
+                    original: None,
+                    ..def
+                }),
+            }
+        }
+    }
+
+    /// Expands a BibTeX value expression with the current definitions,
+    /// using the same parser as loading does.
+    pub fn expand(&self, raw: &str) -> Result<Vec<Spanned<Chunk>>, String> {
+        let mut source = String::new();
+        for def in &self.strings {
+            let _ = writeln!(source, "{}", def.to_bibtex());
+        }
+        let _ = write!(source, "@misc{{{}, value = {}}}", EXPAND_KEY, raw);
+
+        let bib = Bibliography::parse(&source).map_err(|e| e.to_string())?;
+        bib.get(EXPAND_KEY)
+            .and_then(|entry| entry.fields.get("value"))
+            .cloned()
+            .ok_or_else(|| format!("{} is not a valid value", raw))
+    }
+
+    /// Preambles and string definitions, for writing a file from scratch.
+    pub fn header(&self) -> String {
+        let mut out = String::new();
+        for preamble in &self.preambles {
+            out.push_str(preamble);
+            out.push('\n');
+        }
+        for def in &self.strings {
+            out.push_str(&def.to_bibtex());
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Splits at `sep` outside braces and quotes.
+fn split_top_level(text: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut start = 0;
+
+    for (i, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '"' if depth == 0 => quoted = !quoted,
+            c if c == sep && depth == 0 && !quoted => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// The macro names in a value expression: the `#`-separated parts that
+/// are neither braced, quoted nor a number.
+fn macro_names(value: &str) -> impl Iterator<Item = &str> {
+    split_top_level(value, '#').into_iter().filter_map(|part| {
+        let part = part.trim();
+        let literal = part.is_empty()
+            || part.starts_with('{')
+            || part.starts_with('"')
+            || part.chars().all(|c| c.is_ascii_digit());
+        (!literal).then_some(part)
+    })
+}
+
+fn uses_macro(value: &str) -> bool {
+    macro_names(value).next().is_some()
+}
+
+// ----------------------------------------------------------------------------
+// Handlers
+// ----------------------------------------------------------------------------
+
+/// Applies the string definitions from the dialog. Fields that use a
+/// changed macro, and were not edited since, get its new expansion.
+pub fn update_definitions(model: &mut AppModel, strings: Vec<(String, String)>) {
+    let mut defs = model.definitions.clone();
+
+    // A macro still in use cannot go: the file would no longer parse
+    let in_use: Vec<String> = defs
+        .strings
+        .iter()
+        .filter(|def| {
+            !strings
+                .iter()
+                .any(|(name, _)| name.eq_ignore_ascii_case(&def.name))
+        })
+        .filter(|def| defs.usage(&def.name) > 0)
+        .map(|def| format!("{} ({} fields)", def.name, defs.usage(&def.name)))
+        .collect();
+    if !in_use.is_empty() {
+        model.alert.emit(AlertMsg::Show(format!(
+            "These strings are still used and cannot be removed:\n{}",
+            in_use.join("\n")
+        )));
+        return;
+    }
+
+    defs.strings = strings
+        .into_iter()
+        .map(|(name, value)| {
+            let original = model
+                .definitions
+                .string(&name)
+                .and_then(|def| def.original.clone());
+            StringDef {
+                name,
+                value,
+                original,
+            }
+        })
+        .collect();
+    if defs.strings == model.definitions.strings {
+        return;
+    }
+    // Parsing an empty value checks every definition
+    if let Err(e) = defs.expand("{}") {
+        model.alert.emit(AlertMsg::Show(format!(
+            "The strings were not changed, a definition is not valid BibTeX:\n{}",
+            e
+        )));
+        return;
+    }
+
+    // Re-expand every reference with the new values
+    let mut updates = Vec::new();
+    for (key, refs) in &defs.references {
+        for (field, reference) in refs {
+            match defs.expand(&reference.raw) {
+                Ok(chunks) => updates.push((key.clone(), field.clone(), chunks)),
+                Err(e) => {
+                    model.alert.emit(AlertMsg::Show(format!(
+                        "The strings were not changed: {} in {} no longer expands.\n{}",
+                        field, key, e
+                    )));
+                    return;
+                }
+            }
+        }
+    }
+
+    model.push_snapshot();
+    let mut changed = 0;
+    for (key, field, chunks) in updates {
+        let Some(reference) = defs
+            .references
+            .get_mut(&key)
+            .and_then(|refs| refs.get_mut(&field))
+        else {
+            continue;
+        };
+        let Some(entry) = model.bibliography.get_mut(&key) else {
+            continue;
+        };
+        let current = entry
+            .fields
+            .get(field.as_str())
+            .map(|c| core::bib_to_string(c));
+        let expanded = core::bib_to_string(&chunks);
+        // Edited by hand: the field keeps its own text
+        if current.as_deref() != Some(reference.expanded.as_str()) || reference.expanded == expanded
+        {
+            continue;
+        }
+        entry.fields.insert(field, chunks);
+        reference.expanded = expanded;
+        changed += 1;
+    }
+    model.definitions = defs;
+
+    // Undo puts the old definitions back along with the fields
+    if changed > 0 {
+        crate::logic::library::refresh_ui_list(model);
+    }
+    model.is_dirty = true;
+    model.sidebar.emit(SidebarMsg::SetStatus(format!(
+        "Updated string definitions ({} fields changed).",
+        changed
+    )));
+}
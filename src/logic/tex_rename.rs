@@ -0,0 +1,332 @@
+// src/logic/tex_rename.rs
+//
+// Carries citation key renames over into the LaTeX sources of a project:
+// finds every \cite-style command that uses an old key, previews the
+// edits as a diff and writes each touched file atomically.
+
+use crate::app::alert::AlertMsg;
+use crate::app::AppModel;
+use crate::core;
+use crate::ui::sidebar::SidebarMsg;
+use crate::ui::tex_rename_dialog::TexRenameDialogMsg;
+use relm4::ComponentController;
+use relm4_components::open_dialog::{OpenDialogMsg, OpenDialogResponse, SingleSelection};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// A planned rewrite of one .tex file.
+#[derive(Debug, Clone)]
+pub struct TexEdit {
+    pub path: PathBuf,
+    pub original: String,
+    pub updated: String,
+    pub replacements: usize,
+}
+
+// ----------------------------------------------------------------------------
+// 1. Citation Scanner
+// ----------------------------------------------------------------------------
+
+/// Rewrites the keys inside citation commands (\cite, \citep, \autocite,
+/// \nocite, \parencite, \textcites, ...), outside of `%` comments. Returns
+/// the new text and the number of keys replaced.
+pub fn rewrite_citations(text: &str, renames: &BTreeMap<String, String>) -> (String, usize) {
+    let bytes = text.as_bytes();
+    let mut out = String::with_capacity(text.len());
+    let mut count = 0;
+    let mut copied = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        // A comment runs to the end of the line; "\%" is skipped below
+        if bytes[i] == b'%' {
+            while i < bytes.len() && bytes[i] != b'\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if bytes[i] != b'\\' {
+            i += 1;
+            continue;
+        }
+
+        // 1. Command name
+        let start = i + 1;
+        let mut end = start;
+        while end < bytes.len() && bytes[end].is_ascii_alphabetic() {
+            end += 1;
+        }
+        if end == start {
+            // Escaped character such as "\\" or "\%"
+            i = end + 1;
+            continue;
+        }
+        let name = text[start..end].to_ascii_lowercase();
+        i = end;
+        if !name.contains("cite") {
+            continue;
+        }
+
+        // Multicite commands (\cites, \parencites) take several key groups
+        let multi = name.ends_with('s');
+        if bytes.get(i) == Some(&b'*') {
+            i += 1;
+        }
+
+        // 2. Optional arguments, then the {key,key} group(s). Once a key
+        //    group was read, only directly adjacent groups belong to it.
+        let mut seen_keys = false;
+        loop {
+            let mut j = i;
+            while !seen_keys && j < bytes.len() && bytes[j].is_ascii_whitespace() {
+                j += 1;
+            }
+            let close = match bytes.get(j) {
+                Some(b'[') => find_byte(bytes, j, b']'),
+                Some(b'(') if multi => find_byte(bytes, j, b')'),
+                Some(b'{') => find_byte(bytes, j, b'}'),
+                _ => None,
+            };
+            let Some(close) = close else { break };
+
+            if bytes[j] == b'{' {
+                let (keys, n) = rename_keys(&text[j + 1..close], renames);
+                if n > 0 {
+                    out.push_str(&text[copied..j + 1]);
+                    out.push_str(&keys);
+                    copied = close;
+                    count += n;
+                }
+                i = close + 1;
+                seen_keys = true;
+                if !multi {
+                    break;
+                }
+            } else {
+                i = close + 1;
+            }
+        }
+    }
+
+    out.push_str(&text[copied..]);
+    (out, count)
+}
+
+/// Index of the first `close` byte after `open`.
+fn find_byte(bytes: &[u8], open: usize, close: u8) -> Option<usize> {
+    bytes[open + 1..]
+        .iter()
+        .position(|&b| b == close)
+        .map(|p| open + 1 + p)
+}
+
+/// Renames the keys of a "key1, key2" list, keeping the original spacing.
+fn rename_keys(list: &str, renames: &BTreeMap<String, String>) -> (String, usize) {
+    let mut count = 0;
+    let parts: Vec<String> = list
+        .split(',')
+        .map(|part| {
+            let key = part.trim();
+            match renames.get(key) {
+                Some(new_key) if !key.is_empty() => {
+                    count += 1;
+                    part.replacen(key, new_key, 1)
+                }
+                _ => part.to_string(),
+            }
+        })
+        .collect();
+    (parts.join(","), count)
+}
+
+// ----------------------------------------------------------------------------
+// 2. Planning & Preview
+// ----------------------------------------------------------------------------
+
+/// Recursively collects the .tex files below `dir`, skipping hidden folders.
+pub fn find_tex_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(current) = pending.pop() {
+        for item in std::fs::read_dir(&current)? {
+            let item = item?;
+            let path = item.path();
+            let file_type = item.file_type()?;
+            let hidden = item.file_name().to_string_lossy().starts_with('.');
+
+            if file_type.is_dir() && !hidden {
+                pending.push(path);
+            } else if file_type.is_file() && path.extension().map_or(false, |e| e == "tex") {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+pub fn plan_edits(dir: &Path, renames: &BTreeMap<String, String>) -> std::io::Result<Vec<TexEdit>> {
+    let mut edits = Vec::new();
+
+    for path in find_tex_files(dir)? {
+        let original = std::fs::read_to_string(&path)?;
+        let (updated, replacements) = rewrite_citations(&original, renames);
+        if replacements > 0 {
+            edits.push(TexEdit {
+                path,
+                original,
+                updated,
+                replacements,
+            });
+        }
+    }
+
+    Ok(edits)
+}
+
+/// Line diff of the planned edits. Renames never add or remove lines,
+/// so old and new lines can be compared pairwise.
+pub fn diff_preview(dir: &Path, edits: &[TexEdit]) -> String {
+    let mut out = String::new();
+
+    for edit in edits {
+        let name = edit.path.strip_prefix(dir).unwrap_or(&edit.path).display();
+        out.push_str(&format!("--- {}\n+++ {}\n", name, name));
+
+        for (n, (old, new)) in edit.original.lines().zip(edit.updated.lines()).enumerate() {
+            if old != new {
+                out.push_str(&format!("@@ line {} @@\n-{}\n+{}\n", n + 1, old, new));
+            }
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Writes the edits one file at a time, each through `atomic_write`, so a
+/// file is either updated or left as it was. Nothing is written if a file
+/// changed on disk since the preview; a failure stops the run, and the
+/// error says which files were updated and which were not.
+pub fn apply_edits(edits: &[TexEdit]) -> Result<usize, String> {
+    // 1. Make sure nobody edited the sources since the preview
+    for edit in edits {
+        let current = std::fs::read_to_string(&edit.path)
+            .map_err(|e| format!("{}: {}\nNo files were changed.", edit.path.display(), e))?;
+        if current != edit.original {
+            return Err(format!(
+                "{} changed on disk since the preview.\nNo files were changed.",
+                edit.path.display()
+            ));
+        }
+    }
+
+    // 2. Write them
+    for (n, edit) in edits.iter().enumerate() {
+        match core::atomic_write(&edit.path, &edit.updated) {
+            Ok(_) => {}
+            // In place; only the folder flush failed
+            Err(e) if e.file_replaced() => {}
+            Err(e) => {
+                let list = |edits: &[TexEdit]| {
+                    edits
+                        .iter()
+                        .map(|edit| format!("\n  {}", edit.path.display()))
+                        .collect::<String>()
+                };
+                let updated = match n {
+                    0 => "\nNo files were updated.".to_string(),
+                    _ => format!("\nUpdated:{}", list(&edits[..n])),
+                };
+                return Err(format!(
+                    "{}: {}{}\nNot updated:{}",
+                    edit.path.display(),
+                    e,
+                    updated,
+                    list(&edits[n..])
+                ));
+            }
+        }
+    }
+
+    Ok(edits.iter().map(|e| e.replacements).sum())
+}
+
+// ----------------------------------------------------------------------------
+// 3. App Handlers
+// ----------------------------------------------------------------------------
+
+/// Renames that still apply to the current library: the new key must
+/// exist and the old one must not have been reused (e.g. after Undo).
+fn active_renames(model: &AppModel) -> BTreeMap<String, String> {
+    model
+        .key_renames
+        .iter()
+        .filter(|(old, new)| {
+            model.bibliography.get(new).is_some() && model.bibliography.get(old).is_none()
+        })
+        .map(|(old, new)| (old.clone(), new.clone()))
+        .collect()
+}
+
+pub fn trigger(model: &mut AppModel) {
+    if active_renames(model).is_empty() {
+        model.alert.emit(AlertMsg::ShowInfo(
+            "No renamed keys.\nRename or regenerate keys first, then update the LaTeX sources.\n\
+             Renames made before MkBib was restarted are not remembered."
+                .into(),
+        ));
+        return;
+    }
+    model.tex_folder_dialog.emit(OpenDialogMsg::Open);
+}
+
+pub fn handle_folder_response(model: &mut AppModel, resp: OpenDialogResponse<SingleSelection>) {
+    let OpenDialogResponse::Accept(dir) = resp else {
+        return;
+    };
+
+    let renames = active_renames(model);
+    match plan_edits(&dir, &renames) {
+        Ok(edits) if edits.is_empty() => {
+            model.alert.emit(AlertMsg::ShowInfo(format!(
+                "No citations of the {} renamed keys found in {}.",
+                renames.len(),
+                dir.display()
+            )));
+        }
+        Ok(edits) => {
+            let preview = diff_preview(&dir, &edits);
+            model
+                .tex_rename_dialog
+                .emit(TexRenameDialogMsg::Open(edits, preview));
+        }
+        Err(e) => {
+            model.alert.emit(AlertMsg::Show(format!(
+                "Could not scan {}:\n{}",
+                dir.display(),
+                e
+            )));
+        }
+    }
+}
+
+pub fn apply(model: &mut AppModel, edits: Vec<TexEdit>) {
+    match apply_edits(&edits) {
+        Ok(count) => {
+            model.key_renames.clear();
+            model.sidebar.emit(SidebarMsg::SetStatus(format!(
+                "Updated {} citations in {} files. Renames made before a restart are not included.",
+                count,
+                edits.len()
+            )));
+        }
+        Err(e) => {
+            model
+                .alert
+                .emit(AlertMsg::Show(format!("Updating citations failed:\n{}", e)));
+        }
+    }
+}
@@ -1,16 +1,15 @@
 // src/logic/undo.rs
 use crate::app::AppModel;
-use crate::ui::row::BibEntry;
 use crate::ui::sidebar::SidebarMsg;
 use relm4::ComponentController;
 
 pub fn perform_undo(model: &mut AppModel) {
     if let Some(previous_state) = model.undo_stack.pop_back() {
         // 1. Move CURRENT state to Redo stack
-        model.redo_stack.push_back(model.bibliography.clone());
+        model.redo_stack.push_back(model.snapshot());
 
         // 2. Load PREVIOUS state
-        model.bibliography = previous_state;
+        model.restore_snapshot(previous_state);
 
         // 3. Refresh UI
         refresh_ui(model);
@@ -28,10 +27,10 @@ pub fn perform_undo(model: &mut AppModel) {
 pub fn perform_redo(model: &mut AppModel) {
     if let Some(next_state) = model.redo_stack.pop_back() {
         // 1. Move CURRENT state to Undo stack
-        model.undo_stack.push_back(model.bibliography.clone());
+        model.undo_stack.push_back(model.snapshot());
 
         // 2. Load NEXT state
-        model.bibliography = next_state;
+        model.restore_snapshot(next_state);
 
         // 3. Refresh UI
         refresh_ui(model);
@@ -48,9 +47,6 @@ pub fn perform_redo(model: &mut AppModel) {
 
 // Helper to rebuild the list
 fn refresh_ui(model: &mut AppModel) {
-    model.entries.guard().clear();
-    for entry in model.bibliography.iter() {
-        model.entries.guard().push_back(BibEntry::from_entry(entry));
-    }
+    crate::logic::library::refresh_ui_list(model);
     model.is_dirty = true;
 }
@@ -1,5 +1,7 @@
 // src/menu/actions_edit.rs
 use crate::app::{AppModel, AppMsg};
+use crate::core::config::Dialect;
+use crate::core::latex::TextEncoding;
 use gtk4 as gtk;
 use gtk4::gio;
 use gtk4::glib::clone;
@@ -16,6 +18,13 @@ pub fn init(root: &gtk::ApplicationWindow, sender: ComponentSender<AppModel>) {
     }));
     group.add_action(&action_prefs);
 
+    // Action: strings
+    let action_strings = gio::SimpleAction::new("strings", None);
+    action_strings.connect_activate(clone!(@strong sender => move |_, _| {
+        sender.input(AppMsg::ShowStrings);
+    }));
+    group.add_action(&action_strings);
+
     // Action: regenerate_keys
     let action_regen = gio::SimpleAction::new("regenerate_keys", None);
     action_regen.connect_activate(clone!(@strong sender => move |_, _| {
@@ -23,6 +32,20 @@ pub fn init(root: &gtk::ApplicationWindow, sender: ComponentSender<AppModel>) {
     }));
     group.add_action(&action_regen);
 
+    // Action: regenerate_mismatched_keys
+    let action_regen_mismatched = gio::SimpleAction::new("regenerate_mismatched_keys", None);
+    action_regen_mismatched.connect_activate(clone!(@strong sender => move |_, _| {
+        sender.input(AppMsg::RegenerateMismatchedKeys);
+    }));
+    group.add_action(&action_regen_mismatched);
+
+    // Action: update_tex_citations
+    let action_tex = gio::SimpleAction::new("update_tex_citations", None);
+    action_tex.connect_activate(clone!(@strong sender => move |_, _| {
+        sender.input(AppMsg::UpdateTexCitations);
+    }));
+    group.add_action(&action_tex);
+
     // --- Action: scan_duplicates ---
     let action_scan = gio::SimpleAction::new("scan_duplicates", None);
     action_scan.connect_activate(clone!(@strong sender => move |_, _| {
@@ -42,6 +65,36 @@ pub fn init(root: &gtk::ApplicationWindow, sender: ComponentSender<AppModel>) {
     }));
     group.add_action(&action_unabbr);
 
+    let action_unicode = gio::SimpleAction::new("convert_unicode", None);
+    action_unicode.connect_activate(clone!(@strong sender => move |_, _| {
+        sender.input(AppMsg::ConvertText(TextEncoding::Unicode));
+    }));
+    group.add_action(&action_unicode);
+
+    let action_latex = gio::SimpleAction::new("convert_latex", None);
+    action_latex.connect_activate(clone!(@strong sender => move |_, _| {
+        sender.input(AppMsg::ConvertText(TextEncoding::Latex));
+    }));
+    group.add_action(&action_latex);
+
+    let action_bibtex = gio::SimpleAction::new("convert_bibtex", None);
+    action_bibtex.connect_activate(clone!(@strong sender => move |_, _| {
+        sender.input(AppMsg::ConvertDialect(Dialect::Bibtex));
+    }));
+    group.add_action(&action_bibtex);
+
+    let action_biblatex = gio::SimpleAction::new("convert_biblatex", None);
+    action_biblatex.connect_activate(clone!(@strong sender => move |_, _| {
+        sender.input(AppMsg::ConvertDialect(Dialect::Biblatex));
+    }));
+    group.add_action(&action_biblatex);
+
+    let action_sort = gio::SimpleAction::new("sort_library", None);
+    action_sort.connect_activate(clone!(@strong sender => move |_, _| {
+        sender.input(AppMsg::SortLibrary);
+    }));
+    group.add_action(&action_sort);
+
     // CRITICAL FIX: Use "edit" group to avoid overwriting "win" group from File actions
     root.insert_action_group("edit", Some(&group));
 }
@@ -28,6 +28,20 @@ pub fn init(root: &gtk::ApplicationWindow, sender: ComponentSender<AppModel>) {
     }));
     root.add_action(&action_save_as);
 
+    // --- RESTORE BACKUP ---
+    let action_restore = gio::SimpleAction::new("restore_backup", None);
+    action_restore.connect_activate(clone!(@strong sender => move |_, _| {
+        sender.input(AppMsg::ShowBackups);
+    }));
+    root.add_action(&action_restore);
+
+    // --- REFORMAT ---
+    let action_reformat = gio::SimpleAction::new("reformat_all", None);
+    action_reformat.connect_activate(clone!(@strong sender => move |_, _| {
+        sender.input(AppMsg::ReformatAll);
+    }));
+    root.add_action(&action_reformat);
+
     // --- QUIT ---
     let action_quit = gio::SimpleAction::new("quit", None);
     action_quit.connect_activate(move |_, _| {
@@ -2,7 +2,19 @@
 
 use crate::app::alert::AlertMsg;
 use crate::app::{AppModel, AppMsg};
-use crate::ui::row::BibEntry;
+use crate::core::backup;
+use crate::core::lock::{self, FileLock, LockError};
+use crate::core::recovery::{self, RecoveryFile};
+use crate::logic::diff;
+use crate::logic::external::{self, DiskStamp};
+use crate::logic::library;
+use crate::logic::loader::{self, Loaded};
+use crate::logic::merger::EntryIds;
+use crate::logic::scanner::{self, Problem};
+use crate::logic::strings::Definitions;
+use crate::ui::backup_dialog::BackupDialogMsg;
+use crate::ui::lock_dialog::LockDialogMsg;
+use crate::ui::review_dialog::ReviewDialogMsg;
 use crate::ui::sidebar::SidebarMsg;
 use biblatex::Bibliography;
 use relm4::{ComponentController, ComponentSender};
@@ -16,58 +28,143 @@ pub fn handle_open_response(
     _sender: ComponentSender<AppModel>,
 ) {
     if let OpenDialogResponse::Accept(path) = resp {
-        if let Ok(content) = std::fs::read_to_string(&path) {
-            model.sidebar.emit(SidebarMsg::SetStatus(format!(
-                "Loading {}...",
-                path.display()
-            )));
-
-            // Capture original content for the Merger (Step 1 - Coming next)
-            // For now, we are still using the Rewrite strategy, but we prep the field.
-            model.original_file_content = Some(content.clone());
+        // Reopening our own file: the lock we hold stays ours
+        if model.current_file_path.as_ref() == Some(&path) && model.file_lock.is_some() {
+            let file_lock = model.file_lock.take();
+            load_library(model, path, file_lock);
+            return;
+        }
 
-            match Bibliography::parse(&content) {
-                Ok(bib) => {
-                    let count = bib.len();
-                    model.bibliography = bib;
-                    model.current_file_path = Some(path.clone());
+        match lock::acquire(&path, false) {
+            Ok(file_lock) => {
+                load_library(model, path, Some(file_lock));
+            }
+            Err(LockError::Held(holder)) => {
+                model.lock_dialog.emit(LockDialogMsg::Open(path, holder));
+            }
+            Err(e) => {
+                // e.g. a read-only share: open it, just without protection
+                if load_library(model, path, None) {
+                    model.sidebar.emit(SidebarMsg::SetStatus(format!(
+                        "{}. Other MkBib sessions will not see this file as open.",
+                        e
+                    )));
+                }
+            }
+        }
+    }
+}
 
-                    model.undo_stack.clear();
-                    model.redo_stack.clear();
-                    model.is_dirty = false;
+/// Second step of opening a file someone else has locked.
+pub fn open_locked(model: &mut AppModel, path: PathBuf, force: bool) {
+    if !force {
+        if load_library(model, path, None) {
+            model.read_only = true;
+            model.sidebar.emit(SidebarMsg::SetStatus(
+                "Opened read-only. Use Save As to keep your changes.".into(),
+            ));
+        }
+        return;
+    }
 
-                    model.entries.guard().clear();
-                    for entry in model.bibliography.iter() {
-                        model.entries.guard().push_back(BibEntry::from_entry(entry));
-                    }
+    match lock::acquire(&path, true) {
+        Ok(file_lock) => {
+            load_library(model, path, Some(file_lock));
+        }
+        Err(e) => model.alert.emit(AlertMsg::Show(format!("{}", e))),
+    }
+}
 
-                    model
-                        .sidebar
-                        .emit(SidebarMsg::SetStatus(format!("Loaded {} entries.", count)));
-                }
-                Err(e) => {
-                    model
-                        .alert
-                        .emit(AlertMsg::Show(format!("Parse Error:\n{}", e)));
-                }
-            }
-        } else {
-            model
-                .alert
-                .emit(AlertMsg::Show("Failed to read file.".into()));
+/// Replaces the library with the file at `path`. Returns false (and
+/// leaves the current library alone) if the file cannot be read.
+fn load_library(model: &mut AppModel, path: PathBuf, file_lock: Option<FileLock>) -> bool {
+    let Ok(content) = std::fs::read_to_string(&path) else {
+        // Rereading the open file: its lock is still needed
+        if model.current_file_path.as_ref() == Some(&path) && model.file_lock.is_none() {
+            model.file_lock = file_lock;
         }
+        model
+            .alert
+            .emit(AlertMsg::Show("Failed to read file.".into()));
+        return false;
+    };
+
+    model.sidebar.emit(SidebarMsg::SetStatus(format!(
+        "Loading {}...",
+        path.display()
+    )));
+
+    // Capture original content for the Merger (Step 1 - Coming next)
+    // For now, we are still using the Rewrite strategy, but we prep the field.
+    model.original_file_content = Some(content.clone());
+
+    // Entries that cannot be read are kept as written and listed as errors
+    let Loaded { bib, malformed } = loader::load(&content);
+    let count = bib.len();
+    model.definitions = Definitions::scan(&content, &bib);
+    model.definitions.malformed = malformed;
+    model.entry_ids = EntryIds::assign(&bib);
+    model.bibliography = bib;
+    model.current_file_path = Some(path.clone());
+    model.disk_stamp = DiskStamp::of(&path);
+    model.pending_merge = None;
+    model.file_lock = file_lock;
+    model.read_only = false;
+    reload_library_config(model);
+
+    model.undo_stack.clear();
+    model.redo_stack.clear();
+    model.key_renames.clear();
+    model.is_dirty = false;
+
+    library::refresh_ui_list(model);
+
+    let status = match model.definitions.malformed.len() {
+        0 => format!("Loaded {} entries.", count),
+        n => format!(
+            "Loaded {} entries. {} could not be read: they are marked in the list and saved as written.",
+            count, n
+        ),
+    };
+    model.sidebar.emit(SidebarMsg::SetStatus(status));
+    // Saving keeps these parts as they are; say so before any edit
+    if let Some(report) = scanner::scan(&content).report() {
+        model.alert.emit(AlertMsg::ShowInfo(report));
+    }
+    true
+}
+
+/// Recomputes `model.config` from the global settings and the project
+/// file (.mkbib.toml) that applies to the current library, if any.
+pub fn reload_library_config(model: &mut AppModel) {
+    model.config = model.global_config.clone();
+
+    let Some(project) = model
+        .current_file_path
+        .as_deref()
+        .and_then(crate::core::config::find_project_file)
+    else {
+        return;
+    };
+
+    match crate::core::config::load_project(&project, &model.global_config) {
+        Ok(config) => model.config = config,
+        Err(e) => model.alert.emit(AlertMsg::Show(format!(
+            "Library settings ignored, using your preferences instead.\n{}",
+            e
+        ))),
     }
 }
 
 pub fn handle_save_response(model: &mut AppModel, resp: SaveDialogResponse) {
     if let SaveDialogResponse::Accept(path) = resp {
-        perform_safe_save(model, path);
+        perform_safe_save(model, path, false);
     }
 }
 
 pub fn trigger_save(model: &mut AppModel) {
     if let Some(path) = &model.current_file_path {
-        perform_safe_save(model, path.clone());
+        perform_safe_save(model, path.clone(), false);
     } else {
         model
             .save_dialog
@@ -75,58 +172,312 @@ pub fn trigger_save(model: &mut AppModel) {
     }
 }
 
+/// Saves after the user went through the changes in the review dialog.
+pub fn confirm_save(model: &mut AppModel, path: PathBuf) {
+    perform_safe_save(model, path, true);
+}
+
 /// ✅ THE DIAMOND STANDARD SAVE FUNCTION
-fn perform_safe_save(model: &mut AppModel, path: PathBuf) {
+fn perform_safe_save(model: &mut AppModel, path: PathBuf, reviewed: bool) {
+    // 0. Never overwrite edits made by another program without merging them
+    let same_file = model.current_file_path.as_ref() == Some(&path);
+    if same_file && model.read_only {
+        model.alert.emit(AlertMsg::ShowInfo(
+            "This library is open read-only because another MkBib session is editing it.\n\
+             Use Save As to save a copy."
+                .into(),
+        ));
+        return;
+    }
+    if same_file && external::changed_before_save(model) {
+        return;
+    }
+
     // 1. Generate Content
     let output = if let Some(original) = &model.original_file_content {
         crate::logic::merger::merge_bibliography_into_source(
             original,
             &model.bibliography,
-            &model.key_config,
+            &model.definitions,
+            &model.entry_ids,
+            &model.config.formatting,
         )
     } else {
         crate::logic::merger::merge_bibliography_into_source(
             "",
             &model.bibliography,
-            &model.key_config,
+            &model.definitions,
+            &model.entry_ids,
+            &model.config.formatting,
         )
     };
 
+    // Duplicates, and text that could not be read (listed as errors)
+    let duplicates = model.original_file_content.as_deref().map_or(0, |text| {
+        scanner::scan(text)
+            .problems
+            .iter()
+            .filter(|p| matches!(p, Problem::DuplicateKey { .. }))
+            .count()
+    });
+    let left_alone = duplicates
+        + model
+            .definitions
+            .malformed
+            .iter()
+            .filter(|block| block.is_open(&model.bibliography))
+            .count();
+
     // ✅ FIX: Use trim_end() to remove ALL trailing newlines first.
     // format!("{}\n", ...) ensures exactly ONE newline exists at the end.
     let final_output = format!("{}\n", output.trim_end());
 
+    // Show what is about to change first, if asked to; the dialog's Save
+    // button comes back here
+    if model.config.formatting.review_changes && !reviewed {
+        let before = std::fs::read_to_string(&path).unwrap_or_default();
+        let changes = diff::diff_sources(&before, &final_output, |key| {
+            model.entry_ids.source_key(key).map(str::to_string)
+        });
+        if !changes.is_empty() {
+            model
+                .review_dialog
+                .emit(ReviewDialogMsg::Open(path, changes));
+            return;
+        }
+    }
+
+    // Saving to a new place: lock it first, unless someone is editing it
+    let new_lock = if same_file {
+        None
+    } else {
+        match lock::acquire(&path, false) {
+            Ok(file_lock) => Some(file_lock),
+            Err(LockError::Held(holder)) => {
+                model.alert.emit(AlertMsg::Show(format!(
+                    "{} is open in another MkBib session ({}).",
+                    path.display(),
+                    holder
+                )));
+                return;
+            }
+            Err(_) => None,
+        }
+    };
+
     // 2. Create Backup
-    if let Err(e) = crate::core::create_backup(&path) {
+    if let Err(e) = crate::core::backup::create_backup(&path, &model.config.backup) {
         println!("Backup warning: {}", e);
         // We log to console/stdout instead of blocking the user with an alert
         // because backups failing shouldn't stop the user from saving their work.
     }
 
-    // 3. ATOMIC WRITE (Write to .tmp -> Rename to .bib)
-    let tmp_path = path.with_extension("bib.tmp");
+    // 3. ATOMIC WRITE (Write to .tmp -> fsync -> Rename to .bib -> fsync folder)
+    let written = match crate::core::atomic_write(&path, &final_output) {
+        Ok(_) => Ok(None),
+        // The new file is in place; only the folder flush failed
+        Err(e) if e.file_replaced() => Ok(Some(e)),
+        Err(e) => Err(e),
+    };
 
-    match std::fs::write(&tmp_path, &final_output) {
-        Ok(_) => {
-            match std::fs::rename(&tmp_path, &path) {
-                Ok(_) => {
-                    model.current_file_path = Some(path.clone());
-                    // Update internal state to match what is now on disk
-                    model.original_file_content = Some(final_output);
-                    model.is_dirty = false;
-                    model.sidebar.emit(SidebarMsg::SetStatus(format!(
-                        "Saved to {}",
-                        path.display()
-                    )));
-                }
-                Err(e) => model
-                    .alert
-                    .emit(AlertMsg::Show(format!("Rename failed: {}", e))),
+    match written {
+        Ok(warning) => {
+            // The edits are safe on disk now, drop the autosave
+            recovery::clear(model.current_file_path.as_deref());
+            recovery::clear(Some(&path));
+
+            model.current_file_path = Some(path.clone());
+            model.disk_stamp = DiskStamp::of(&path);
+            if !same_file {
+                model.file_lock = new_lock;
+                model.read_only = false;
+                reload_library_config(model);
+            }
+            // Update internal state to match what is now on disk
+            model
+                .definitions
+                .mark_saved(&model.bibliography, &final_output);
+            model.original_file_content = Some(final_output);
+            model.entry_ids.saved(&model.bibliography);
+            model.is_dirty = false;
+            let mut status = match warning {
+                None => format!("Saved to {}", path.display()),
+                Some(e) => format!("Saved to {}, but {}", path.display(), e),
+            };
+            if left_alone > 0 {
+                status.push_str(&format!(
+                    ". {} unreadable or duplicate block(s) were kept as written.",
+                    left_alone
+                ));
             }
+            model.sidebar.emit(SidebarMsg::SetStatus(status));
         }
         Err(e) => model
             .alert
-            .emit(AlertMsg::Show(format!("Write failed: {}", e))),
+            .emit(AlertMsg::Show(format!("Save failed: {}", e))),
+    }
+}
+
+pub fn show_backups(model: &mut AppModel) {
+    let Some(path) = &model.current_file_path else {
+        model.alert.emit(AlertMsg::ShowInfo(
+            "No backups yet.\nBackups are taken each time the library is saved.".into(),
+        ));
+        return;
+    };
+
+    let backups = backup::list_backups(path, &model.config.backup);
+    if backups.is_empty() {
+        model.alert.emit(AlertMsg::ShowInfo(format!(
+            "No backups found in {}.",
+            backup::backup_dir(path, &model.config.backup).display()
+        )));
+        return;
+    }
+
+    model
+        .backup_dialog
+        .emit(BackupDialogMsg::Open(backups, model.bibliography.clone()));
+}
+
+/// Replaces the library with a backup. Nothing is written to disk until
+/// the next save, and the restore can be undone.
+pub fn restore_backup(model: &mut AppModel, path: PathBuf, content: String) {
+    model.push_snapshot();
+
+    // Its strings and preambles come back with it; saving still writes
+    // into the library file
+    let Loaded { bib, malformed } = loader::load(&content);
+    model.definitions = Definitions::scan(&content, &bib);
+    model.definitions.malformed = malformed;
+    model
+        .definitions
+        .compare_with(model.original_file_content.as_deref().unwrap_or(""));
+
+    let count = bib.len();
+    model.bibliography = bib;
+    library::refresh_ui_list(model);
+
+    model.is_dirty = true;
+    model.sidebar.emit(SidebarMsg::SetStatus(format!(
+        "Restored {} entries from {}. Save to keep them.",
+        count,
+        path.file_name().unwrap_or_default().to_string_lossy()
+    )));
+}
+
+/// Saving normally rewrites only the entries that were added or edited.
+/// This makes the next save format every entry with the current settings.
+pub fn reformat_all(model: &mut AppModel) {
+    model.entry_ids.mark_all_modified();
+    model.is_dirty = true;
+    model.sidebar.emit(SidebarMsg::SetStatus(
+        "Every entry will be reformatted on the next save.".into(),
+    ));
+}
+
+/// Writes unsaved edits to the recovery file once per configured interval.
+/// The library file itself is left alone.
+pub fn autosave(model: &mut AppModel) {
+    let settings = &model.config.recovery;
+    // A read-only session would overwrite the lock holder's recovery file
+    if !settings.enabled || !model.is_dirty || model.read_only {
+        return;
+    }
+    let interval = std::time::Duration::from_secs(settings.interval_secs.max(5));
+    if model
+        .last_autosave
+        .map_or(false, |t| t.elapsed() < interval)
+    {
+        return;
+    }
+    model.last_autosave = Some(std::time::Instant::now());
+
+    let content = crate::logic::merger::journal(
+        model.original_file_content.as_deref().unwrap_or(""),
+        &model.bibliography,
+        &model.definitions,
+        &model.entry_ids,
+    );
+    if let Err(e) = recovery::write(model.current_file_path.as_deref(), &content) {
+        model
+            .sidebar
+            .emit(SidebarMsg::SetStatus(format!("Autosave failed: {}", e)));
+    }
+}
+
+/// Loads the edits from a recovery file as unsaved changes to its library.
+pub fn restore_recovery(model: &mut AppModel, file: RecoveryFile) {
+    // Autosaves keep what could not be read, so it can be read again
+    let Loaded { bib, malformed } = loader::load(&file.content);
+
+    // The library on disk (if it still exists) stays the base for saving
+    model.original_file_content = file
+        .library
+        .as_ref()
+        .and_then(|p| std::fs::read_to_string(p).ok());
+    model.disk_stamp = file.library.as_deref().and_then(DiskStamp::of);
+    model.current_file_path = file.library.clone();
+    model.pending_merge = None;
+    model.read_only = false;
+    model.file_lock = None;
+    if let Some(library) = file.library.as_deref().filter(|p| p.exists()) {
+        match lock::acquire(library, false) {
+            Ok(file_lock) => model.file_lock = Some(file_lock),
+            Err(LockError::Held(holder)) => {
+                model.read_only = true;
+                model.alert.emit(AlertMsg::ShowInfo(format!(
+                    "{} is open in another MkBib session ({}).\n\
+                     The recovered changes are read-only; use Save As to keep them.",
+                    library.display(),
+                    holder
+                )));
+            }
+            Err(_) => {}
+        }
+    }
+    reload_library_config(model);
+
+    // Strings are compared with the library on disk, which saving rewrites
+    model.definitions = Definitions::scan(&file.content, &bib);
+    model.definitions.malformed = malformed;
+    model
+        .definitions
+        .compare_with(model.original_file_content.as_deref().unwrap_or(""));
+
+    let count = bib.len();
+    // Recovered entries that match the library on disk are not rewritten
+    model.entry_ids = model
+        .original_file_content
+        .as_deref()
+        .map(|text| EntryIds::assign(&loader::load(text).bib))
+        .unwrap_or_default();
+    model.bibliography = bib;
+    model.undo_stack.clear();
+    model.redo_stack.clear();
+    model.key_renames.clear();
+    model.is_dirty = true;
+
+    library::refresh_ui_list(model);
+
+    model.sidebar.emit(SidebarMsg::SetStatus(format!(
+        "Recovered {} entries for {}. Save to keep them.",
+        count,
+        file.library_label()
+    )));
+}
+
+pub fn discard_recovery(model: &mut AppModel, file: RecoveryFile) {
+    match std::fs::remove_file(&file.file) {
+        Ok(_) => model.sidebar.emit(SidebarMsg::SetStatus(format!(
+            "Discarded unsaved changes for {}.",
+            file.library_label()
+        ))),
+        Err(e) => model.alert.emit(AlertMsg::Show(format!(
+            "Could not delete {}:\n{}",
+            file.file.display(),
+            e
+        ))),
     }
 }
 
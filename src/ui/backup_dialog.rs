@@ -0,0 +1,223 @@
+// src/ui/backup_dialog.rs
+use crate::core::backup::BackupInfo;
+use crate::logic::diff;
+use crate::logic::loader::{self, Loaded};
+use biblatex::Bibliography;
+use gtk4::prelude::*;
+use relm4::factory::FactoryVecDeque;
+use relm4::prelude::*;
+use std::path::PathBuf;
+
+// ----------------------------------------------------------------------------
+// Component 1: BackupRow
+// ----------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct BackupRow {
+    pub label: String,
+}
+
+#[relm4::factory(pub)]
+impl FactoryComponent for BackupRow {
+    type Init = String;
+    type Input = ();
+    type Output = ();
+    type CommandOutput = ();
+    type ParentWidget = gtk::ListBox;
+
+    view! {
+        gtk::ListBoxRow {
+            gtk::Label {
+                set_label: &self.label,
+                set_halign: gtk::Align::Start,
+                set_margin_all: 8,
+                set_css_classes: &["monospace"],
+            }
+        }
+    }
+
+    fn init_model(label: Self::Init, _index: &DynamicIndex, _sender: FactorySender<Self>) -> Self {
+        Self { label }
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Main Dialog
+// ----------------------------------------------------------------------------
+
+pub struct BackupDialogModel {
+    pub is_active: bool,
+    pub backups: Vec<BackupInfo>,
+    pub rows: FactoryVecDeque<BackupRow>,
+    pub current: Bibliography,
+    pub selected: Option<(PathBuf, String)>,
+    pub preview: String,
+}
+
+#[derive(Debug)]
+pub enum BackupDialogMsg {
+    Open(Vec<BackupInfo>, Bibliography), // (Backups newest first, Current library)
+    Select(usize),
+    Restore,
+    Close,
+}
+
+#[derive(Debug)]
+pub enum BackupDialogOutput {
+    Restore(PathBuf, String),
+}
+
+#[relm4::component(pub)]
+impl Component for BackupDialogModel {
+    type Init = ();
+    type Input = BackupDialogMsg;
+    type Output = BackupDialogOutput;
+    type CommandOutput = ();
+
+    view! {
+        gtk::Window {
+            set_modal: true,
+            set_default_width: 850,
+            set_default_height: 550,
+            set_title: Some("Restore from Backup"),
+            set_hide_on_close: true,
+            #[watch] set_visible: model.is_active,
+
+            connect_close_request[sender] => move |_| {
+                sender.input(BackupDialogMsg::Close);
+                gtk::glib::Propagation::Stop
+            },
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_margin_all: 12,
+                set_spacing: 12,
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_vexpand: true,
+                    set_spacing: 12,
+
+                    // --- Backups (newest first) ---
+                    gtk::ScrolledWindow {
+                        set_width_request: 320,
+                        gtk::Frame {
+                            #[local_ref]
+                            backups_listbox -> gtk::ListBox {
+                                set_selection_mode: gtk::SelectionMode::Single,
+                                add_css_class: "boxed-list",
+                                connect_row_selected[sender] => move |_, row| {
+                                    if let Some(row) = row {
+                                        sender.input(BackupDialogMsg::Select(row.index() as usize));
+                                    }
+                                }
+                            }
+                        }
+                    },
+
+                    // --- Changes against the current library ---
+                    gtk::ScrolledWindow {
+                        set_hexpand: true,
+                        gtk::Label {
+                            #[watch]
+                            set_label: &model.preview,
+                            add_css_class: "monospace",
+                            set_selectable: true,
+                            set_xalign: 0.0,
+                            set_yalign: 0.0,
+                            set_margin_all: 8,
+                        }
+                    },
+                },
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_halign: gtk::Align::End,
+                    set_spacing: 12,
+
+                    gtk::Button {
+                        set_label: "Cancel",
+                        connect_clicked => BackupDialogMsg::Close,
+                    },
+                    gtk::Button {
+                        set_label: "Restore",
+                        add_css_class: "suggested-action",
+                        #[watch]
+                        set_sensitive: model.selected.is_some(),
+                        connect_clicked => BackupDialogMsg::Restore,
+                    }
+                }
+            }
+        }
+    }
+
+    fn init(_: (), root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
+        let rows = FactoryVecDeque::builder()
+            .launch(gtk::ListBox::default())
+            .detach();
+
+        let model = BackupDialogModel {
+            is_active: false,
+            backups: Vec::new(),
+            rows,
+            current: Bibliography::new(),
+            selected: None,
+            preview: String::new(),
+        };
+
+        let backups_listbox = model.rows.widget();
+        let widgets = view_output!();
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>, _root: &Self::Root) {
+        match msg {
+            BackupDialogMsg::Open(backups, current) => {
+                let mut rows = self.rows.guard();
+                rows.clear();
+                for backup in &backups {
+                    rows.push_back(backup.label());
+                }
+                drop(rows);
+
+                self.backups = backups;
+                self.current = current;
+                self.selected = None;
+                self.preview = "Select a backup to compare it with the current library.".into();
+                self.is_active = true;
+            }
+            BackupDialogMsg::Select(index) => {
+                let Some(backup) = self.backups.get(index) else {
+                    return;
+                };
+                // Saves keep what could not be read, so backups may have it too
+                match std::fs::read_to_string(&backup.path) {
+                    Ok(text) => {
+                        let Loaded { bib, malformed } = loader::load(&text);
+                        self.preview = diff::describe_library_changes(&self.current, &bib);
+                        if !malformed.is_empty() {
+                            self.preview.push_str(&format!(
+                                "\n\n{} unreadable block(s) are restored as they are.",
+                                malformed.len()
+                            ));
+                        }
+                        self.selected = Some((backup.path.clone(), text));
+                    }
+                    Err(e) => {
+                        self.preview = format!("This backup cannot be read:\n{}", e);
+                        self.selected = None;
+                    }
+                }
+            }
+            BackupDialogMsg::Restore => {
+                if let Some((path, text)) = self.selected.take() {
+                    let _ = sender.output(BackupDialogOutput::Restore(path, text));
+                }
+                self.is_active = false;
+            }
+            BackupDialogMsg::Close => {
+                self.selected = None;
+                self.is_active = false;
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+// src/ui/lock_dialog.rs
+use crate::core::lock::LockInfo;
+use gtk4::prelude::*;
+use relm4::prelude::*;
+use std::path::PathBuf;
+
+pub struct LockDialogModel {
+    pub is_active: bool,
+    pub path: Option<PathBuf>,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub enum LockDialogMsg {
+    Open(PathBuf, LockInfo),
+    ReadOnly,
+    Force,
+    Close,
+}
+
+#[derive(Debug)]
+pub enum LockDialogOutput {
+    OpenReadOnly(PathBuf),
+    ForceOpen(PathBuf),
+}
+
+#[relm4::component(pub)]
+impl Component for LockDialogModel {
+    type Init = ();
+    type Input = LockDialogMsg;
+    type Output = LockDialogOutput;
+    type CommandOutput = ();
+
+    view! {
+        gtk::Window {
+            set_modal: true,
+            set_default_width: 450,
+            set_resizable: false,
+            set_title: Some("Library In Use"),
+            set_hide_on_close: true,
+            #[watch] set_visible: model.is_active,
+
+            connect_close_request[sender] => move |_| {
+                sender.input(LockDialogMsg::Close);
+                gtk::glib::Propagation::Stop
+            },
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_margin_all: 24,
+                set_spacing: 16,
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 12,
+
+                    gtk::Image {
+                        set_icon_name: Some("dialog-warning-symbolic"),
+                        set_pixel_size: 32,
+                        add_css_class: "warning",
+                    },
+                    gtk::Label {
+                        set_label: "This library is open elsewhere",
+                        add_css_class: "title-3",
+                    },
+                },
+
+                gtk::Label {
+                    #[watch]
+                    set_label: &model.message,
+                    set_wrap: true,
+                    set_max_width_chars: 50,
+                    set_xalign: 0.0,
+                    set_halign: gtk::Align::Start,
+                },
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_halign: gtk::Align::End,
+                    set_spacing: 12,
+
+                    gtk::Button {
+                        set_label: "Cancel",
+                        connect_clicked => LockDialogMsg::Close,
+                    },
+                    gtk::Button {
+                        set_label: "Open Anyway",
+                        add_css_class: "destructive-action",
+                        set_tooltip_text: Some("Take over the lock; saves from the other session may be lost"),
+                        connect_clicked => LockDialogMsg::Force,
+                    },
+                    gtk::Button {
+                        set_label: "Open Read-Only",
+                        add_css_class: "suggested-action",
+                        connect_clicked => LockDialogMsg::ReadOnly,
+                    }
+                }
+            }
+        }
+    }
+
+    fn init(_: (), root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
+        let model = LockDialogModel {
+            is_active: false,
+            path: None,
+            message: String::new(),
+        };
+        let widgets = view_output!();
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>, _root: &Self::Root) {
+        match msg {
+            LockDialogMsg::Open(path, holder) => {
+                self.message = format!(
+                    "{}\nis being edited by another MkBib session:\n{}.\n\n\
+                     If you open it anyway, whichever session saves last overwrites the other.",
+                    path.display(),
+                    holder
+                );
+                self.path = Some(path);
+                self.is_active = true;
+            }
+            LockDialogMsg::ReadOnly => {
+                if let Some(path) = self.path.take() {
+                    let _ = sender.output(LockDialogOutput::OpenReadOnly(path));
+                }
+                self.is_active = false;
+            }
+            LockDialogMsg::Force => {
+                if let Some(path) = self.path.take() {
+                    let _ = sender.output(LockDialogOutput::ForceOpen(path));
+                }
+                self.is_active = false;
+            }
+            LockDialogMsg::Close => {
+                self.path = None;
+                self.is_active = false;
+            }
+        }
+    }
+}
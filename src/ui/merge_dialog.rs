@@ -0,0 +1,238 @@
+// src/ui/merge_dialog.rs
+use crate::logic::external::Side;
+use gtk4::prelude::*;
+use relm4::factory::FactoryVecDeque;
+use relm4::prelude::*;
+
+/// One conflicting entry, already rendered for display.
+#[derive(Debug, Clone)]
+pub struct ConflictView {
+    pub key: String,
+    pub summary: String,
+    pub mine: String,
+    pub disk: String,
+}
+
+// ----------------------------------------------------------------------------
+// Component 1: ConflictRow
+// ----------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct ConflictRow {
+    pub view: ConflictView,
+    pub choice: Side,
+}
+
+#[relm4::factory(pub)]
+impl FactoryComponent for ConflictRow {
+    type Init = ConflictView;
+    type Input = Side;
+    type Output = ();
+    type CommandOutput = ();
+    type ParentWidget = gtk::Box;
+
+    view! {
+        gtk::Frame {
+            set_label: Some(&format!("{} — {}", self.view.key, self.view.summary)),
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_homogeneous: true,
+                set_margin_all: 8,
+                set_spacing: 12,
+
+                // --- Mine ---
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_spacing: 6,
+
+                    #[name = "mine_button"]
+                    gtk::CheckButton {
+                        set_label: Some("Keep my version"),
+                        set_active: self.choice == Side::Mine,
+                        connect_toggled[sender] => move |button| {
+                            if button.is_active() {
+                                sender.input(Side::Mine);
+                            }
+                        }
+                    },
+                    gtk::Label {
+                        set_label: &self.view.mine,
+                        add_css_class: "monospace",
+                        set_selectable: true,
+                        set_xalign: 0.0,
+                        set_yalign: 0.0,
+                        set_wrap: true,
+                        set_wrap_mode: gtk::pango::WrapMode::WordChar,
+                    }
+                },
+
+                // --- Disk ---
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Vertical,
+                    set_spacing: 6,
+
+                    gtk::CheckButton {
+                        set_label: Some("Use the version on disk"),
+                        set_group: Some(&mine_button),
+                        set_active: self.choice == Side::Disk,
+                        connect_toggled[sender] => move |button| {
+                            if button.is_active() {
+                                sender.input(Side::Disk);
+                            }
+                        }
+                    },
+                    gtk::Label {
+                        set_label: &self.view.disk,
+                        add_css_class: "monospace",
+                        set_selectable: true,
+                        set_xalign: 0.0,
+                        set_yalign: 0.0,
+                        set_wrap: true,
+                        set_wrap_mode: gtk::pango::WrapMode::WordChar,
+                    }
+                }
+            }
+        }
+    }
+
+    fn init_model(view: Self::Init, _index: &DynamicIndex, _sender: FactorySender<Self>) -> Self {
+        Self {
+            view,
+            choice: Side::Mine,
+        }
+    }
+
+    fn update(&mut self, choice: Self::Input, _sender: FactorySender<Self>) {
+        self.choice = choice;
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Main Dialog
+// ----------------------------------------------------------------------------
+
+pub struct MergeDialogModel {
+    pub is_active: bool,
+    pub rows: FactoryVecDeque<ConflictRow>,
+}
+
+#[derive(Debug)]
+pub enum MergeDialogMsg {
+    Open(Vec<ConflictView>),
+    Apply,
+    Close,
+}
+
+#[derive(Debug)]
+pub enum MergeDialogOutput {
+    Resolve(Vec<Side>), // One choice per conflict, in order
+    Dismiss,
+}
+
+#[relm4::component(pub)]
+impl Component for MergeDialogModel {
+    type Init = ();
+    type Input = MergeDialogMsg;
+    type Output = MergeDialogOutput;
+    type CommandOutput = ();
+
+    view! {
+        gtk::Window {
+            set_modal: true,
+            set_default_width: 1000,
+            set_default_height: 600,
+            set_title: Some("File Changed on Disk"),
+            set_hide_on_close: true,
+            #[watch] set_visible: model.is_active,
+
+            connect_close_request[sender] => move |_| {
+                sender.input(MergeDialogMsg::Close);
+                gtk::glib::Propagation::Stop
+            },
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_margin_all: 12,
+                set_spacing: 12,
+
+                gtk::Label {
+                    #[watch]
+                    set_label: &format!(
+                        "The library was changed by another program while you had unsaved edits.\n\
+                         Everything else was merged; choose a version for these {} entries.",
+                        model.rows.len()
+                    ),
+                    set_halign: gtk::Align::Start,
+                    set_xalign: 0.0,
+                },
+
+                gtk::ScrolledWindow {
+                    set_vexpand: true,
+
+                    #[local_ref]
+                    conflicts_box -> gtk::Box {
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_spacing: 12,
+                    }
+                },
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_halign: gtk::Align::End,
+                    set_spacing: 12,
+
+                    gtk::Button {
+                        set_label: "Ignore Disk Changes",
+                        set_tooltip_text: Some("Keep your library as it is; saving overwrites the file"),
+                        connect_clicked => MergeDialogMsg::Close,
+                    },
+                    gtk::Button {
+                        set_label: "Merge",
+                        add_css_class: "suggested-action",
+                        connect_clicked => MergeDialogMsg::Apply,
+                    }
+                }
+            }
+        }
+    }
+
+    fn init(_: (), root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
+        let rows = FactoryVecDeque::builder()
+            .launch(gtk::Box::default())
+            .detach();
+
+        let model = MergeDialogModel {
+            is_active: false,
+            rows,
+        };
+
+        let conflicts_box = model.rows.widget();
+        let widgets = view_output!();
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>, _root: &Self::Root) {
+        match msg {
+            MergeDialogMsg::Open(conflicts) => {
+                let mut rows = self.rows.guard();
+                rows.clear();
+                for conflict in conflicts {
+                    rows.push_back(conflict);
+                }
+                drop(rows);
+                self.is_active = true;
+            }
+            MergeDialogMsg::Apply => {
+                let choices = self.rows.iter().map(|row| row.choice).collect();
+                let _ = sender.output(MergeDialogOutput::Resolve(choices));
+                self.is_active = false;
+            }
+            MergeDialogMsg::Close => {
+                if self.is_active {
+                    let _ = sender.output(MergeDialogOutput::Dismiss);
+                }
+                self.is_active = false;
+            }
+        }
+    }
+}
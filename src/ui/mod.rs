@@ -1,7 +1,14 @@
 // src/ui/mod.rs
+pub mod backup_dialog;
 pub mod details_dialog;
 pub mod duplicate_dialog;
+pub mod lock_dialog;
+pub mod merge_dialog;
 pub mod preferences;
+pub mod recovery_dialog;
+pub mod review_dialog;
 pub mod row;
 pub mod search_dialog;
 pub mod sidebar;
+pub mod strings_dialog;
+pub mod tex_rename_dialog;
@@ -1,9 +1,13 @@
 // src/ui/preferences.rs
 
-use crate::core::keygen::{KeyGenConfig, KeyPart};
+use crate::core::config::{AppConfig, Delimiter, Dialect, NameCase, SortKey};
+use crate::core::keygen::{KeyPart, KeyPattern, SuffixStyle};
+use crate::core::latex::TextEncoding;
+use crate::core::transliterate::Transliteration;
 use gtk4::prelude::*;
 use relm4::factory::FactoryVecDeque;
 use relm4::prelude::*;
+use std::path::PathBuf;
 
 // ----------------------------------------------------------------------------
 // Component 1: KeyPartRow (Key Generator Fields)
@@ -130,41 +134,173 @@ impl FactoryComponent for FieldRow {
     }
 }
 
+// ----------------------------------------------------------------------------
+// Component 3: TypePatternRow (Per-Entry-Type Key Patterns)
+// ----------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct TypePatternRow {
+    pub entry_type: String,
+    pub pattern: String,
+}
+
+#[derive(Debug)]
+pub enum TypePatternRowMsg {
+    Remove,
+}
+
+#[relm4::factory(pub)]
+impl FactoryComponent for TypePatternRow {
+    type Init = (String, String);
+    type Input = TypePatternRowMsg;
+    type Output = String;
+    type CommandOutput = ();
+    type ParentWidget = gtk::ListBox;
+
+    view! {
+        gtk::ListBoxRow {
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_margin_all: 8,
+                set_spacing: 10,
+
+                gtk::Label {
+                    set_label: &format!("@{}", self.entry_type),
+                    set_width_chars: 14,
+                    set_xalign: 0.0,
+                },
+
+                gtk::Label {
+                    set_label: &self.pattern,
+                    set_hexpand: true,
+                    set_halign: gtk::Align::Start,
+                    set_ellipsize: gtk::pango::EllipsizeMode::End,
+                    set_css_classes: &["monospace"],
+                },
+
+                gtk::Button {
+                    set_icon_name: "list-remove-symbolic",
+                    add_css_class: "flat",
+                    set_tooltip_text: Some("Remove override"),
+                    connect_clicked => TypePatternRowMsg::Remove,
+                }
+            }
+        }
+    }
+
+    fn init_model(init: Self::Init, _index: &DynamicIndex, _sender: FactorySender<Self>) -> Self {
+        let (entry_type, pattern) = init;
+        Self {
+            entry_type,
+            pattern,
+        }
+    }
+
+    fn update(&mut self, msg: Self::Input, sender: FactorySender<Self>) {
+        if let TypePatternRowMsg::Remove = msg {
+            let _ = sender.output(self.entry_type.clone());
+        }
+    }
+}
+
+/// Entry types offered in the override picker.
+const PATTERN_TYPES: &[&str] = &[
+    "article",
+    "book",
+    "inbook",
+    "incollection",
+    "inproceedings",
+    "thesis",
+    "phdthesis",
+    "mastersthesis",
+    "report",
+    "techreport",
+    "software",
+    "dataset",
+    "online",
+    "manual",
+    "patent",
+    "unpublished",
+    "misc",
+];
+
 // ----------------------------------------------------------------------------
 // Main Preferences Model
 // ----------------------------------------------------------------------------
 
+/// Which settings file the dialog edits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettingsScope {
+    Global,
+    Library,
+}
+
+/// The open library's settings: its project file and the effective config.
+#[derive(Debug, Clone)]
+pub struct LibraryScope {
+    pub path: PathBuf,
+    pub config: AppConfig,
+}
+
 #[derive(Debug)]
 pub struct PreferencesModel {
-    pub config: KeyGenConfig,
+    pub config: AppConfig,
+    pub global: AppConfig,
+    pub library: Option<LibraryScope>,
+    pub scope: SettingsScope,
     pub parts_list: FactoryVecDeque<KeyPartRow>,
     pub fields_list: FactoryVecDeque<FieldRow>,
+    pub type_patterns_list: FactoryVecDeque<TypePatternRow>,
+    pub pattern_status: String,
+    pub type_pattern_status: String,
     // ✅ FIX 1: Add visibility state
     pub is_visible: bool,
 }
 
 #[derive(Debug)]
 pub enum PreferencesMsg {
-    Show,
+    Show(Option<LibraryScope>),
+    SetScope(SettingsScope),
     Close,
     Save,
     SetSeparator(String),
+    SetPattern(String),
+    SetTransliteration(Transliteration),
+    SetSuffixStyle(SuffixStyle),
+    AddTypePattern(String, String),
+    RemoveTypePattern(String),
     AddPart(KeyPart),
     RemovePart(usize),
     ToggleAbbreviate(bool),
+    SetImportEncoding(TextEncoding),
+    ToggleRecordDateAdded(bool),
     SetIndentChar(char),
     SetIndentWidth(f64),
+    ToggleProtectCapitals(bool),
+    ToggleAlignEquals(bool),
+    SetDelimiter(Delimiter),
+    SetTypeCase(NameCase),
+    SetFieldCase(NameCase),
+    ToggleTrailingComma(bool),
+    ToggleBareNumbers(bool),
+    SetWrapWidth(f64),
+    ToggleUnwrapLines(bool),
+    SetSaveEncoding(TextEncoding),
+    SetDialect(Dialect),
+    ToggleReviewChanges(bool),
+    ToggleSortOnSave(bool),
+    SetSortLevel(usize, Option<SortKey>),
     MoveField(usize, FieldRowMsg),
 }
 
 #[derive(Debug)]
 pub enum PreferencesOutput {
-    ConfigUpdated(KeyGenConfig),
+    ConfigUpdated(AppConfig),
+    LibraryConfigUpdated(PathBuf, AppConfig),
 }
 
 #[relm4::component(pub)]
 impl SimpleComponent for PreferencesModel {
-    type Init = KeyGenConfig;
+    type Init = AppConfig;
     type Input = PreferencesMsg;
     type Output = PreferencesOutput;
 
@@ -187,6 +323,42 @@ impl SimpleComponent for PreferencesModel {
             gtk::Box {
                 set_orientation: gtk::Orientation::Vertical,
 
+                // --- SCOPE ---
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 12,
+                    set_margin_top: 12,
+                    set_margin_start: 12,
+                    set_margin_end: 12,
+
+                    gtk::Label { set_label: "Apply to:" },
+
+                    gtk::DropDown {
+                        set_model: Some(&gtk::StringList::new(&["All Libraries", "This Library"])),
+                        #[watch]
+                        set_selected: if model.scope == SettingsScope::Library { 1 } else { 0 },
+                        #[watch]
+                        set_sensitive: model.library.is_some(),
+                        connect_selected_notify[sender] => move |dd| {
+                            let scope = if dd.selected() == 1 {
+                                SettingsScope::Library
+                            } else {
+                                SettingsScope::Global
+                            };
+                            sender.input(PreferencesMsg::SetScope(scope));
+                        }
+                    },
+
+                    gtk::Label {
+                        #[watch]
+                        set_label: &model.scope_hint(),
+                        set_css_classes: &["caption", "dim-label"],
+                        set_hexpand: true,
+                        set_xalign: 0.0,
+                        set_ellipsize: gtk::pango::EllipsizeMode::Middle,
+                    },
+                },
+
                 // --- TABS (Stack Switcher) ---
                 gtk::StackSwitcher {
                     set_stack: Some(&main_stack),
@@ -222,7 +394,7 @@ impl SimpleComponent for PreferencesModel {
                                 set_model: Some(&gtk::StringList::new(&["None", "- (Hyphen)", "_ (Underscore)"])),
 
                                 #[watch]
-                                set_selected: match model.config.separator.as_str() {
+                                set_selected: match model.config.keygen.separator.as_str() {
                                     "-" => 1,
                                     "_" => 2,
                                     _ => 0,
@@ -239,6 +411,100 @@ impl SimpleComponent for PreferencesModel {
                             },
                         },
 
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label { set_label: "Pattern:" },
+
+                            #[name = "pattern_entry"]
+                            gtk::Entry {
+                                set_hexpand: true,
+                                // Only rewrite the text when the scope changed, so
+                                // typing doesn't reset the cursor.
+                                #[watch]
+                                set_visible: {
+                                    if pattern_entry.text() != model.config.keygen.pattern {
+                                        pattern_entry.set_text(&model.config.keygen.pattern);
+                                    }
+                                    true
+                                },
+                                #[watch]
+                                set_placeholder_text: Some(&model.config.keygen.parts_pattern()),
+                                set_tooltip_text: Some("e.g. [auth:lower][year][shorttitle:3] or [auth.etal][shortyear]"),
+                                connect_changed[sender] => move |e| {
+                                    sender.input(PreferencesMsg::SetPattern(e.text().into()));
+                                }
+                            },
+                        },
+
+                        gtk::Label {
+                            #[watch]
+                            set_label: &model.pattern_status,
+                            set_halign: gtk::Align::Start,
+                            set_wrap: true,
+                            set_css_classes: &["caption"],
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label { set_label: "Accents:" },
+
+                            gtk::DropDown {
+                                set_model: Some(&gtk::StringList::new(&[
+                                    Transliteration::Off.label(),
+                                    Transliteration::Ascii.label(),
+                                    Transliteration::German.label(),
+                                ])),
+
+                                #[watch]
+                                set_selected: match model.config.keygen.transliteration {
+                                    Transliteration::Off => 0,
+                                    Transliteration::Ascii => 1,
+                                    Transliteration::German => 2,
+                                },
+
+                                connect_selected_notify[sender] => move |dd| {
+                                    let mode = match dd.selected() {
+                                        0 => Transliteration::Off,
+                                        2 => Transliteration::German,
+                                        _ => Transliteration::Ascii,
+                                    };
+                                    sender.input(PreferencesMsg::SetTransliteration(mode));
+                                }
+                            },
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label { set_label: "Duplicate keys:" },
+
+                            gtk::DropDown {
+                                set_model: Some(&gtk::StringList::new(&[
+                                    SuffixStyle::Letters.label(),
+                                    SuffixStyle::Numbers.label(),
+                                    SuffixStyle::Dashed.label(),
+                                ])),
+
+                                #[watch]
+                                set_selected: match model.config.keygen.suffix_style {
+                                    SuffixStyle::Letters => 0,
+                                    SuffixStyle::Numbers => 1,
+                                    SuffixStyle::Dashed => 2,
+                                },
+
+                                connect_selected_notify[sender] => move |dd| {
+                                    let style = match dd.selected() {
+                                        1 => SuffixStyle::Numbers,
+                                        2 => SuffixStyle::Dashed,
+                                        _ => SuffixStyle::Letters,
+                                    };
+                                    sender.input(PreferencesMsg::SetSuffixStyle(style));
+                                }
+                            },
+                        },
+
                         gtk::Label {
                             set_label: "Key Format Parts:",
                             set_halign: gtk::Align::Start,
@@ -292,16 +558,137 @@ impl SimpleComponent for PreferencesModel {
                             },
                             gtk::Switch {
                                 #[watch]
-                                set_active: model.config.abbreviate_journals,
+                                set_active: model.config.import.abbreviate_journals,
                                 connect_state_set[sender] => move |_, state| {
                                     sender.input(PreferencesMsg::ToggleAbbreviate(state));
                                     gtk::glib::Propagation::Stop
                                 }
                             }
                         },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label {
+                                set_label: "Convert text of new entries to:",
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                            },
+
+                            gtk::DropDown {
+                                set_model: Some(&gtk::StringList::new(&[
+                                    TextEncoding::Keep.label(),
+                                    TextEncoding::Unicode.label(),
+                                    TextEncoding::Latex.label(),
+                                ])),
+
+                                #[watch]
+                                set_selected: match model.config.import.encoding {
+                                    TextEncoding::Keep => 0,
+                                    TextEncoding::Unicode => 1,
+                                    TextEncoding::Latex => 2,
+                                },
+
+                                connect_selected_notify[sender] => move |dd| {
+                                    let encoding = match dd.selected() {
+                                        1 => TextEncoding::Unicode,
+                                        2 => TextEncoding::Latex,
+                                        _ => TextEncoding::Keep,
+                                    };
+                                    sender.input(PreferencesMsg::SetImportEncoding(encoding));
+                                }
+                            },
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label {
+                                set_label: "Record the date entries are added:",
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                            },
+                            gtk::Switch {
+                                #[watch]
+                                set_active: model.config.import.record_date_added,
+                                connect_state_set[sender] => move |_, state| {
+                                    sender.input(PreferencesMsg::ToggleRecordDateAdded(state));
+                                    gtk::glib::Propagation::Stop
+                                }
+                            }
+                        },
+                    },
+
+                    // --- TAB 2: Entry Types ---
+                    add_titled[Some("entry_types"), "Entry Types"] = &gtk::Box {
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_margin_all: 12,
+                        set_spacing: 12,
+
+                        gtk::Label {
+                            set_label: "Per-Type Key Patterns",
+                            set_css_classes: &["title-4"],
+                            set_halign: gtk::Align::Start,
+                        },
+                        gtk::Label {
+                            set_label: "Entries of these types use their own pattern instead of the default one.",
+                            set_css_classes: &["caption"],
+                            set_halign: gtk::Align::Start,
+                        },
+
+                        gtk::ScrolledWindow {
+                            set_vexpand: true,
+                            gtk::Frame {
+                                #[local_ref]
+                                type_patterns_listbox -> gtk::ListBox {
+                                    set_selection_mode: gtk::SelectionMode::None,
+                                    add_css_class: "boxed-list",
+                                }
+                            }
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 6,
+
+                            #[name = "type_dropdown"]
+                            gtk::DropDown {
+                                set_model: Some(&gtk::StringList::new(PATTERN_TYPES)),
+                            },
+
+                            #[name = "type_pattern_entry"]
+                            gtk::Entry {
+                                set_hexpand: true,
+                                set_placeholder_text: Some("[auth][year]-thesis"),
+                            },
+
+                            gtk::Button {
+                                set_label: "Add",
+                                connect_clicked[sender, type_dropdown, type_pattern_entry] => move |_| {
+                                    let idx = type_dropdown.selected() as usize;
+                                    if let Some(ty) = PATTERN_TYPES.get(idx) {
+                                        sender.input(PreferencesMsg::AddTypePattern(
+                                            ty.to_string(),
+                                            type_pattern_entry.text().into(),
+                                        ));
+                                        type_pattern_entry.set_text("");
+                                    }
+                                }
+                            },
+                        },
+
+                        gtk::Label {
+                            #[watch]
+                            set_label: &model.type_pattern_status,
+                            #[watch]
+                            set_visible: !model.type_pattern_status.is_empty(),
+                            set_halign: gtk::Align::Start,
+                            set_wrap: true,
+                            set_css_classes: &["caption", "error"],
+                        },
                     },
 
-                    // --- TAB 2: Formatting ---
+                    // --- TAB 3: Formatting ---
                     add_titled[Some("formatting"), "Formatting"] = &gtk::Box {
                         set_orientation: gtk::Orientation::Vertical,
                         set_margin_all: 12,
@@ -322,7 +709,7 @@ impl SimpleComponent for PreferencesModel {
                             gtk::DropDown {
                                 set_model: Some(&gtk::StringList::new(&["Spaces", "Tabs"])),
                                 #[watch]
-                                set_selected: if model.config.indent_char == '\t' { 1 } else { 0 },
+                                set_selected: if model.config.formatting.indent_char == '\t' { 1 } else { 0 },
                                 connect_selected_notify[sender] => move |d| {
                                     let char = if d.selected() == 1 { '\t' } else { ' ' };
                                     sender.input(PreferencesMsg::SetIndentChar(char));
@@ -336,15 +723,374 @@ impl SimpleComponent for PreferencesModel {
                                 set_digits: 0,
                                 set_increments: (1.0, 1.0),
                                 #[watch]
-                                set_value: model.config.indent_width as f64,
+                                set_value: model.config.formatting.indent_width as f64,
                                 #[watch]
-                                set_sensitive: model.config.indent_char == ' ',
+                                set_sensitive: model.config.formatting.indent_char == ' ',
                                 connect_value_changed[sender] => move |btn| {
                                     sender.input(PreferencesMsg::SetIndentWidth(btn.value()));
                                 }
                             }
                         },
 
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label {
+                                set_label: "Protect capitalized words in titles ({DNA}):",
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                            },
+                            gtk::Switch {
+                                #[watch]
+                                set_active: model.config.formatting.protect_capitals,
+                                connect_state_set[sender] => move |_, state| {
+                                    sender.input(PreferencesMsg::ToggleProtectCapitals(state));
+                                    gtk::glib::Propagation::Stop
+                                }
+                            }
+                        },
+
+                        gtk::Separator { set_margin_top: 10, set_margin_bottom: 10 },
+
+                        gtk::Label {
+                            set_label: "Output Style",
+                            set_css_classes: &["title-4"],
+                            set_halign: gtk::Align::Start,
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label {
+                                set_label: "Value delimiters:",
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                            },
+
+                            gtk::DropDown {
+                                set_model: Some(&gtk::StringList::new(&[
+                                    Delimiter::Braces.label(),
+                                    Delimiter::Quotes.label(),
+                                ])),
+
+                                #[watch]
+                                set_selected: match model.config.formatting.delimiter {
+                                    Delimiter::Braces => 0,
+                                    Delimiter::Quotes => 1,
+                                },
+
+                                connect_selected_notify[sender] => move |dd| {
+                                    let delimiter = if dd.selected() == 1 { Delimiter::Quotes } else { Delimiter::Braces };
+                                    sender.input(PreferencesMsg::SetDelimiter(delimiter));
+                                }
+                            },
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label {
+                                set_label: "Entry type case (@article):",
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                            },
+
+                            gtk::DropDown {
+                                set_model: Some(&gtk::StringList::new(&[
+                                    NameCase::Lower.label(),
+                                    NameCase::Upper.label(),
+                                    NameCase::Capitalized.label(),
+                                ])),
+
+                                #[watch]
+                                set_selected: match model.config.formatting.type_case {
+                                    NameCase::Lower => 0,
+                                    NameCase::Upper => 1,
+                                    NameCase::Capitalized => 2,
+                                },
+
+                                connect_selected_notify[sender] => move |dd| {
+                                    let case = match dd.selected() {
+                                        1 => NameCase::Upper,
+                                        2 => NameCase::Capitalized,
+                                        _ => NameCase::Lower,
+                                    };
+                                    sender.input(PreferencesMsg::SetTypeCase(case));
+                                }
+                            },
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label {
+                                set_label: "Field name case (title):",
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                            },
+
+                            gtk::DropDown {
+                                set_model: Some(&gtk::StringList::new(&[
+                                    NameCase::Lower.label(),
+                                    NameCase::Upper.label(),
+                                    NameCase::Capitalized.label(),
+                                ])),
+
+                                #[watch]
+                                set_selected: match model.config.formatting.field_case {
+                                    NameCase::Lower => 0,
+                                    NameCase::Upper => 1,
+                                    NameCase::Capitalized => 2,
+                                },
+
+                                connect_selected_notify[sender] => move |dd| {
+                                    let case = match dd.selected() {
+                                        1 => NameCase::Upper,
+                                        2 => NameCase::Capitalized,
+                                        _ => NameCase::Lower,
+                                    };
+                                    sender.input(PreferencesMsg::SetFieldCase(case));
+                                }
+                            },
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label {
+                                set_label: "Align equals signs:",
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                            },
+                            gtk::Switch {
+                                #[watch]
+                                set_active: model.config.formatting.align_equals,
+                                connect_state_set[sender] => move |_, state| {
+                                    sender.input(PreferencesMsg::ToggleAlignEquals(state));
+                                    gtk::glib::Propagation::Stop
+                                }
+                            }
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label {
+                                set_label: "Comma after the last field:",
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                            },
+                            gtk::Switch {
+                                #[watch]
+                                set_active: model.config.formatting.trailing_comma,
+                                connect_state_set[sender] => move |_, state| {
+                                    sender.input(PreferencesMsg::ToggleTrailingComma(state));
+                                    gtk::glib::Propagation::Stop
+                                }
+                            }
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label {
+                                set_label: "Write year and volume without braces:",
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                            },
+                            gtk::Switch {
+                                #[watch]
+                                set_active: model.config.formatting.bare_numbers,
+                                connect_state_set[sender] => move |_, state| {
+                                    sender.input(PreferencesMsg::ToggleBareNumbers(state));
+                                    gtk::glib::Propagation::Stop
+                                }
+                            }
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label {
+                                set_label: "Wrap long values at column (0 = never):",
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                            },
+                            gtk::SpinButton {
+                                set_range: (0.0, 200.0),
+                                set_digits: 0,
+                                set_increments: (1.0, 10.0),
+                                #[watch]
+                                set_value: model.config.formatting.wrap_width as f64,
+                                connect_value_changed[sender] => move |btn| {
+                                    sender.input(PreferencesMsg::SetWrapWidth(btn.value()));
+                                }
+                            }
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label {
+                                set_label: "Join wrapped lines in values:",
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                            },
+                            gtk::Switch {
+                                #[watch]
+                                set_active: model.config.formatting.unwrap_lines || model.config.formatting.wrap_width > 0,
+                                #[watch]
+                                set_sensitive: model.config.formatting.wrap_width == 0,
+                                connect_state_set[sender] => move |_, state| {
+                                    sender.input(PreferencesMsg::ToggleUnwrapLines(state));
+                                    gtk::glib::Propagation::Stop
+                                }
+                            }
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label {
+                                set_label: "Write text on save as:",
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                            },
+
+                            gtk::DropDown {
+                                set_model: Some(&gtk::StringList::new(&[
+                                    TextEncoding::Keep.label(),
+                                    TextEncoding::Unicode.label(),
+                                    TextEncoding::Latex.label(),
+                                ])),
+
+                                #[watch]
+                                set_selected: match model.config.formatting.encoding {
+                                    TextEncoding::Keep => 0,
+                                    TextEncoding::Unicode => 1,
+                                    TextEncoding::Latex => 2,
+                                },
+
+                                connect_selected_notify[sender] => move |dd| {
+                                    let encoding = match dd.selected() {
+                                        1 => TextEncoding::Unicode,
+                                        2 => TextEncoding::Latex,
+                                        _ => TextEncoding::Keep,
+                                    };
+                                    sender.input(PreferencesMsg::SetSaveEncoding(encoding));
+                                }
+                            },
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label {
+                                set_label: "Write fields and types as:",
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                            },
+
+                            gtk::DropDown {
+                                set_model: Some(&gtk::StringList::new(&[
+                                    Dialect::Keep.label(),
+                                    Dialect::Bibtex.label(),
+                                    Dialect::Biblatex.label(),
+                                ])),
+
+                                #[watch]
+                                set_selected: match model.config.formatting.dialect {
+                                    Dialect::Keep => 0,
+                                    Dialect::Bibtex => 1,
+                                    Dialect::Biblatex => 2,
+                                },
+
+                                connect_selected_notify[sender] => move |dd| {
+                                    let dialect = match dd.selected() {
+                                        1 => Dialect::Bibtex,
+                                        2 => Dialect::Biblatex,
+                                        _ => Dialect::Keep,
+                                    };
+                                    sender.input(PreferencesMsg::SetDialect(dialect));
+                                }
+                            },
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label {
+                                set_label: "Review changes before saving:",
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                            },
+                            gtk::Switch {
+                                #[watch]
+                                set_active: model.config.formatting.review_changes,
+                                connect_state_set[sender] => move |_, state| {
+                                    sender.input(PreferencesMsg::ToggleReviewChanges(state));
+                                    gtk::glib::Propagation::Stop
+                                }
+                            }
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label {
+                                set_label: "Sort entries on save:",
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                            },
+                            gtk::Switch {
+                                #[watch]
+                                set_active: model.config.formatting.sort_on_save,
+                                connect_state_set[sender] => move |_, state| {
+                                    sender.input(PreferencesMsg::ToggleSortOnSave(state));
+                                    gtk::glib::Propagation::Stop
+                                }
+                            }
+                        },
+
+                        gtk::Box {
+                            set_orientation: gtk::Orientation::Horizontal,
+                            set_spacing: 12,
+                            gtk::Label {
+                                set_label: "Sort by, then by:",
+                                set_hexpand: true,
+                                set_halign: gtk::Align::Start,
+                            },
+
+                            gtk::DropDown {
+                                set_model: Some(&sort_key_list()),
+                                #[watch]
+                                set_selected: sort_level_index(&model.config.formatting.sort_order, 0),
+                                connect_selected_notify[sender] => move |dd| {
+                                    sender.input(PreferencesMsg::SetSortLevel(0, sort_key_at(dd.selected())));
+                                }
+                            },
+                            gtk::DropDown {
+                                set_model: Some(&sort_key_list()),
+                                #[watch]
+                                set_sensitive: !model.config.formatting.sort_order.is_empty(),
+                                #[watch]
+                                set_selected: sort_level_index(&model.config.formatting.sort_order, 1),
+                                connect_selected_notify[sender] => move |dd| {
+                                    sender.input(PreferencesMsg::SetSortLevel(1, sort_key_at(dd.selected())));
+                                }
+                            },
+                            gtk::DropDown {
+                                set_model: Some(&sort_key_list()),
+                                #[watch]
+                                set_sensitive: model.config.formatting.sort_order.len() >= 2,
+                                #[watch]
+                                set_selected: sort_level_index(&model.config.formatting.sort_order, 2),
+                                connect_selected_notify[sender] => move |dd| {
+                                    sender.input(PreferencesMsg::SetSortLevel(2, sort_key_at(dd.selected())));
+                                }
+                            },
+                        },
+
                         gtk::Separator { set_margin_top: 10, set_margin_bottom: 10 },
 
                         gtk::Label {
@@ -408,40 +1154,28 @@ impl SimpleComponent for PreferencesModel {
                 PreferencesMsg::MoveField(idx, msg)
             });
 
+        let type_patterns_list = FactoryVecDeque::builder()
+            .launch(gtk::ListBox::default())
+            .forward(sender.input_sender(), PreferencesMsg::RemoveTypePattern);
+
         // ✅ FIX 3: Init with is_visible = false
         let mut model = PreferencesModel {
-            config,
+            config: config.clone(),
+            global: config.clone(),
+            library: None,
+            scope: SettingsScope::Global,
             parts_list,
             fields_list,
+            type_patterns_list,
+            pattern_status: String::new(),
+            type_pattern_status: String::new(),
             is_visible: false,
         };
-
-        // 1. Populate Key Parts
-        for (i, part) in model.config.parts.iter().enumerate() {
-            model.parts_list.guard().push_back((i, part.clone()));
-        }
-
-        // 2. Populate Field Order
-        let mut field_order = model.config.field_order.clone();
-        if field_order.is_empty() {
-            field_order = vec![
-                "author".into(),
-                "title".into(),
-                "year".into(),
-                "date".into(),
-                "journal".into(),
-                "journaltitle".into(),
-                "volume".into(),
-                "doi".into(),
-            ];
-        }
-
-        for (i, field) in field_order.iter().enumerate() {
-            model.fields_list.guard().push_back((i, field.clone()));
-        }
+        model.load_config(config);
 
         let parts_listbox = model.parts_list.widget();
         let fields_listbox = model.fields_list.widget();
+        let type_patterns_listbox = model.type_patterns_list.widget();
         let widgets = view_output!();
 
         ComponentParts { model, widgets }
@@ -450,35 +1184,110 @@ impl SimpleComponent for PreferencesModel {
     fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>) {
         match msg {
             // ✅ FIX 4: Handle Showing/Closing
-            PreferencesMsg::Show => self.is_visible = true,
+            PreferencesMsg::Show(library) => {
+                self.library = library;
+                if self.library.is_none() {
+                    self.scope = SettingsScope::Global;
+                }
+                self.load_scope();
+                self.is_visible = true;
+            }
             PreferencesMsg::Close => self.is_visible = false,
+            PreferencesMsg::SetScope(scope) => {
+                if scope != self.scope && (scope == SettingsScope::Global || self.library.is_some())
+                {
+                    self.scope = scope;
+                    self.load_scope();
+                }
+            }
 
             PreferencesMsg::Save => {
-                let _ = sender.output(PreferencesOutput::ConfigUpdated(self.config.clone()));
+                match (&mut self.library, self.scope) {
+                    (Some(library), SettingsScope::Library) => {
+                        library.config = self.config.clone();
+                        let _ = sender.output(PreferencesOutput::LibraryConfigUpdated(
+                            library.path.clone(),
+                            self.config.clone(),
+                        ));
+                    }
+                    _ => {
+                        self.global = self.config.clone();
+                        let _ =
+                            sender.output(PreferencesOutput::ConfigUpdated(self.config.clone()));
+                    }
+                }
                 self.is_visible = false; // Close on save
             }
 
             // --- Tab 1 ---
-            PreferencesMsg::SetSeparator(s) => self.config.separator = s,
+            PreferencesMsg::SetSeparator(s) => self.config.keygen.separator = s,
+            PreferencesMsg::SetTransliteration(mode) => self.config.keygen.transliteration = mode,
+            PreferencesMsg::SetSuffixStyle(style) => self.config.keygen.suffix_style = style,
+            PreferencesMsg::AddTypePattern(entry_type, pattern) => {
+                // A typo would otherwise only show when keys are generated
+                if let Err(e) = KeyPattern::parse(&pattern) {
+                    self.type_pattern_status = format!("@{} pattern not added: {}", entry_type, e);
+                    return;
+                }
+                self.type_pattern_status.clear();
+                self.config.keygen.set_type_pattern(&entry_type, &pattern);
+                self.reload_type_patterns();
+            }
+            PreferencesMsg::RemoveTypePattern(entry_type) => {
+                self.config.keygen.set_type_pattern(&entry_type, "");
+                self.reload_type_patterns();
+            }
+            PreferencesMsg::SetPattern(p) => {
+                self.pattern_status = pattern_status(&p);
+                self.config.keygen.pattern = p;
+            }
             PreferencesMsg::AddPart(part) => {
-                self.config.parts.push(part.clone());
-                let idx = self.config.parts.len() - 1;
+                self.config.keygen.parts.push(part.clone());
+                let idx = self.config.keygen.parts.len() - 1;
                 self.parts_list.guard().push_back((idx, part));
             }
             PreferencesMsg::RemovePart(index) => {
-                if index < self.config.parts.len() {
-                    self.config.parts.remove(index);
+                if index < self.config.keygen.parts.len() {
+                    self.config.keygen.parts.remove(index);
                     self.parts_list.guard().clear();
-                    for (i, part) in self.config.parts.iter().enumerate() {
+                    for (i, part) in self.config.keygen.parts.iter().enumerate() {
                         self.parts_list.guard().push_back((i, part.clone()));
                     }
                 }
             }
-            PreferencesMsg::ToggleAbbreviate(state) => self.config.abbreviate_journals = state,
+            PreferencesMsg::ToggleAbbreviate(state) => {
+                self.config.import.abbreviate_journals = state
+            }
+            PreferencesMsg::SetImportEncoding(e) => self.config.import.encoding = e,
+            PreferencesMsg::ToggleRecordDateAdded(on) => self.config.import.record_date_added = on,
 
             // --- Tab 2 ---
-            PreferencesMsg::SetIndentChar(c) => self.config.indent_char = c,
-            PreferencesMsg::SetIndentWidth(w) => self.config.indent_width = w as u8,
+            PreferencesMsg::SetIndentChar(c) => self.config.formatting.indent_char = c,
+            PreferencesMsg::SetIndentWidth(w) => self.config.formatting.indent_width = w as u8,
+            PreferencesMsg::ToggleProtectCapitals(on) => {
+                self.config.formatting.protect_capitals = on
+            }
+            PreferencesMsg::ToggleAlignEquals(on) => self.config.formatting.align_equals = on,
+            PreferencesMsg::SetDelimiter(d) => self.config.formatting.delimiter = d,
+            PreferencesMsg::SetTypeCase(case) => self.config.formatting.type_case = case,
+            PreferencesMsg::SetFieldCase(case) => self.config.formatting.field_case = case,
+            PreferencesMsg::ToggleTrailingComma(on) => self.config.formatting.trailing_comma = on,
+            PreferencesMsg::ToggleBareNumbers(on) => self.config.formatting.bare_numbers = on,
+            PreferencesMsg::SetWrapWidth(w) => self.config.formatting.wrap_width = w as usize,
+            PreferencesMsg::ToggleUnwrapLines(on) => self.config.formatting.unwrap_lines = on,
+            PreferencesMsg::SetSaveEncoding(e) => self.config.formatting.encoding = e,
+            PreferencesMsg::SetDialect(d) => self.config.formatting.dialect = d,
+            PreferencesMsg::ToggleReviewChanges(on) => self.config.formatting.review_changes = on,
+            PreferencesMsg::ToggleSortOnSave(on) => self.config.formatting.sort_on_save = on,
+            PreferencesMsg::SetSortLevel(level, key) => {
+                let order = &mut self.config.formatting.sort_order;
+                match key {
+                    Some(key) if level < order.len() => order[level] = key,
+                    Some(key) => order.push(key),
+                    // Later levels only break ties of this one
+                    None => order.truncate(level),
+                }
+            }
 
             PreferencesMsg::MoveField(idx, move_msg) => {
                 let mut guard = self.fields_list.guard();
@@ -487,7 +1296,7 @@ impl SimpleComponent for PreferencesModel {
                 match move_msg {
                     FieldRowMsg::MoveUp if idx > 0 => {
                         guard.swap(idx, idx - 1);
-                        self.config.field_order.swap(idx, idx - 1);
+                        self.config.formatting.field_order.swap(idx, idx - 1);
 
                         // ✅ FIX 5: Update the internal index of swapped items
                         // (Otherwise clicking them again sends the old index)
@@ -500,7 +1309,7 @@ impl SimpleComponent for PreferencesModel {
                     }
                     FieldRowMsg::MoveDown if idx < len - 1 => {
                         guard.swap(idx, idx + 1);
-                        self.config.field_order.swap(idx, idx + 1);
+                        self.config.formatting.field_order.swap(idx, idx + 1);
 
                         // ✅ FIX 5 (Down)
                         if let Some(item) = guard.get_mut(idx) {
@@ -516,3 +1325,100 @@ impl SimpleComponent for PreferencesModel {
         }
     }
 }
+
+impl PreferencesModel {
+    /// Loads the settings of the current scope into the form.
+    fn load_scope(&mut self) {
+        let config = match (&self.library, self.scope) {
+            (Some(library), SettingsScope::Library) => library.config.clone(),
+            _ => self.global.clone(),
+        };
+        self.load_config(config);
+    }
+
+    fn load_config(&mut self, config: AppConfig) {
+        self.pattern_status = pattern_status(&config.keygen.pattern);
+        self.config = config;
+
+        // 1. Populate Key Parts
+        let mut parts = self.parts_list.guard();
+        parts.clear();
+        for (i, part) in self.config.keygen.parts.iter().enumerate() {
+            parts.push_back((i, part.clone()));
+        }
+        drop(parts);
+
+        // 2. Populate Field Order
+        if self.config.formatting.field_order.is_empty() {
+            self.config.formatting.field_order = vec![
+                "author".into(),
+                "title".into(),
+                "year".into(),
+                "date".into(),
+                "journal".into(),
+                "journaltitle".into(),
+                "volume".into(),
+                "doi".into(),
+            ];
+        }
+        let mut fields = self.fields_list.guard();
+        fields.clear();
+        for (i, field) in self.config.formatting.field_order.iter().enumerate() {
+            fields.push_back((i, field.clone()));
+        }
+        drop(fields);
+
+        // 3. Populate Per-Type Patterns
+        self.reload_type_patterns();
+    }
+
+    fn scope_hint(&self) -> String {
+        match (&self.library, self.scope) {
+            (Some(library), SettingsScope::Library) => {
+                format!("Saved to {}", library.path.display())
+            }
+            _ => "Saved to your user settings.".to_string(),
+        }
+    }
+
+    fn reload_type_patterns(&mut self) {
+        let mut guard = self.type_patterns_list.guard();
+        guard.clear();
+        for (entry_type, pattern) in &self.config.keygen.type_patterns {
+            guard.push_back((entry_type.clone(), pattern.clone()));
+        }
+    }
+}
+
+/// Short feedback line shown under the pattern entry.
+fn pattern_status(pattern: &str) -> String {
+    if pattern.trim().is_empty() {
+        return "Empty pattern: the key is built from the parts below.".to_string();
+    }
+    match KeyPattern::parse(pattern) {
+        Ok(_) => "Pattern OK.".to_string(),
+        Err(e) => format!("Invalid pattern. {}", e),
+    }
+}
+
+/// "(none)" and the sort criteria, for the sort order dropdowns.
+fn sort_key_list() -> gtk::StringList {
+    let keys = SortKey::ALL;
+    let mut labels = vec!["(none)"];
+    labels.extend(keys.iter().map(|key| key.label()));
+    gtk::StringList::new(&labels)
+}
+
+/// Dropdown position of the criterion at `level` of the sort order.
+fn sort_level_index(order: &[SortKey], level: usize) -> u32 {
+    order
+        .get(level)
+        .and_then(|key| SortKey::ALL.iter().position(|k| k == key))
+        .map_or(0, |i| i as u32 + 1)
+}
+
+fn sort_key_at(index: u32) -> Option<SortKey> {
+    (index as usize)
+        .checked_sub(1)
+        .and_then(|i| SortKey::ALL.get(i).copied())
+}
@@ -0,0 +1,167 @@
+// src/ui/recovery_dialog.rs
+use crate::core::recovery::RecoveryFile;
+use gtk4::prelude::*;
+use relm4::prelude::*;
+use std::time::SystemTime;
+
+pub struct RecoveryDialogModel {
+    pub is_active: bool,
+    /// Recovery files still to be decided on; the first one is shown.
+    pub queue: Vec<RecoveryFile>,
+}
+
+#[derive(Debug)]
+pub enum RecoveryDialogMsg {
+    Open(Vec<RecoveryFile>),
+    Restore,
+    Discard,
+    Close,
+}
+
+#[derive(Debug)]
+pub enum RecoveryDialogOutput {
+    Restore(RecoveryFile),
+    Discard(RecoveryFile),
+}
+
+#[relm4::component(pub)]
+impl Component for RecoveryDialogModel {
+    type Init = ();
+    type Input = RecoveryDialogMsg;
+    type Output = RecoveryDialogOutput;
+    type CommandOutput = ();
+
+    view! {
+        gtk::Window {
+            set_modal: true,
+            set_default_width: 450,
+            set_resizable: false,
+            set_title: Some("Recover Unsaved Changes"),
+            set_hide_on_close: true,
+            #[watch] set_visible: model.is_active,
+
+            connect_close_request[sender] => move |_| {
+                sender.input(RecoveryDialogMsg::Close);
+                gtk::glib::Propagation::Stop
+            },
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_margin_all: 24,
+                set_spacing: 16,
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 12,
+
+                    gtk::Image {
+                        set_icon_name: Some("document-revert-symbolic"),
+                        set_pixel_size: 32,
+                    },
+                    gtk::Label {
+                        set_label: "Unsaved changes were found",
+                        add_css_class: "title-3",
+                    },
+                },
+
+                gtk::Label {
+                    #[watch]
+                    set_label: &model.message(),
+                    set_wrap: true,
+                    set_max_width_chars: 50,
+                    set_xalign: 0.0,
+                    set_halign: gtk::Align::Start,
+                },
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_halign: gtk::Align::End,
+                    set_spacing: 12,
+
+                    gtk::Button {
+                        set_label: "Later",
+                        set_tooltip_text: Some("Ask again next time MkBib starts"),
+                        connect_clicked => RecoveryDialogMsg::Close,
+                    },
+                    gtk::Button {
+                        set_label: "Discard",
+                        add_css_class: "destructive-action",
+                        connect_clicked => RecoveryDialogMsg::Discard,
+                    },
+                    gtk::Button {
+                        set_label: "Restore",
+                        add_css_class: "suggested-action",
+                        connect_clicked => RecoveryDialogMsg::Restore,
+                    }
+                }
+            }
+        }
+    }
+
+    fn init(_: (), root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
+        let model = RecoveryDialogModel {
+            is_active: false,
+            queue: Vec::new(),
+        };
+        let widgets = view_output!();
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>, _root: &Self::Root) {
+        match msg {
+            RecoveryDialogMsg::Open(files) => {
+                self.queue = files;
+                self.is_active = !self.queue.is_empty();
+            }
+            RecoveryDialogMsg::Restore => {
+                if !self.queue.is_empty() {
+                    let file = self.queue.remove(0);
+                    let _ = sender.output(RecoveryDialogOutput::Restore(file));
+                }
+                // Only one library can be open, the rest wait for next start
+                self.queue.clear();
+                self.is_active = false;
+            }
+            RecoveryDialogMsg::Discard => {
+                if !self.queue.is_empty() {
+                    let file = self.queue.remove(0);
+                    let _ = sender.output(RecoveryDialogOutput::Discard(file));
+                }
+                self.is_active = !self.queue.is_empty();
+            }
+            RecoveryDialogMsg::Close => {
+                self.queue.clear();
+                self.is_active = false;
+            }
+        }
+    }
+}
+
+impl RecoveryDialogModel {
+    fn message(&self) -> String {
+        let Some(file) = self.queue.first() else {
+            return String::new();
+        };
+        let minutes = SystemTime::now()
+            .duration_since(file.saved)
+            .map(|d| d.as_secs() / 60)
+            .unwrap_or(0);
+        let age = match minutes {
+            0 => "less than a minute ago".to_string(),
+            1 => "1 minute ago".to_string(),
+            m if m < 120 => format!("{} minutes ago", m),
+            m if m < 48 * 60 => format!("{} hours ago", m / 60),
+            m => format!("{} days ago", m / (24 * 60)),
+        };
+        let more = match self.queue.len() {
+            1 => String::new(),
+            n => format!("\n\n{} more libraries have unsaved changes.", n - 1),
+        };
+        format!(
+            "MkBib was closed before these edits were saved.\n\nLibrary: {}\nLast autosave: {}{}",
+            file.library_label(),
+            age,
+            more
+        )
+    }
+}
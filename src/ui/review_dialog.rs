@@ -0,0 +1,222 @@
+// src/ui/review_dialog.rs
+use crate::logic::diff::{BlockChange, Change, DiffLine};
+use gtk4::prelude::*;
+use relm4::factory::FactoryVecDeque;
+use relm4::prelude::*;
+use std::path::PathBuf;
+
+/// Changes beyond this many are counted but not shown.
+const SHOWN_CHANGES: usize = 300;
+
+// ----------------------------------------------------------------------------
+// Component 1: ChangeRow
+// ----------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct ChangeRow {
+    pub title: String,
+    pub markup: String,
+}
+
+#[relm4::factory(pub)]
+impl FactoryComponent for ChangeRow {
+    type Init = BlockChange;
+    type Input = ();
+    type Output = ();
+    type CommandOutput = ();
+    type ParentWidget = gtk::Box;
+
+    view! {
+        gtk::Frame {
+            set_label: Some(&self.title),
+
+            gtk::Label {
+                set_markup: &self.markup,
+                add_css_class: "monospace",
+                set_selectable: true,
+                set_xalign: 0.0,
+                set_margin_all: 8,
+                set_wrap: true,
+                set_wrap_mode: gtk::pango::WrapMode::WordChar,
+            }
+        }
+    }
+
+    fn init_model(change: Self::Init, _index: &DynamicIndex, _sender: FactorySender<Self>) -> Self {
+        let sign = match change.change {
+            Change::Added => "Added",
+            Change::Removed => "Removed",
+            Change::Modified => "Modified",
+        };
+        let title = if change.fields.is_empty() {
+            format!("{}: {}", sign, change.label)
+        } else {
+            format!("{}: {} ({})", sign, change.label, change.fields.join(", "))
+        };
+        Self {
+            title,
+            markup: diff_markup(&change.lines),
+        }
+    }
+}
+
+/// The lines of a block, added ones in green and removed ones in red.
+fn diff_markup(lines: &[DiffLine]) -> String {
+    lines
+        .iter()
+        .map(|line| {
+            let (prefix, text, color) = match line {
+                DiffLine::Same(text) => (' ', text, None),
+                DiffLine::Added(text) => ('+', text, Some("#26a269")),
+                DiffLine::Removed(text) => ('-', text, Some("#c01c28")),
+            };
+            let text = gtk::glib::markup_escape_text(&format!("{} {}", prefix, text));
+            match color {
+                Some(color) => format!("<span foreground=\"{}\"><b>{}</b></span>", color, text),
+                None => format!("<span alpha=\"70%\">{}</span>", text),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// ----------------------------------------------------------------------------
+// Main Dialog
+// ----------------------------------------------------------------------------
+
+pub struct ReviewDialogModel {
+    pub is_active: bool,
+    pub path: Option<PathBuf>,
+    pub summary: String,
+    pub rows: FactoryVecDeque<ChangeRow>,
+}
+
+#[derive(Debug)]
+pub enum ReviewDialogMsg {
+    Open(PathBuf, Vec<BlockChange>),
+    Confirm,
+    Close,
+}
+
+#[derive(Debug)]
+pub enum ReviewDialogOutput {
+    Save(PathBuf),
+}
+
+#[relm4::component(pub)]
+impl Component for ReviewDialogModel {
+    type Init = ();
+    type Input = ReviewDialogMsg;
+    type Output = ReviewDialogOutput;
+    type CommandOutput = ();
+
+    view! {
+        gtk::Window {
+            set_modal: true,
+            set_default_width: 800,
+            set_default_height: 600,
+            set_title: Some("Review Changes"),
+            set_hide_on_close: true,
+            #[watch] set_visible: model.is_active,
+
+            connect_close_request[sender] => move |_| {
+                sender.input(ReviewDialogMsg::Close);
+                gtk::glib::Propagation::Stop
+            },
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_margin_all: 12,
+                set_spacing: 12,
+
+                gtk::Label {
+                    #[watch]
+                    set_label: &model.summary,
+                    set_halign: gtk::Align::Start,
+                    set_xalign: 0.0,
+                    set_wrap: true,
+                },
+
+                gtk::ScrolledWindow {
+                    set_vexpand: true,
+
+                    #[local_ref]
+                    changes_box -> gtk::Box {
+                        set_orientation: gtk::Orientation::Vertical,
+                        set_spacing: 12,
+                    }
+                },
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_halign: gtk::Align::End,
+                    set_spacing: 12,
+
+                    gtk::Button {
+                        set_label: "Cancel",
+                        connect_clicked => ReviewDialogMsg::Close,
+                    },
+                    gtk::Button {
+                        set_label: "Save",
+                        add_css_class: "suggested-action",
+                        connect_clicked => ReviewDialogMsg::Confirm,
+                    }
+                }
+            }
+        }
+    }
+
+    fn init(_: (), root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
+        let rows = FactoryVecDeque::builder()
+            .launch(gtk::Box::default())
+            .detach();
+
+        let model = ReviewDialogModel {
+            is_active: false,
+            path: None,
+            summary: String::new(),
+            rows,
+        };
+
+        let changes_box = model.rows.widget();
+        let widgets = view_output!();
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>, _root: &Self::Root) {
+        match msg {
+            ReviewDialogMsg::Open(path, changes) => {
+                let count = |kind| changes.iter().filter(|c| c.change == kind).count();
+                self.summary = format!(
+                    "Saving writes these changes to {}:\n{} added, {} removed, {} modified.",
+                    path.display(),
+                    count(Change::Added),
+                    count(Change::Removed),
+                    count(Change::Modified)
+                );
+                if changes.len() > SHOWN_CHANGES {
+                    self.summary
+                        .push_str(&format!(" The first {} are shown.", SHOWN_CHANGES));
+                }
+
+                let mut rows = self.rows.guard();
+                rows.clear();
+                for change in changes.into_iter().take(SHOWN_CHANGES) {
+                    rows.push_back(change);
+                }
+                drop(rows);
+                self.path = Some(path);
+                self.is_active = true;
+            }
+            ReviewDialogMsg::Confirm => {
+                if let Some(path) = self.path.take() {
+                    let _ = sender.output(ReviewDialogOutput::Save(path));
+                }
+                self.is_active = false;
+            }
+            ReviewDialogMsg::Close => {
+                self.path = None;
+                self.is_active = false;
+            }
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::core;
+use crate::logic::loader::Malformed;
 use gtk4::prelude::*;
 use relm4::prelude::*; // Add this if not present
 
@@ -8,6 +9,7 @@ pub struct BibEntry {
     pub title: String,
     pub kind: String,
     pub is_error: bool,
+    pub is_locked: bool,
 }
 
 impl BibEntry {
@@ -24,6 +26,18 @@ impl BibEntry {
             title,
             kind: format!("{}", entry.entry_type),
             is_error: false,
+            is_locked: core::keygen::is_key_locked(entry),
+        }
+    }
+
+    /// Row for text of the file that could not be read as an entry.
+    pub fn from_malformed(block: &Malformed) -> Self {
+        BibEntry {
+            key: block.label.clone(),
+            title: format!("Could not be read: {}", block.error),
+            kind: block.kind.clone(),
+            is_error: true,
+            is_locked: false,
         }
     }
 }
@@ -32,12 +46,14 @@ impl BibEntry {
 pub enum BibEntryMsg {
     Delete,
     Select,
+    ToggleLock,
 }
 
 #[derive(Debug)]
 pub enum BibEntryOutput {
     Delete(String),
     Select(String),
+    ToggleLock(String),
 }
 
 #[relm4::factory(pub)]
@@ -89,6 +105,19 @@ impl FactoryComponent for BibEntry {
                     }
                 },
 
+                gtk::Button {
+                    // Text that is not an entry yet has no key to lock
+                    set_visible: !self.is_error,
+                    set_icon_name: if self.is_locked { "changes-prevent-symbolic" } else { "changes-allow-symbolic" },
+                    add_css_class: "flat",
+                    set_tooltip_text: Some(if self.is_locked {
+                        "Key locked: Regenerate Keys will skip this entry"
+                    } else {
+                        "Lock key"
+                    }),
+                    connect_clicked => BibEntryMsg::ToggleLock,
+                },
+
                 gtk::Button {
                     set_icon_name: "user-trash-symbolic",
                     add_css_class: "flat",
@@ -117,6 +146,9 @@ impl FactoryComponent for BibEntry {
             BibEntryMsg::Select => {
                 let _ = sender.output(BibEntryOutput::Select(self.key.clone()));
             }
+            BibEntryMsg::ToggleLock => {
+                let _ = sender.output(BibEntryOutput::ToggleLock(self.key.clone()));
+            }
         }
     }
 }
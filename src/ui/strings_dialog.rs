@@ -0,0 +1,317 @@
+// src/ui/strings_dialog.rs
+use gtk4::prelude::*;
+use relm4::factory::FactoryVecDeque;
+use relm4::prelude::*;
+
+// ----------------------------------------------------------------------------
+// Component 1: StringRow
+// ----------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct StringRow {
+    pub name: String,
+    pub value: String,
+    pub usage: usize,
+}
+
+#[derive(Debug)]
+pub enum StringRowMsg {
+    Edit,
+    Remove,
+}
+
+#[derive(Debug)]
+pub enum StringRowOutput {
+    Edit(String),
+    Remove(String),
+}
+
+#[relm4::factory(pub)]
+impl FactoryComponent for StringRow {
+    type Init = (String, String, usize);
+    type Input = StringRowMsg;
+    type Output = StringRowOutput;
+    type CommandOutput = ();
+    type ParentWidget = gtk::ListBox;
+
+    view! {
+        gtk::ListBoxRow {
+            gtk::Box {
+                set_orientation: gtk::Orientation::Horizontal,
+                set_margin_all: 8,
+                set_spacing: 10,
+
+                gtk::Label {
+                    set_label: &self.name,
+                    set_width_chars: 12,
+                    set_xalign: 0.0,
+                    set_css_classes: &["monospace"],
+                },
+
+                gtk::Label {
+                    set_label: &self.value,
+                    set_hexpand: true,
+                    set_halign: gtk::Align::Start,
+                    set_ellipsize: gtk::pango::EllipsizeMode::End,
+                    set_css_classes: &["monospace"],
+                },
+
+                gtk::Label {
+                    set_label: &match self.usage {
+                        0 => "unused".to_string(),
+                        1 => "1 field".to_string(),
+                        n => format!("{} fields", n),
+                    },
+                    set_css_classes: &["caption"],
+                },
+
+                gtk::Button {
+                    set_icon_name: "document-edit-symbolic",
+                    add_css_class: "flat",
+                    set_tooltip_text: Some("Edit string"),
+                    connect_clicked => StringRowMsg::Edit,
+                },
+                gtk::Button {
+                    set_icon_name: "list-remove-symbolic",
+                    add_css_class: "flat",
+                    set_tooltip_text: Some("Remove string"),
+                    set_sensitive: self.usage == 0,
+                    connect_clicked => StringRowMsg::Remove,
+                }
+            }
+        }
+    }
+
+    fn init_model(init: Self::Init, _index: &DynamicIndex, _sender: FactorySender<Self>) -> Self {
+        let (name, value, usage) = init;
+        Self { name, value, usage }
+    }
+
+    fn update(&mut self, msg: Self::Input, sender: FactorySender<Self>) {
+        let _ = sender.output(match msg {
+            StringRowMsg::Edit => StringRowOutput::Edit(self.name.clone()),
+            StringRowMsg::Remove => StringRowOutput::Remove(self.name.clone()),
+        });
+    }
+}
+
+// ----------------------------------------------------------------------------
+// Main Dialog
+// ----------------------------------------------------------------------------
+
+pub struct StringsDialogModel {
+    pub is_active: bool,
+    /// (Name, Value as BibTeX, Fields using it)
+    pub strings: Vec<(String, String, usize)>,
+    pub rows: FactoryVecDeque<StringRow>,
+    pub name_input: String,
+    pub value_input: String,
+}
+
+#[derive(Debug)]
+pub enum StringsDialogMsg {
+    Open(Vec<(String, String, usize)>),
+    Set(String, String),
+    Edit(String),
+    Remove(String),
+    Apply,
+    Close,
+}
+
+#[derive(Debug)]
+pub enum StringsDialogOutput {
+    Apply(Vec<(String, String)>),
+}
+
+#[relm4::component(pub)]
+impl Component for StringsDialogModel {
+    type Init = ();
+    type Input = StringsDialogMsg;
+    type Output = StringsDialogOutput;
+    type CommandOutput = ();
+
+    view! {
+        gtk::Window {
+            set_modal: true,
+            set_default_width: 650,
+            set_default_height: 450,
+            set_title: Some("String Definitions"),
+            set_hide_on_close: true,
+            #[watch] set_visible: model.is_active,
+
+            connect_close_request[sender] => move |_| {
+                sender.input(StringsDialogMsg::Close);
+                gtk::glib::Propagation::Stop
+            },
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_margin_all: 12,
+                set_spacing: 12,
+
+                gtk::Label {
+                    set_label: "Macros defined with @string. Fields that use one are saved with the macro name, not its text.",
+                    set_css_classes: &["caption"],
+                    set_halign: gtk::Align::Start,
+                    set_wrap: true,
+                },
+
+                gtk::ScrolledWindow {
+                    set_vexpand: true,
+                    gtk::Frame {
+                        #[local_ref]
+                        strings_listbox -> gtk::ListBox {
+                            set_selection_mode: gtk::SelectionMode::None,
+                            add_css_class: "boxed-list",
+                        }
+                    }
+                },
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_spacing: 6,
+
+                    #[name = "name_entry"]
+                    gtk::Entry {
+                        set_width_chars: 12,
+                        set_placeholder_text: Some("prl"),
+                        #[watch]
+                        set_text: &model.name_input,
+                    },
+
+                    #[name = "value_entry"]
+                    gtk::Entry {
+                        set_hexpand: true,
+                        set_placeholder_text: Some("{Physical Review Letters}"),
+                        #[watch]
+                        set_text: &model.value_input,
+                        connect_activate[sender, name_entry] => move |entry| {
+                            sender.input(StringsDialogMsg::Set(name_entry.text().into(), entry.text().into()));
+                        },
+                    },
+
+                    gtk::Button {
+                        set_label: "Set",
+                        set_tooltip_text: Some("Add the string, or change its value if it exists"),
+                        connect_clicked[sender, name_entry, value_entry] => move |_| {
+                            sender.input(StringsDialogMsg::Set(
+                                name_entry.text().into(),
+                                value_entry.text().into(),
+                            ));
+                        }
+                    },
+                },
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_halign: gtk::Align::End,
+                    set_spacing: 12,
+
+                    gtk::Button {
+                        set_label: "Cancel",
+                        connect_clicked => StringsDialogMsg::Close,
+                    },
+                    gtk::Button {
+                        set_label: "Apply",
+                        add_css_class: "suggested-action",
+                        connect_clicked => StringsDialogMsg::Apply,
+                    }
+                }
+            }
+        }
+    }
+
+    fn init(_: (), root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
+        let rows = FactoryVecDeque::builder()
+            .launch(gtk::ListBox::default())
+            .forward(sender.input_sender(), |output| match output {
+                StringRowOutput::Edit(name) => StringsDialogMsg::Edit(name),
+                StringRowOutput::Remove(name) => StringsDialogMsg::Remove(name),
+            });
+
+        let model = StringsDialogModel {
+            is_active: false,
+            strings: Vec::new(),
+            rows,
+            name_input: String::new(),
+            value_input: String::new(),
+        };
+
+        let strings_listbox = model.rows.widget();
+        let widgets = view_output!();
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>, _root: &Self::Root) {
+        match msg {
+            StringsDialogMsg::Open(strings) => {
+                self.strings = strings;
+                self.name_input.clear();
+                self.value_input.clear();
+                self.reload_rows();
+                self.is_active = true;
+            }
+            StringsDialogMsg::Set(name, value) => {
+                let name = name.trim().to_string();
+                let value = as_bibtex_value(value.trim());
+                if name.is_empty() || value.is_empty() {
+                    return;
+                }
+                match self
+                    .strings
+                    .iter_mut()
+                    .find(|(n, _, _)| n.eq_ignore_ascii_case(&name))
+                {
+                    Some(existing) => existing.1 = value,
+                    None => self.strings.push((name, value, 0)),
+                }
+                self.name_input.clear();
+                self.value_input.clear();
+                self.reload_rows();
+            }
+            StringsDialogMsg::Edit(name) => {
+                if let Some((n, v, _)) = self.strings.iter().find(|(n, _, _)| *n == name) {
+                    self.name_input = n.clone();
+                    self.value_input = v.clone();
+                }
+            }
+            StringsDialogMsg::Remove(name) => {
+                self.strings.retain(|(n, _, _)| *n != name);
+                self.reload_rows();
+            }
+            StringsDialogMsg::Apply => {
+                let strings = self
+                    .strings
+                    .drain(..)
+                    .map(|(name, value, _)| (name, value))
+                    .collect();
+                let _ = sender.output(StringsDialogOutput::Apply(strings));
+                self.is_active = false;
+            }
+            StringsDialogMsg::Close => self.is_active = false,
+        }
+    }
+}
+
+impl StringsDialogModel {
+    fn reload_rows(&mut self) {
+        let mut rows = self.rows.guard();
+        rows.clear();
+        for (name, value, usage) in &self.strings {
+            rows.push_back((name.clone(), value.clone(), *usage));
+        }
+    }
+}
+
+/// Plain text typed in the dialog is braced; values that already look
+/// like BibTeX (`{...}`, `"..."`, numbers, `a # b`) are kept as typed.
+fn as_bibtex_value(value: &str) -> String {
+    let is_bibtex = value.starts_with('{')
+        || value.starts_with('"')
+        || value.contains(" # ")
+        || value.chars().all(|c| c.is_ascii_digit());
+    if value.is_empty() || is_bibtex {
+        value.to_string()
+    } else {
+        format!("{{{}}}", value)
+    }
+}
@@ -0,0 +1,130 @@
+// src/ui/tex_rename_dialog.rs
+use crate::logic::tex_rename::TexEdit;
+use gtk4::prelude::*;
+use relm4::prelude::*;
+
+pub struct TexRenameDialogModel {
+    pub is_active: bool,
+    pub edits: Vec<TexEdit>,
+    pub preview: String,
+}
+
+#[derive(Debug)]
+pub enum TexRenameDialogMsg {
+    Open(Vec<TexEdit>, String), // (Planned edits, Diff preview)
+    Apply,
+    Close,
+}
+
+#[derive(Debug)]
+pub enum TexRenameDialogOutput {
+    Apply(Vec<TexEdit>),
+}
+
+#[relm4::component(pub)]
+impl Component for TexRenameDialogModel {
+    type Init = ();
+    type Input = TexRenameDialogMsg;
+    type Output = TexRenameDialogOutput;
+    type CommandOutput = ();
+
+    view! {
+        gtk::Window {
+            set_modal: true,
+            set_default_width: 800,
+            set_default_height: 550,
+            set_title: Some("Update Citations"),
+            set_hide_on_close: true,
+            #[watch] set_visible: model.is_active,
+
+            connect_close_request[sender] => move |_| {
+                sender.input(TexRenameDialogMsg::Close);
+                gtk::glib::Propagation::Stop
+            },
+
+            gtk::Box {
+                set_orientation: gtk::Orientation::Vertical,
+                set_margin_all: 12,
+                set_spacing: 12,
+
+                gtk::Label {
+                    #[watch]
+                    set_label: &model.summary(),
+                    set_css_classes: &["title-4"],
+                    set_halign: gtk::Align::Start,
+                },
+
+                gtk::ScrolledWindow {
+                    set_vexpand: true,
+                    set_hscrollbar_policy: gtk::PolicyType::Automatic,
+
+                    gtk::Label {
+                        #[watch]
+                        set_label: &model.preview,
+                        add_css_class: "monospace",
+                        set_selectable: true,
+                        set_xalign: 0.0,
+                        set_yalign: 0.0,
+                        set_margin_all: 8,
+                    }
+                },
+
+                gtk::Box {
+                    set_orientation: gtk::Orientation::Horizontal,
+                    set_halign: gtk::Align::End,
+                    set_spacing: 12,
+
+                    gtk::Button {
+                        set_label: "Cancel",
+                        connect_clicked => TexRenameDialogMsg::Close,
+                    },
+                    gtk::Button {
+                        set_label: "Apply Changes",
+                        add_css_class: "suggested-action",
+                        connect_clicked => TexRenameDialogMsg::Apply,
+                    }
+                }
+            }
+        }
+    }
+
+    fn init(_: (), root: Self::Root, sender: ComponentSender<Self>) -> ComponentParts<Self> {
+        let model = TexRenameDialogModel {
+            is_active: false,
+            edits: Vec::new(),
+            preview: String::new(),
+        };
+        let widgets = view_output!();
+        ComponentParts { model, widgets }
+    }
+
+    fn update(&mut self, msg: Self::Input, sender: ComponentSender<Self>, _root: &Self::Root) {
+        match msg {
+            TexRenameDialogMsg::Open(edits, preview) => {
+                self.edits = edits;
+                self.preview = preview;
+                self.is_active = true;
+            }
+            TexRenameDialogMsg::Apply => {
+                let edits = std::mem::take(&mut self.edits);
+                let _ = sender.output(TexRenameDialogOutput::Apply(edits));
+                self.is_active = false;
+            }
+            TexRenameDialogMsg::Close => {
+                self.edits.clear();
+                self.is_active = false;
+            }
+        }
+    }
+}
+
+impl TexRenameDialogModel {
+    fn summary(&self) -> String {
+        let count: usize = self.edits.iter().map(|e| e.replacements).sum();
+        format!(
+            "{} citations in {} files will be updated:",
+            count,
+            self.edits.len()
+        )
+    }
+}